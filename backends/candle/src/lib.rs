@@ -22,8 +22,8 @@ use crate::compute_cap::{
     compatible_compute_cap, get_compile_compute_cap, get_runtime_compute_cap,
 };
 use crate::models::{
-    BertConfig, BertModel, DebertaV2Config, DebertaV2Model, Dense, DenseConfig, DenseLayer,
-    DistilBertConfig, DistilBertModel, GTEConfig, GTEModel, Gemma3Config, Gemma3Model,
+    BertConfig, BertModel, ColBERTProjection, DebertaV2Config, DebertaV2Model, Dense, DenseConfig,
+    DenseLayer, DistilBertConfig, DistilBertModel, GTEConfig, GTEModel, Gemma3Config, Gemma3Model,
     JinaBertModel, JinaCodeBertModel, MPNetConfig, MPNetModel, MistralConfig, Model,
     ModernBertConfig, ModernBertModel, NomicBertModel, NomicConfig, Qwen2Config, Qwen3Config,
     Qwen3Model, StaticEmbeddingConfig, StaticEmbeddingModel,
@@ -124,6 +124,8 @@ pub struct CandleBackend {
     device: Device,
     model: Box<dyn Model + Send>,
     dense_layers: Vec<Box<dyn DenseLayer + Send>>,
+    /// ColBERT projection applied to the token embeddings of multi-vector models
+    colbert: Option<ColBERTProjection>,
 }
 
 impl CandleBackend {
@@ -132,6 +134,7 @@ impl CandleBackend {
         dtype: String,
         model_type: ModelType,
         dense_paths: Option<Vec<String>>,
+        multi_vector: bool,
     ) -> Result<Self, BackendError> {
         // Default files
         let default_safetensors = model_path.join("model.safetensors");
@@ -237,6 +240,14 @@ impl CandleBackend {
         }
         .s()?;
 
+        // The token embeddings are only projected with `--multi-vector`, the PyLate module is not
+        // even downloaded otherwise
+        let colbert = if multi_vector {
+            ColBERTProjection::load(model_path, &vb, dtype, &device).s()?
+        } else {
+            None
+        };
+
         let model: Result<Box<dyn Model + Send>, BackendError> = match (config, &device) {
             #[cfg(not(feature = "cuda"))]
             (_, Device::Cuda(_)) => Err(BackendError::Start(
@@ -597,6 +608,7 @@ impl CandleBackend {
             device,
             model: model?,
             dense_layers,
+            colbert,
         })
     }
 }
@@ -618,6 +630,10 @@ impl Backend for CandleBackend {
         self.model.is_padded()
     }
 
+    fn is_multi_vector(&self) -> bool {
+        self.colbert.is_some()
+    }
+
     fn embed(&self, batch: Batch) -> Result<Embeddings, BackendError> {
         let batch_size = batch.len();
         let pooled_indices = batch.pooled_indices.clone();
@@ -650,6 +666,12 @@ impl Backend for CandleBackend {
             Some(pooled_embeddings) => pooled_embeddings.to_dtype(DType::F32).e()?.to_vec2().e()?,
         };
 
+        // Project and normalize the token embeddings of ColBERT models
+        let raw_embeddings = match (raw_embeddings, &self.colbert) {
+            (Some(raw_embeddings), Some(colbert)) => Some(colbert.forward(&raw_embeddings).e()?),
+            (raw_embeddings, _) => raw_embeddings,
+        };
+
         // This transfer is expensive...
         let raw_embeddings = match raw_embeddings {
             None => vec![],
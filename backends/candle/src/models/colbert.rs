@@ -0,0 +1,94 @@
+use crate::layers::Linear;
+use crate::models::{Dense, DenseConfig, DenseLayer};
+use candle::{DType, Device, Result, Tensor, D};
+use candle_nn::VarBuilder;
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+struct ModuleConfig {
+    path: String,
+    #[serde(rename = "type")]
+    module_type: String,
+}
+
+/// ColBERT projection of the token embeddings to the late-interaction space
+pub enum ColBERTProjection {
+    /// `colbert_linear` (or Stanford ColBERT `linear`) layer stored with the model weights
+    Linear(Linear),
+    /// PyLate `1_Dense` module
+    Dense(Dense),
+}
+
+impl ColBERTProjection {
+    /// Load the ColBERT projection of the model, if it has one
+    pub fn load(
+        model_path: &Path,
+        vb: &VarBuilder,
+        dtype: DType,
+        device: &Device,
+    ) -> Result<Option<Self>> {
+        if let Some(dense_path) = pylate_dense_path(model_path) {
+            let config_path = model_path.join(format!("{dense_path}/config.json"));
+            let config = std::fs::read_to_string(&config_path)?;
+            let config: DenseConfig = serde_json::from_str(&config).map_err(|err| {
+                candle::Error::Msg(format!("Unable to parse {config_path:?}: {err}"))
+            })?;
+
+            let safetensors = model_path.join(format!("{dense_path}/model.safetensors"));
+            let dense_vb = if safetensors.exists() {
+                unsafe { VarBuilder::from_mmaped_safetensors(&[safetensors], dtype, device) }?
+            } else {
+                let pytorch = model_path.join(format!("{dense_path}/pytorch_model.bin"));
+                VarBuilder::from_pth(pytorch, dtype, device)?
+            };
+
+            tracing::info!("Loaded ColBERT projection from `{dense_path}`");
+            return Ok(Some(Self::Dense(Dense::load(dense_vb, &config)?)));
+        }
+
+        // Stanford ColBERT checkpoints name their projection `linear` and ship an
+        // `artifact.metadata` file
+        let name = if vb.contains_tensor("colbert_linear.weight") {
+            "colbert_linear.weight"
+        } else if vb.contains_tensor("linear.weight")
+            && model_path.join("artifact.metadata").exists()
+        {
+            "linear.weight"
+        } else {
+            return Ok(None);
+        };
+
+        tracing::info!("Loaded ColBERT projection from `{name}`");
+        let weight = vb.get_unchecked(name)?;
+        Ok(Some(Self::Linear(Linear::new(weight, None, None))))
+    }
+
+    /// Project the token embeddings and normalize each of them
+    pub fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+        let hidden_states = match self {
+            Self::Linear(linear) => linear.forward(hidden_states)?,
+            Self::Dense(dense) => dense.forward(hidden_states)?,
+        };
+
+        let norm = hidden_states
+            .to_dtype(DType::F32)?
+            .sqr()?
+            .sum_keepdim(D::Minus1)?
+            .sqrt()?
+            .clamp(1e-12, f64::MAX)?
+            .to_dtype(hidden_states.dtype())?;
+        hidden_states.broadcast_div(&norm)
+    }
+}
+
+/// Path of the PyLate Dense module listed in `modules.json`, if any
+fn pylate_dense_path(model_path: &Path) -> Option<String> {
+    let modules = std::fs::read_to_string(model_path.join("modules.json")).ok()?;
+    let modules: Vec<ModuleConfig> = serde_json::from_str(&modules).ok()?;
+
+    modules
+        .into_iter()
+        .find(|module| module.module_type == "pylate.models.Dense.Dense")
+        .map(|module| module.path)
+}
@@ -8,6 +8,7 @@ use candle::{Result, Tensor};
 use text_embeddings_backend_core::Batch;
 
 mod bert;
+mod colbert;
 mod debertav2;
 mod dense;
 mod distilbert;
@@ -54,6 +55,7 @@ mod flash_qwen2;
 mod flash_qwen3;
 
 pub use bert::{BertConfig, BertModel, PositionEmbeddingType};
+pub use colbert::ColBERTProjection;
 pub use debertav2::{DebertaV2Config, DebertaV2Model};
 pub use dense::{Dense, DenseConfig, DenseLayer};
 pub use distilbert::{DistilBertConfig, DistilBertModel};
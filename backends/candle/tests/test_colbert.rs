@@ -0,0 +1,34 @@
+mod common;
+
+use anyhow::Result;
+use common::{batch, download_artifacts, load_tokenizer};
+use text_embeddings_backend_candle::CandleBackend;
+use text_embeddings_backend_core::{Backend, ModelType, Pool};
+
+#[test]
+#[serial_test::serial]
+fn test_pylate_without_multi_vector() -> Result<()> {
+    // The PyLate `1_Dense` module is not downloaded without `--multi-vector`
+    let (model_root, dense_paths) =
+        download_artifacts("lightonai/GTE-ModernColBERT-v1", None, None)?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let backend = CandleBackend::new(
+        &model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Mean),
+        dense_paths,
+        false,
+    )?;
+    assert!(!backend.is_multi_vector());
+
+    let input = batch(
+        vec![tokenizer.encode("What is Deep Learning?", true).unwrap()],
+        [0].to_vec(),
+        vec![],
+    );
+    let embeddings = backend.embed(input)?;
+    assert_eq!(embeddings.len(), 1);
+
+    Ok(())
+}
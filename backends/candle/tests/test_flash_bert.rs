@@ -23,6 +23,7 @@ fn test_flash_mini() -> Result<()> {
         "float16".to_string(),
         ModelType::Embedding(Pool::Mean),
         None,
+        false,
     )?;
 
     let input_batch = batch(
@@ -88,6 +89,7 @@ fn test_flash_mini_pooled_raw() -> Result<()> {
         "float16".to_string(),
         ModelType::Embedding(Pool::Cls),
         None,
+        false,
     )?;
 
     let input_batch = batch(
@@ -163,6 +165,7 @@ fn test_flash_emotions() -> Result<()> {
         "float16".to_string(),
         ModelType::Classifier,
         None,
+        false,
     )?;
 
     let input_batch = batch(
@@ -223,6 +226,7 @@ fn test_flash_bert_classification() -> Result<()> {
         "float16".to_string(),
         ModelType::Classifier,
         None,
+        false,
     )?;
 
     let input_single = batch(
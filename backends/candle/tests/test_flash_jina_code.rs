@@ -19,6 +19,7 @@ fn test_flash_jina_code_base() -> Result<()> {
         "float16".to_string(),
         ModelType::Embedding(Pool::Mean),
         None,
+        false,
     )?;
 
     let input_batch = batch(
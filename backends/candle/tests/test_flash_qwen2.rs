@@ -44,6 +44,7 @@ fn test_flash_qwen2() -> Result<()> {
         "float16".to_string(),
         ModelType::Embedding(Pool::LastToken),
         None,
+        false,
     )?;
 
     let input_batch = batch(
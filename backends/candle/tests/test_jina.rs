@@ -16,6 +16,7 @@ fn test_jina_small() -> Result<()> {
         "float32".to_string(),
         ModelType::Embedding(Pool::Mean),
         None,
+        false,
     )?;
 
     let input_batch = batch(
@@ -62,6 +63,7 @@ fn test_jina_rerank() -> Result<()> {
         "float32".to_string(),
         ModelType::Classifier,
         None,
+        false,
     )?;
 
     let input_single = batch(
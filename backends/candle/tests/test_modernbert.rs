@@ -19,6 +19,7 @@ fn test_modernbert() -> Result<()> {
         "float32".to_string(),
         ModelType::Embedding(Pool::Mean),
         None,
+        false,
     )?;
 
     let input_batch = batch(
@@ -88,6 +89,7 @@ fn test_modernbert_pooled_raw() -> Result<()> {
         "float32".to_string(),
         ModelType::Embedding(Pool::Cls),
         None,
+        false,
     )?;
 
     let input_batch = batch(
@@ -184,6 +186,7 @@ fn test_modernbert_classification() -> Result<()> {
         "float32".to_string(),
         ModelType::Classifier,
         None,
+        false,
     )?;
 
     let input_single = batch(
@@ -221,6 +224,7 @@ fn test_modernbert_classification_mean_pooling() -> Result<()> {
         "float32".to_string(),
         ModelType::Classifier,
         None,
+        false,
     )?;
 
     let input_single = batch(
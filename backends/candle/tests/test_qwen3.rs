@@ -17,6 +17,7 @@ fn test_qwen3() -> Result<()> {
         "float32".to_string(),
         ModelType::Embedding(Pool::LastToken),
         None,
+        false,
     )?;
 
     let input_batch = batch(
@@ -62,6 +63,7 @@ fn test_qwen3_reranker() -> Result<()> {
         "float32".to_string(),
         ModelType::Classifier,
         None,
+        false,
     )?;
 
     let input_single = batch(
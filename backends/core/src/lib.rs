@@ -42,6 +42,12 @@ pub trait Backend {
 
     fn is_padded(&self) -> bool;
 
+    /// Whether the backend returns projected and normalized token embeddings for late
+    /// interaction (ColBERT)
+    fn is_multi_vector(&self) -> bool {
+        false
+    }
+
     fn embed(&self, batch: Batch) -> Result<Embeddings, BackendError>;
 
     fn predict(&self, batch: Batch) -> Result<Predictions, BackendError>;
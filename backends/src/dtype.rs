@@ -3,8 +3,8 @@ use std::fmt;
 #[cfg(feature = "clap")]
 use clap::ValueEnum;
 
-#[derive(Debug, PartialEq)]
-#[cfg_attr(feature = "clap", derive(Clone, ValueEnum))]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
 pub enum DType {
     // Float16 is not available on accelerate
     #[cfg(any(
@@ -9,7 +9,7 @@ use std::process::Command;
 use std::sync::Arc;
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
-use text_embeddings_backend_core::{Backend as CoreBackend, Predictions};
+use text_embeddings_backend_core::Backend as CoreBackend;
 use tokio::sync::{mpsc, oneshot, watch};
 use tracing::{instrument, Span};
 
@@ -18,7 +18,7 @@ use serde::Deserialize;
 
 pub use crate::dtype::DType;
 pub use text_embeddings_backend_core::{
-    BackendError, Batch, Embedding, Embeddings, ModelType, Pool,
+    BackendError, Batch, Embedding, Embeddings, ModelType, Pool, Predictions,
 };
 
 #[cfg(feature = "candle")]
@@ -30,6 +30,16 @@ use text_embeddings_backend_ort::OrtBackend;
 #[cfg(feature = "python")]
 use text_embeddings_backend_python::PythonBackend;
 
+// The circuit breaker settings are documented in the `Backend recovery` section of the README
+
+/// Consecutive failed batches after which the circuit breaker opens
+const BREAKER_FAILURE_THRESHOLD: usize = 3;
+/// Delay before the first probe of an open circuit breaker. It doubles after each failed probe.
+const BREAKER_PROBE_BACKOFF: Duration = Duration::from_secs(1);
+const BREAKER_MAX_PROBE_BACKOFF: Duration = Duration::from_secs(60);
+/// Failed probes after which the model is re-initialized
+const BREAKER_REINIT_PROBES: usize = 3;
+
 fn powers_of_two(max_value: usize) -> Vec<usize> {
     let mut result = Vec::new();
     let mut power: usize = 1;
@@ -75,6 +85,7 @@ pub struct Backend {
     health_receiver: watch::Receiver<bool>,
     _backend_thread: Arc<BackendThread>,
     pub padded_model: bool,
+    pub multi_vector: bool,
     pub max_batch_size: Option<usize>,
     pub model_type: ModelType,
 }
@@ -82,11 +93,13 @@ pub struct Backend {
 impl Backend {
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
+        model_id: String,
         model_path: PathBuf,
         api_repo: Option<ApiRepo>,
         dtype: DType,
         model_type: ModelType,
         dense_path: Option<String>,
+        multi_vector: bool,
         uds_path: String,
         otlp_endpoint: Option<String>,
         otlp_service_name: String,
@@ -94,28 +107,57 @@ impl Backend {
         let (backend_sender, backend_receiver) = mpsc::channel(8);
 
         let backend = init_backend(
-            model_path,
+            model_path.clone(),
             api_repo,
-            dtype,
+            dtype.clone(),
             model_type.clone(),
-            dense_path,
-            uds_path,
-            otlp_endpoint,
-            otlp_service_name,
+            dense_path.clone(),
+            multi_vector,
+            uds_path.clone(),
+            otlp_endpoint.clone(),
+            otlp_service_name.clone(),
         )
         .await?;
         let padded_model = backend.is_padded();
+        let multi_vector = backend.is_multi_vector();
         let max_batch_size = backend.max_batch_size();
 
         let (health_sender, health_receiver) = watch::channel(false);
-        let _backend_thread =
-            Arc::new(BackendThread::new(backend, backend_receiver, health_sender));
+        let (breaker_sender, breaker_receiver) = watch::channel(BreakerState::Closed);
+        let _backend_thread = Arc::new(BackendThread::new(
+            backend,
+            model_type.clone(),
+            backend_receiver,
+            CircuitBreaker::new(model_id, health_sender, breaker_sender),
+        ));
+
+        // The weights are already downloaded: re-initializations load them from `model_path`
+        let reinit_model_type = model_type.clone();
+        let reinit = move || {
+            init_backend(
+                model_path.clone(),
+                None,
+                dtype.clone(),
+                reinit_model_type.clone(),
+                dense_path.clone(),
+                multi_vector,
+                uds_path.clone(),
+                otlp_endpoint.clone(),
+                otlp_service_name.clone(),
+            )
+        };
+        tokio::spawn(recovery_task(
+            backend_sender.downgrade(),
+            breaker_receiver,
+            reinit,
+        ));
 
         Ok(Self {
             backend_sender,
             health_receiver,
             _backend_thread,
             padded_model,
+            multi_vector,
             max_batch_size,
             model_type,
         })
@@ -313,21 +355,15 @@ impl Backend {
             )
         } else {
             // The backend is un-healthy or only just started. Do a more advanced health check
-            // by calling the model forward on a test batch
-
-            let batch = Batch {
-                input_ids: vec![0],
-                token_type_ids: vec![0],
-                position_ids: vec![0],
-                cumulative_seq_lengths: vec![0, 1],
-                max_length: 1,
-                pooled_indices: vec![0],
-                raw_indices: vec![],
-            };
-            match &self.model_type {
-                ModelType::Classifier => self.predict(batch).await.map(|_| ()),
-                ModelType::Embedding(_) => self.embed(batch).await.map(|_| ()),
-            }
+            // by calling the model forward on a test batch, even if the circuit breaker is open
+            let (sender, receiver) = oneshot::channel();
+            self.backend_sender
+                .send(BackendCommand::Probe(Span::current(), sender))
+                .await
+                .expect("No backend receiver. This is a bug.");
+            receiver.await.expect(
+                "Backend blocking task dropped the sender without sending a response. This is a bug.",
+            )
         }
     }
 
@@ -338,22 +374,58 @@ impl Backend {
 
     #[instrument(skip_all)]
     pub async fn embed(&self, batch: Batch) -> Result<(Embeddings, Duration), BackendError> {
+        self.send_embed(batch, false).await
+    }
+
+    /// Embed a part of a failed batch. Its failure does not count towards the circuit breaker.
+    #[instrument(skip_all)]
+    pub async fn embed_retry(&self, batch: Batch) -> Result<(Embeddings, Duration), BackendError> {
+        self.send_embed(batch, true).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn predict(&self, batch: Batch) -> Result<(Predictions, Duration), BackendError> {
+        self.send_predict(batch, false).await
+    }
+
+    /// Predict a part of a failed batch. Its failure does not count towards the circuit breaker.
+    #[instrument(skip_all)]
+    pub async fn predict_retry(
+        &self,
+        batch: Batch,
+    ) -> Result<(Predictions, Duration), BackendError> {
+        self.send_predict(batch, true).await
+    }
+
+    async fn send_embed(
+        &self,
+        batch: Batch,
+        retry: bool,
+    ) -> Result<(Embeddings, Duration), BackendError> {
         let (sender, receiver) = oneshot::channel();
 
         self.backend_sender
-            .try_send(BackendCommand::Embed(batch, Span::current(), sender))
+            .try_send(BackendCommand::Embed(batch, retry, Span::current(), sender))
             .expect("No backend receiver. This is a bug.");
         receiver.await.expect(
             "Backend blocking task dropped the sender without send a response. This is a bug.",
         )
     }
 
-    #[instrument(skip_all)]
-    pub async fn predict(&self, batch: Batch) -> Result<(Predictions, Duration), BackendError> {
+    async fn send_predict(
+        &self,
+        batch: Batch,
+        retry: bool,
+    ) -> Result<(Predictions, Duration), BackendError> {
         let (sender, receiver) = oneshot::channel();
 
         self.backend_sender
-            .try_send(BackendCommand::Predict(batch, Span::current(), sender))
+            .try_send(BackendCommand::Predict(
+                batch,
+                retry,
+                Span::current(),
+                sender,
+            ))
             .expect("No backend receiver. This is a bug.");
         receiver.await.expect(
             "Backend blocking task dropped the sender without send a response. This is a bug.",
@@ -368,6 +440,7 @@ async fn init_backend(
     dtype: DType,
     model_type: ModelType,
     dense_path: Option<String>,
+    multi_vector: bool,
     uds_path: String,
     otlp_endpoint: Option<String>,
     otlp_service_name: String,
@@ -440,6 +513,11 @@ async fn init_backend(
                 let dense_paths = download_dense_modules(api_repo, dense_path)
                     .await
                     .map_err(|err| BackendError::WeightsNotFound(err.to_string()))?;
+                if multi_vector {
+                    download_colbert_module(api_repo)
+                        .await
+                        .map_err(|err| BackendError::WeightsNotFound(err.to_string()))?;
+                }
                 tracing::info!("Dense modules downloaded in {:?}", start.elapsed());
                 Some(dense_paths)
             } else {
@@ -484,6 +562,7 @@ async fn init_backend(
                 dtype.to_string(),
                 model_type.clone(),
                 dense_paths,
+                multi_vector,
             );
             match backend {
                 Ok(b) => return Ok(Box::new(b)),
@@ -530,46 +609,259 @@ async fn init_backend(
     }
 }
 
+/// State of the circuit breaker, published as the `te_backend_breaker_state` gauge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakerState {
+    /// Batches are computed
+    Closed = 0,
+    /// Batches fail fast with `BackendError::Unhealthy` until a probe succeeds
+    Open = 1,
+    /// A probe or a re-initialization is running
+    HalfOpen = 2,
+}
+
+/// Opens after consecutive failed batches and flips the backend health to unhealthy, so that
+/// load balancers stop sending traffic while the backend recovers
+#[derive(Debug)]
+struct CircuitBreaker {
+    /// `model` label of the gauge
+    model_id: String,
+    state: BreakerState,
+    consecutive_failures: usize,
+    health_sender: watch::Sender<bool>,
+    state_sender: watch::Sender<BreakerState>,
+}
+
+impl CircuitBreaker {
+    fn new(
+        model_id: String,
+        health_sender: watch::Sender<bool>,
+        state_sender: watch::Sender<BreakerState>,
+    ) -> Self {
+        metrics::gauge!("te_backend_breaker_state", "model" => model_id.clone())
+            .set(BreakerState::Closed as u8 as f64);
+        Self {
+            model_id,
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            health_sender,
+            state_sender,
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.state != BreakerState::Closed
+    }
+
+    fn set_state(&mut self, state: BreakerState) {
+        if self.state != state {
+            self.state = state;
+            metrics::gauge!("te_backend_breaker_state", "model" => self.model_id.clone())
+                .set(state as u8 as f64);
+            let _ = self.state_sender.send(state);
+        }
+    }
+
+    fn success(&mut self) {
+        if self.is_open() {
+            tracing::info!("Backend recovered, closing the circuit breaker");
+        }
+        self.consecutive_failures = 0;
+        self.set_state(BreakerState::Closed);
+        let _ = self.health_sender.send(true);
+    }
+
+    fn failure(&mut self) {
+        self.consecutive_failures += 1;
+        if self.state == BreakerState::Closed
+            && self.consecutive_failures >= BREAKER_FAILURE_THRESHOLD
+        {
+            tracing::error!(
+                "{} consecutive backend failures, opening the circuit breaker",
+                self.consecutive_failures
+            );
+            self.set_state(BreakerState::Open);
+            let _ = self.health_sender.send(false);
+        }
+    }
+
+    /// Record the result of a batch. Only inference errors count, and the failures of the
+    /// retried parts of a failed batch do not: the batch already counted as one failure.
+    fn record<T>(&mut self, result: &Result<T, BackendError>, retry: bool) {
+        match result {
+            Ok(_) => self.success(),
+            Err(BackendError::Inference(_)) if !retry => self.failure(),
+            Err(_) => {}
+        }
+    }
+}
+
 #[derive(Debug)]
 struct BackendThread(Option<JoinHandle<()>>);
 
 impl BackendThread {
     fn new(
         backend: Box<dyn CoreBackend + Send>,
+        model_type: ModelType,
         mut backend_receiver: mpsc::Receiver<BackendCommand>,
-        health_sender: watch::Sender<bool>,
+        mut breaker: CircuitBreaker,
     ) -> Self {
         let handle = std::thread::spawn(move || {
+            // Unloaded while a re-initialization is running
+            let mut backend = Some(backend);
             while let Some(cmd) = backend_receiver.blocking_recv() {
                 let start = Instant::now();
-                let mut healthy = false;
                 match cmd {
                     BackendCommand::Health(span, sender) => {
                         let _span = span.entered();
-                        let _ = sender.send(backend.health().map(|_| healthy = true));
+                        let result = match &backend {
+                            Some(backend) => backend.health(),
+                            None => Err(BackendError::Unhealthy),
+                        };
+                        // Only batches and probes can close the breaker
+                        if result.is_err() {
+                            breaker.failure();
+                        }
+                        let _ = sender.send(result);
+                    }
+                    BackendCommand::Embed(batch, retry, span, sender) => {
+                        let _span = span.entered();
+                        let Some(backend) = backend.as_ref().filter(|_| !breaker.is_open()) else {
+                            let _ = sender.send(Err(BackendError::Unhealthy));
+                            continue;
+                        };
+                        let result = backend.embed(batch).map(|e| (e, start.elapsed()));
+                        breaker.record(&result, retry);
+                        let _ = sender.send(result);
                     }
-                    BackendCommand::Embed(batch, span, sender) => {
+                    BackendCommand::Predict(batch, retry, span, sender) => {
                         let _span = span.entered();
-                        let _ = sender.send(backend.embed(batch).map(|e| {
-                            healthy = true;
-                            (e, start.elapsed())
-                        }));
+                        let Some(backend) = backend.as_ref().filter(|_| !breaker.is_open()) else {
+                            let _ = sender.send(Err(BackendError::Unhealthy));
+                            continue;
+                        };
+                        let result = backend.predict(batch).map(|e| (e, start.elapsed()));
+                        breaker.record(&result, retry);
+                        let _ = sender.send(result);
                     }
-                    BackendCommand::Predict(batch, span, sender) => {
+                    BackendCommand::Probe(span, sender) => {
                         let _span = span.entered();
-                        let _ = sender.send(backend.predict(batch).map(|e| {
-                            healthy = true;
-                            (e, start.elapsed())
-                        }));
+                        let was_open = breaker.is_open();
+                        if was_open {
+                            breaker.set_state(BreakerState::HalfOpen);
+                        }
+                        let result = match &backend {
+                            Some(backend) => probe(backend.as_ref(), &model_type),
+                            None => Err(BackendError::Unhealthy),
+                        };
+                        match &result {
+                            Ok(()) => breaker.success(),
+                            Err(_) if was_open => breaker.set_state(BreakerState::Open),
+                            Err(_) => breaker.failure(),
+                        }
+                        let _ = sender.send(result);
+                    }
+                    BackendCommand::Unload(sender) => {
+                        tracing::info!("Unloading the backend");
+                        backend = None;
+                        let _ = sender.send(());
+                    }
+                    BackendCommand::Reinit(new_backend) => {
+                        tracing::info!("Backend re-initialized");
+                        backend = Some(new_backend);
                     }
                 };
-                let _ = health_sender.send(healthy);
             }
         });
         Self(Some(handle))
     }
 }
 
+/// Forward a single token test batch
+fn probe(backend: &(dyn CoreBackend + Send), model_type: &ModelType) -> Result<(), BackendError> {
+    let batch = Batch {
+        input_ids: vec![0],
+        token_type_ids: vec![0],
+        position_ids: vec![0],
+        cumulative_seq_lengths: vec![0, 1],
+        max_length: 1,
+        pooled_indices: vec![0],
+        raw_indices: vec![],
+    };
+    match model_type {
+        ModelType::Classifier => backend.predict(batch).map(|_| ()),
+        ModelType::Embedding(_) => backend.embed(batch).map(|_| ()),
+    }
+}
+
+/// Probe the backend with an exponential backoff while the circuit breaker is open, and
+/// re-initialize the model after repeated failed probes
+async fn recovery_task<F, Fut>(
+    backend_sender: mpsc::WeakSender<BackendCommand>,
+    mut breaker_receiver: watch::Receiver<BreakerState>,
+    reinit: F,
+) where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<Box<dyn CoreBackend + Send>, BackendError>>,
+{
+    loop {
+        while *breaker_receiver.borrow_and_update() != BreakerState::Open {
+            if breaker_receiver.changed().await.is_err() {
+                // The backend thread stopped
+                return;
+            }
+        }
+
+        let mut backoff = BREAKER_PROBE_BACKOFF;
+        let mut failed_probes = 0;
+        loop {
+            tokio::time::sleep(backoff).await;
+
+            // Only hold the sender while probing so that the backend can be dropped
+            let Some(sender) = backend_sender.upgrade() else {
+                return;
+            };
+            let (probe_sender, probe_receiver) = oneshot::channel();
+            if sender
+                .send(BackendCommand::Probe(Span::current(), probe_sender))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            match probe_receiver.await {
+                Ok(Ok(())) => break,
+                Ok(Err(err)) => tracing::warn!("Backend probe failed: {err}"),
+                Err(_) => return,
+            }
+
+            failed_probes += 1;
+            if failed_probes % BREAKER_REINIT_PROBES == 0 {
+                tracing::warn!("Re-initializing the backend after {failed_probes} failed probes");
+                // Free the memory of the old model before loading the new one
+                let (unload_sender, unload_receiver) = oneshot::channel();
+                if sender
+                    .send(BackendCommand::Unload(unload_sender))
+                    .await
+                    .is_err()
+                    || unload_receiver.await.is_err()
+                {
+                    return;
+                }
+                match reinit().await {
+                    Ok(backend) => {
+                        if sender.send(BackendCommand::Reinit(backend)).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => tracing::error!("Could not re-initialize the backend: {err}"),
+                }
+            }
+            backoff = (backoff * 2).min(BREAKER_MAX_PROBE_BACKOFF);
+        }
+    }
+}
+
 impl Drop for BackendThread {
     fn drop(&mut self) {
         self.0.take().unwrap().join().unwrap();
@@ -578,17 +870,26 @@ impl Drop for BackendThread {
 
 enum BackendCommand {
     Health(Span, oneshot::Sender<Result<(), BackendError>>),
+    /// The `bool` is set for the retried parts of a failed batch
     Embed(
         Batch,
+        bool,
         Span,
         oneshot::Sender<Result<(Embeddings, Duration), BackendError>>,
     ),
     Predict(
         Batch,
+        bool,
         Span,
         #[allow(clippy::type_complexity)]
         oneshot::Sender<Result<(Predictions, Duration), BackendError>>,
     ),
+    /// Forward a test batch, even if the circuit breaker is open
+    Probe(Span, oneshot::Sender<Result<(), BackendError>>),
+    /// Drop the model before its re-initialization
+    Unload(oneshot::Sender<()>),
+    /// Replace the model by a re-initialized one
+    Reinit(Box<dyn CoreBackend + Send>),
 }
 
 async fn download_safetensors(api: Arc<ApiRepo>) -> Result<Vec<PathBuf>, ApiError> {
@@ -603,7 +904,10 @@ async fn download_safetensors(api: Arc<ApiRepo>) -> Result<Vec<PathBuf>, ApiErro
     tracing::info!("Downloading `0_StaticEmbedding/model.safetensors`");
     match api.get("0_StaticEmbedding/model.safetensors").await {
         Ok(p) => return Ok(vec![p]),
-        Err(err) => tracing::warn!("Could not download `0_StaticEmbedding/model.safetensors`: {}", err),
+        Err(err) => tracing::warn!(
+            "Could not download `0_StaticEmbedding/model.safetensors`: {}",
+            err
+        ),
     };
 
     // Sharded weights
@@ -695,6 +999,8 @@ enum ModuleType {
     Pooling,
     #[serde(rename = "sentence_transformers.models.Transformer")]
     Transformer,
+    #[serde(rename = "pylate.models.Dense.Dense")]
+    PyLateDense,
 }
 
 #[cfg(feature = "candle")]
@@ -807,6 +1113,32 @@ pub async fn download_dense_modules(
     }
 }
 
+/// Download the PyLate Dense module holding the ColBERT projection, if the model has one
+#[cfg(feature = "candle")]
+#[instrument(skip_all)]
+pub async fn download_colbert_module(api: &ApiRepo) -> Result<Option<String>, ApiError> {
+    let Ok(modules_path) = download_file(api, "modules.json").await else {
+        return Ok(None);
+    };
+    let Ok(content) = std::fs::read_to_string(modules_path) else {
+        return Ok(None);
+    };
+    let Ok(modules) = serde_json::from_str::<Vec<ModuleConfig>>(&content) else {
+        return Ok(None);
+    };
+
+    match modules
+        .into_iter()
+        .find(|module| module.module_type == ModuleType::PyLateDense)
+    {
+        Some(module) => {
+            download_dense_module(api, &module.path).await?;
+            Ok(Some(module.path))
+        }
+        None => Ok(None),
+    }
+}
+
 #[cfg(feature = "candle")]
 async fn download_dense_module(api: &ApiRepo, dense_path: &str) -> Result<PathBuf, ApiError> {
     // Download `config.json` for the Dense module
@@ -833,3 +1165,158 @@ async fn download_dense_module(api: &ApiRepo, dense_path: &str) -> Result<PathBu
 
     Ok(config_path.parent().unwrap().to_path_buf())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Backend whose batches fail while it is not healthy
+    struct FlakyBackend {
+        healthy: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl CoreBackend for FlakyBackend {
+        fn health(&self) -> Result<(), BackendError> {
+            Ok(())
+        }
+
+        fn is_padded(&self) -> bool {
+            false
+        }
+
+        fn embed(&self, _batch: Batch) -> Result<Embeddings, BackendError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.healthy.load(Ordering::SeqCst) {
+                Ok(Embeddings::default())
+            } else {
+                Err(BackendError::Inference("failed".to_string()))
+            }
+        }
+
+        fn predict(&self, _batch: Batch) -> Result<Predictions, BackendError> {
+            unimplemented!()
+        }
+    }
+
+    fn breaker() -> (
+        CircuitBreaker,
+        watch::Receiver<bool>,
+        watch::Receiver<BreakerState>,
+    ) {
+        let (health_sender, health_receiver) = watch::channel(true);
+        let (state_sender, state_receiver) = watch::channel(BreakerState::Closed);
+        let breaker = CircuitBreaker::new("model".to_string(), health_sender, state_sender);
+        (breaker, health_receiver, state_receiver)
+    }
+
+    fn batch() -> Batch {
+        Batch {
+            input_ids: vec![0],
+            token_type_ids: vec![0],
+            position_ids: vec![0],
+            cumulative_seq_lengths: vec![0, 1],
+            max_length: 1,
+            pooled_indices: vec![0],
+            raw_indices: vec![],
+        }
+    }
+
+    fn embed(sender: &mpsc::Sender<BackendCommand>) -> Result<(), BackendError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let command = BackendCommand::Embed(batch(), false, Span::none(), result_sender);
+        sender.blocking_send(command).unwrap();
+        result_receiver.blocking_recv().unwrap().map(|_| ())
+    }
+
+    fn probe(sender: &mpsc::Sender<BackendCommand>) -> Result<(), BackendError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let command = BackendCommand::Probe(Span::none(), result_sender);
+        sender.blocking_send(command).unwrap();
+        result_receiver.blocking_recv().unwrap()
+    }
+
+    #[test]
+    fn test_breaker_opens_and_resets() {
+        let (mut breaker, health, state) = breaker();
+        let failure: Result<(), _> = Err(BackendError::Inference("failed".to_string()));
+
+        for _ in 1..BREAKER_FAILURE_THRESHOLD {
+            breaker.record(&failure, false);
+        }
+        assert_eq!(*state.borrow(), BreakerState::Closed);
+        assert!(*health.borrow());
+
+        // A success resets the count of consecutive failures
+        breaker.record(&Ok(()), false);
+        for _ in 1..BREAKER_FAILURE_THRESHOLD {
+            breaker.record(&failure, false);
+        }
+        assert_eq!(*state.borrow(), BreakerState::Closed);
+
+        breaker.record(&failure, false);
+        assert_eq!(*state.borrow(), BreakerState::Open);
+        assert!(!*health.borrow());
+
+        breaker.record(&Ok(()), false);
+        assert_eq!(*state.borrow(), BreakerState::Closed);
+        assert!(*health.borrow());
+        assert_eq!(breaker.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_breaker_only_counts_inference_failures() {
+        let (mut breaker, health, state) = breaker();
+
+        for _ in 0..2 * BREAKER_FAILURE_THRESHOLD {
+            breaker.record::<()>(&Err(BackendError::Unhealthy), false);
+            breaker.record::<()>(&Err(BackendError::Inference("failed".to_string())), true);
+        }
+        assert_eq!(*state.borrow(), BreakerState::Closed);
+        assert!(*health.borrow());
+        assert_eq!(breaker.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_breaker_probe() {
+        let healthy = Arc::new(AtomicBool::new(false));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let backend = FlakyBackend {
+            healthy: healthy.clone(),
+            calls: calls.clone(),
+        };
+        let (breaker, health, state) = breaker();
+        let (sender, receiver) = mpsc::channel(8);
+        let thread = BackendThread::new(
+            Box::new(backend),
+            ModelType::Embedding(Pool::Cls),
+            receiver,
+            breaker,
+        );
+
+        for _ in 0..BREAKER_FAILURE_THRESHOLD {
+            assert!(matches!(embed(&sender), Err(BackendError::Inference(_))));
+        }
+        assert_eq!(*state.borrow(), BreakerState::Open);
+        assert!(!*health.borrow());
+
+        // Batches fail fast without reaching the backend while the breaker is open
+        assert!(matches!(embed(&sender), Err(BackendError::Unhealthy)));
+        assert_eq!(calls.load(Ordering::SeqCst), BREAKER_FAILURE_THRESHOLD);
+
+        // Probes reach the backend, a failed probe keeps the breaker open
+        assert!(probe(&sender).is_err());
+        assert_eq!(*state.borrow(), BreakerState::Open);
+        assert_eq!(calls.load(Ordering::SeqCst), BREAKER_FAILURE_THRESHOLD + 1);
+
+        healthy.store(true, Ordering::SeqCst);
+        assert!(probe(&sender).is_ok());
+        assert_eq!(*state.borrow(), BreakerState::Closed);
+        assert!(*health.borrow());
+        assert!(embed(&sender).is_ok());
+
+        drop(sender);
+        drop(thread);
+    }
+}
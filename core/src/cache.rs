@@ -0,0 +1,225 @@
+use crate::tokenization::ValidEncoding;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Approximate memory overhead of a cache entry outside of its vectors
+const ENTRY_OVERHEAD_BYTES: usize = 128;
+
+/// Kind of result stored in the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheKind {
+    PooledEmbedding,
+    Classification,
+}
+
+/// Content-addressed key: two requests share an entry only if they produce the same model inputs
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    kind: CacheKind,
+    prompt_name: Option<String>,
+    input_ids: Vec<u32>,
+    token_type_ids: Vec<u32>,
+}
+
+impl CacheKey {
+    pub fn new(kind: CacheKind, prompt_name: Option<String>, encoding: &ValidEncoding) -> Self {
+        Self {
+            kind,
+            prompt_name,
+            input_ids: encoding.input_ids.clone(),
+            token_type_ids: encoding.token_type_ids.clone(),
+        }
+    }
+
+    fn size_bytes(&self) -> usize {
+        (self.input_ids.len() + self.token_type_ids.len()) * std::mem::size_of::<u32>()
+            + self.prompt_name.as_ref().map(|p| p.len()).unwrap_or(0)
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    results: Arc<Vec<f32>>,
+    size_bytes: usize,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Keys ordered by last use: the first key is the least recently used
+    lru: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    size_bytes: usize,
+}
+
+/// Bounded LRU cache of pooled embeddings and classification scores
+///
+/// Entries are scoped to a model revision and the cache is bounded by the memory used by its
+/// entries.
+#[derive(Debug, Clone)]
+pub struct EmbeddingCache {
+    /// Model id, revision and pooling of the model these results were computed with
+    revision: Arc<str>,
+    max_size_bytes: usize,
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl EmbeddingCache {
+    pub fn new(revision: String, max_size_bytes: usize) -> Self {
+        Self {
+            revision: revision.into(),
+            max_size_bytes,
+            state: Arc::new(Mutex::new(CacheState::default())),
+        }
+    }
+
+    pub fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<Vec<f32>>> {
+        let mut guard = self
+            .state
+            .lock()
+            .expect("cache lock was poisoned. This is a bug.");
+        let state = &mut *guard;
+        state.tick += 1;
+        let tick = state.tick;
+
+        let entry = state.entries.get_mut(key)?;
+        let previous = std::mem::replace(&mut entry.last_used, tick);
+        let results = entry.results.clone();
+
+        if let Some(key) = state.lru.remove(&previous) {
+            state.lru.insert(tick, key);
+        }
+        Some(results)
+    }
+
+    pub fn insert(&self, key: CacheKey, results: Vec<f32>) {
+        let size_bytes =
+            key.size_bytes() + results.len() * std::mem::size_of::<f32>() + ENTRY_OVERHEAD_BYTES;
+        if size_bytes > self.max_size_bytes {
+            return;
+        }
+
+        let mut guard = self
+            .state
+            .lock()
+            .expect("cache lock was poisoned. This is a bug.");
+        let state = &mut *guard;
+        state.tick += 1;
+        let tick = state.tick;
+
+        if let Some(previous) = state.entries.remove(&key) {
+            state.lru.remove(&previous.last_used);
+            state.size_bytes -= previous.size_bytes;
+        }
+
+        // Evict least recently used entries until the new entry fits
+        while state.size_bytes + size_bytes > self.max_size_bytes {
+            let Some((_, evicted)) = state.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = state.entries.remove(&evicted) {
+                state.size_bytes -= entry.size_bytes;
+            }
+        }
+
+        state.lru.insert(tick, key.clone());
+        state.entries.insert(
+            key,
+            CacheEntry {
+                results: Arc::new(results),
+                size_bytes,
+                last_used: tick,
+            },
+        );
+        state.size_bytes += size_bytes;
+    }
+
+    pub fn clear(&self) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("cache lock was poisoned. This is a bug.");
+        state.entries.clear();
+        state.lru.clear();
+        state.size_bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .expect("cache lock was poisoned. This is a bug.")
+            .entries
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.state
+            .lock()
+            .expect("cache lock was poisoned. This is a bug.")
+            .size_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(ids: Vec<u32>) -> CacheKey {
+        let encoding = ValidEncoding {
+            token_type_ids: vec![0; ids.len()],
+            position_ids: (0..ids.len() as u32).collect(),
+            input_ids: ids,
+        };
+        CacheKey::new(CacheKind::PooledEmbedding, None, &encoding)
+    }
+
+    #[test]
+    fn test_hit_and_miss() {
+        let cache = EmbeddingCache::new("model@main".to_string(), 1024);
+        cache.insert(key(vec![1, 2, 3]), vec![0.5, 0.25]);
+
+        assert_eq!(
+            cache.get(&key(vec![1, 2, 3])).unwrap().as_slice(),
+            &[0.5, 0.25]
+        );
+        assert!(cache.get(&key(vec![1, 2])).is_none());
+
+        let classification = CacheKey {
+            kind: CacheKind::Classification,
+            ..key(vec![1, 2, 3])
+        };
+        assert!(cache.get(&classification).is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        // Room for two entries of this size
+        let entry_size = key(vec![1]).size_bytes() + 4 + ENTRY_OVERHEAD_BYTES;
+        let cache = EmbeddingCache::new("model@main".to_string(), entry_size * 2);
+
+        cache.insert(key(vec![1]), vec![1.0]);
+        cache.insert(key(vec![2]), vec![2.0]);
+        // Touch the first entry so the second one becomes the least recently used
+        assert!(cache.get(&key(vec![1])).is_some());
+        cache.insert(key(vec![3]), vec![3.0]);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key(vec![1])).is_some());
+        assert!(cache.get(&key(vec![2])).is_none());
+        assert!(cache.get(&key(vec![3])).is_some());
+        assert_eq!(cache.size_bytes(), entry_size * 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.size_bytes(), 0);
+    }
+}
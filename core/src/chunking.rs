@@ -0,0 +1,215 @@
+//! Long inputs chunking logic
+use std::ops::Range;
+
+/// A window of an input text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    /// Character offset of the first character of the chunk in the input
+    pub start: usize,
+    /// Character offset after the last character of the chunk in the input
+    pub end: usize,
+    /// Number of tokens of the chunk, without special tokens
+    pub num_tokens: usize,
+}
+
+/// Ranges of tokens of windows of `window` tokens, overlapping by `stride` tokens
+pub fn token_windows(num_tokens: usize, window: usize, stride: usize) -> Vec<Range<usize>> {
+    assert!(stride < window, "stride must be smaller than the window");
+
+    let step = window - stride;
+    let mut windows = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + window).min(num_tokens);
+        windows.push(start..end);
+        if end == num_tokens {
+            break;
+        }
+        start += step;
+    }
+    windows
+}
+
+/// Split `text` in windows of tokens using the byte offsets of its tokens
+pub fn split_into_chunks(
+    text: &str,
+    offsets: &[(usize, usize)],
+    window: usize,
+    stride: usize,
+) -> Vec<Chunk> {
+    if offsets.is_empty() {
+        return vec![];
+    }
+
+    token_windows(offsets.len(), window, stride)
+        .into_iter()
+        .map(|tokens| {
+            let start_byte = offsets[tokens.start].0;
+            let end_byte = offsets[tokens.end - 1].1;
+            Chunk {
+                text: text[start_byte..end_byte].to_string(),
+                start: text[..start_byte].chars().count(),
+                end: text[..end_byte].chars().count(),
+                num_tokens: tokens.len(),
+            }
+        })
+        .collect()
+}
+
+/// Character spans of the pieces of `text` between `separator`s, skipping blank pieces
+pub fn separator_spans(text: &str, separator: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for piece in text.split(separator) {
+        let end = start + piece.chars().count();
+        if !piece.trim().is_empty() {
+            spans.push(start..end);
+        }
+        start = end + separator.chars().count();
+    }
+    spans
+}
+
+/// Indices of the tokens overlapping each character span of `text`
+///
+/// `offsets` are the byte offsets of the tokens in `text`, or `None` for the tokens that are not
+/// part of it, such as special tokens or prompt tokens.
+pub fn span_tokens(
+    text: &str,
+    offsets: &[Option<(usize, usize)>],
+    spans: &[Range<usize>],
+) -> Vec<Vec<usize>> {
+    // Byte offset of each character, plus the end of the text
+    let bytes: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect();
+
+    spans
+        .iter()
+        .map(|span| {
+            let (start, end) = (bytes[span.start], bytes[span.end]);
+            offsets
+                .iter()
+                .enumerate()
+                .filter_map(|(i, offset)| match offset {
+                    Some((s, e)) if *s < end && *e > start => Some(i),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Average chunk embeddings, weighting each chunk by its number of tokens if `weights` is set
+pub fn aggregate<E: AsRef<[f32]>>(
+    embeddings: &[E],
+    weights: Option<&[usize]>,
+    normalize: bool,
+) -> Vec<f32> {
+    let dim = embeddings.first().map(|e| e.as_ref().len()).unwrap_or(0);
+    let mut aggregated = vec![0.0f64; dim];
+    let mut total_weight = 0.0f64;
+
+    for (i, embedding) in embeddings.iter().enumerate() {
+        let weight = weights.map(|w| w[i] as f64).unwrap_or(1.0);
+        total_weight += weight;
+        for (a, v) in aggregated.iter_mut().zip(embedding.as_ref()) {
+            *a += *v as f64 * weight;
+        }
+    }
+
+    let mut aggregated: Vec<f32> = aggregated
+        .into_iter()
+        .map(|v| (v / total_weight.max(f64::EPSILON)) as f32)
+        .collect();
+
+    if normalize {
+        let norm = aggregated
+            .iter()
+            .map(|v| (*v as f64) * (*v as f64))
+            .sum::<f64>()
+            .sqrt();
+        if norm > 0.0 {
+            let scale = (1.0 / norm) as f32;
+            for v in aggregated.iter_mut() {
+                *v *= scale;
+            }
+        }
+    }
+    aggregated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_windows() {
+        assert_eq!(token_windows(3, 4, 0), vec![0..3]);
+        assert_eq!(token_windows(8, 4, 0), vec![0..4, 4..8]);
+        assert_eq!(token_windows(9, 4, 1), vec![0..4, 3..7, 6..9]);
+    }
+
+    #[test]
+    fn test_split_into_chunks() {
+        let text = "héllo big world";
+        // Byte offsets of "héllo", "big" and "world"
+        let offsets = vec![(0, 6), (7, 10), (11, 16)];
+
+        let chunks = split_into_chunks(text, &offsets, 2, 1);
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk {
+                    text: "héllo big".to_string(),
+                    start: 0,
+                    end: 9,
+                    num_tokens: 2,
+                },
+                Chunk {
+                    text: "big world".to_string(),
+                    start: 6,
+                    end: 15,
+                    num_tokens: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_span_tokens() {
+        let text = "héllo. big world";
+        assert_eq!(separator_spans(text, ". "), vec![0..5, 7..16]);
+        assert_eq!(separator_spans("a\n\n\n\nb\n\n", "\n\n"), vec![0..1, 5..6]);
+
+        // A special token, then "héllo", ".", "big" and "world"
+        let offsets = vec![
+            None,
+            Some((0, 6)),
+            Some((6, 7)),
+            Some((8, 11)),
+            Some((12, 17)),
+        ];
+        assert_eq!(
+            span_tokens(text, &offsets, &[0..5, 7..16, 4..9]),
+            vec![vec![1], vec![3, 4], vec![1, 2, 3]]
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let embeddings = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+
+        assert_eq!(aggregate(&embeddings, None, false), vec![0.5, 0.5]);
+        assert_eq!(
+            aggregate(&embeddings, Some(&[3, 1]), false),
+            vec![0.75, 0.25]
+        );
+
+        let normalized = aggregate(&embeddings, None, true);
+        assert!((normalized[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+}
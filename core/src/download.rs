@@ -14,6 +14,9 @@ pub const ST_CONFIG_NAMES: [&str; 7] = [
     "sentence_xlnet_config.json",
 ];
 
+/// Optional int8 calibration ranges of the embeddings, used for scalar quantization
+pub const QUANTIZATION_RANGES_NAME: &str = "quantization_ranges.json";
+
 async fn download_file(api: &ApiRepo, file_path: &str) -> Result<PathBuf, ApiError> {
     tracing::info!("Downloading `{}`", file_path);
     api.get(file_path).await
@@ -66,6 +69,10 @@ pub async fn download_artifacts(api: &ApiRepo, pool_config: bool) -> Result<Path
             err
         });
 
+    // Download the optional quantization calibration ranges (no warn on failure as most models
+    // don't ship them)
+    let _ = download_file(api, QUANTIZATION_RANGES_NAME).await;
+
     download_file(api, "config.json").await?;
     let path = match download_file(api, "tokenizer.json").await {
         Ok(path) => path,
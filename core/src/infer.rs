@@ -1,12 +1,18 @@
-use crate::queue::{Entry, Metadata, NextBatch, Queue};
-use crate::tokenization::{EncodingInput, RawEncoding, Tokenization};
+use crate::cache::{CacheKey, CacheKind, EmbeddingCache};
+use crate::chunking::{aggregate, span_tokens, split_into_chunks, Chunk};
+use crate::multi_vector::{is_punctuation, kept_tokens, ColBERTConfig, MultiVectorRole};
+use crate::queue::{sub_batch, Entry, Metadata, NextBatch, Priority, Queue};
+use crate::tokenization::{EncodingInput, RawEncoding, Tokenization, ValidEncoding};
 use crate::TextEmbeddingsError;
+use std::ops::Range;
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
 use std::time::{Duration, Instant};
-use text_embeddings_backend::{Backend, BackendError, Embedding, ModelType};
+use text_embeddings_backend::{
+    Backend, BackendError, Batch, Embedding, Embeddings, ModelType, Predictions,
+};
 use tokenizers::TruncationDirection;
 use tokio::sync::{mpsc, oneshot, watch, Notify, OwnedSemaphorePermit, Semaphore};
 use tracing::instrument;
@@ -21,6 +27,31 @@ pub struct Infer {
     /// Inference limit
     limit_concurrent_requests: Arc<Semaphore>,
     backend: Backend,
+    /// Optional cache of pooled embeddings and classification scores
+    cache: Option<EmbeddingCache>,
+    /// Markers and query augmentation of multi-vector (ColBERT) models
+    colbert: Arc<ColBERTConfig>,
+    /// Priority class of the requests queued by this handle
+    priority: Priority,
+    /// Instant after which the requests queued by this handle are not computed anymore
+    deadline: Option<Instant>,
+    /// Stops the batching and backend tasks once every handle is dropped
+    _tasks: Arc<TasksGuard>,
+}
+
+/// Stops the batching task when dropped. The backend task then stops once the last batch is
+/// computed, which releases the backend.
+#[derive(Debug)]
+struct TasksGuard {
+    notify_batching_task: Arc<Notify>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Drop for TasksGuard {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.notify_batching_task.notify_one();
+    }
 }
 
 impl Infer {
@@ -30,8 +61,11 @@ impl Infer {
         queue: Queue,
         max_concurrent_requests: usize,
         backend: Backend,
+        cache: Option<EmbeddingCache>,
+        bisect_failed_batches: bool,
     ) -> Self {
         let notify_batching_task = Arc::new(Notify::new());
+        let stopped = Arc::new(AtomicBool::new(false));
 
         // Bound channel to 1 to be able to prefetch one batch
         let (embed_sender, embed_receiver) = mpsc::channel(1);
@@ -40,11 +74,16 @@ impl Infer {
         tokio::spawn(batching_task(
             queue.clone(),
             notify_batching_task.clone(),
+            stopped.clone(),
             embed_sender,
         ));
 
         // Create embed task to communicate with backend
-        tokio::spawn(backend_task(backend.clone(), embed_receiver));
+        tokio::spawn(backend_task(
+            backend.clone(),
+            bisect_failed_batches,
+            embed_receiver,
+        ));
 
         // Inference limit with a semaphore
         let semaphore = Arc::new(Semaphore::new(max_concurrent_requests));
@@ -52,9 +91,41 @@ impl Infer {
         Self {
             tokenization,
             queue,
-            notify_batching_task,
+            notify_batching_task: notify_batching_task.clone(),
             limit_concurrent_requests: semaphore,
             backend,
+            cache,
+            colbert: Arc::new(ColBERTConfig::default()),
+            priority: Priority::default(),
+            deadline: None,
+            _tasks: Arc::new(TasksGuard {
+                notify_batching_task,
+                stopped,
+            }),
+        }
+    }
+
+    /// Apply the markers and the query augmentation of `config` to the multi-vector inputs
+    pub fn with_colbert_config(self, config: ColBERTConfig) -> Self {
+        Self {
+            colbert: Arc::new(config),
+            ..self
+        }
+    }
+
+    /// Handle queueing its requests with the given priority class
+    pub fn with_priority(&self, priority: Priority) -> Self {
+        Self {
+            priority,
+            ..self.clone()
+        }
+    }
+
+    /// Handle evicting its requests from the queue if they are not computed before `deadline`
+    pub fn with_deadline(&self, deadline: Option<Instant>) -> Self {
+        Self {
+            deadline,
+            ..self.clone()
         }
     }
 
@@ -76,6 +147,58 @@ impl Infer {
             })
     }
 
+    /// Split `inputs` in windows of at most `max_tokens` tokens, overlapping by `stride` tokens
+    ///
+    /// `max_tokens` includes the special tokens and the prompt tokens that are added to each
+    /// window when it is embedded.
+    #[instrument(skip(self, inputs))]
+    pub async fn chunk(
+        &self,
+        inputs: String,
+        max_tokens: usize,
+        stride: usize,
+        prompt_name: Option<String>,
+    ) -> Result<Vec<Chunk>, TextEmbeddingsError> {
+        let (encoded_inputs, encoding) = self.tokenize(inputs.clone(), true, prompt_name).await?;
+
+        // Length of the prompt prepended to the inputs during tokenization
+        let prefix = encoded_inputs
+            .map(|e| e.len().saturating_sub(inputs.len()))
+            .unwrap_or(0);
+
+        let offsets: Vec<(usize, usize)> = encoding
+            .get_offsets()
+            .iter()
+            .zip(encoding.get_special_tokens_mask())
+            .filter(|((start, _), special)| **special == 0 && *start >= prefix)
+            .map(|((start, end), _)| (start - prefix, end - prefix))
+            .collect();
+
+        if offsets.is_empty() {
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            return Err(TextEmbeddingsError::Empty(
+                "`inputs` cannot be empty".to_string(),
+            ));
+        }
+
+        let reserved = encoding.len() - offsets.len();
+        if max_tokens <= reserved + stride {
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            let message = format!(
+                "chunks must be larger than `stride` plus the {reserved} special and prompt tokens. Given: {max_tokens}"
+            );
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Validation(message));
+        }
+
+        Ok(split_into_chunks(
+            &inputs,
+            &offsets,
+            max_tokens - reserved,
+            stride,
+        ))
+    }
+
     #[instrument(skip(self, ids))]
     pub async fn decode(
         &self,
@@ -126,6 +249,30 @@ impl Infer {
         prompt_name: Option<String>,
         permit: OwnedSemaphorePermit,
         batch_counter: Option<Arc<AtomicUsize>>,
+    ) -> Result<AllEmbeddingsInferResponse, TextEmbeddingsError> {
+        self.embed_all_expanded(
+            inputs,
+            truncate,
+            truncation_direction,
+            prompt_name,
+            None,
+            permit,
+            batch_counter,
+        )
+        .await
+    }
+
+    /// `embed_all`, with `expansion` tokens appended to the encoding
+    #[allow(clippy::too_many_arguments)]
+    async fn embed_all_expanded<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        truncate: bool,
+        truncation_direction: TruncationDirection,
+        prompt_name: Option<String>,
+        expansion: Option<(u32, usize)>,
+        permit: OwnedSemaphorePermit,
+        batch_counter: Option<Arc<AtomicUsize>>,
     ) -> Result<AllEmbeddingsInferResponse, TextEmbeddingsError> {
         let start_time = Instant::now();
 
@@ -145,6 +292,7 @@ impl Infer {
                 truncate,
                 truncation_direction,
                 prompt_name,
+                expansion,
                 false,
                 &start_time,
                 permit,
@@ -170,6 +318,204 @@ impl Infer {
         Ok(response)
     }
 
+    /// Embed `inputs` in a single forward pass and mean pool the token embeddings of each
+    /// character span, so every span embedding carries the context of the whole input
+    ///
+    /// `inputs` must fit in the model maximum input length.
+    #[instrument(skip(self, inputs, permit))]
+    pub async fn embed_spans(
+        &self,
+        inputs: String,
+        spans: Vec<Range<usize>>,
+        prompt_name: Option<String>,
+        normalize: bool,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<AllEmbeddingsInferResponse, TextEmbeddingsError> {
+        let num_chars = inputs.chars().count();
+        if spans.is_empty() {
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            return Err(TextEmbeddingsError::Empty(
+                "`spans` cannot be empty".to_string(),
+            ));
+        }
+        if let Some(span) = spans
+            .iter()
+            .find(|span| span.start >= span.end || span.end > num_chars)
+        {
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            let message = format!(
+                "span {}..{} is not a non-empty span of the {num_chars} characters of `inputs`",
+                span.start, span.end
+            );
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Validation(message));
+        }
+
+        let (encoded_inputs, encoding) = self
+            .tokenize(inputs.clone(), true, prompt_name.clone())
+            .await?;
+
+        // Length of the prompt prepended to the inputs during tokenization
+        let prefix = encoded_inputs
+            .map(|e| e.len().saturating_sub(inputs.len()))
+            .unwrap_or(0);
+
+        let offsets: Vec<Option<(usize, usize)>> = encoding
+            .get_offsets()
+            .iter()
+            .zip(encoding.get_special_tokens_mask())
+            .map(|((start, end), special)| {
+                (*special == 0 && *start >= prefix).then(|| (start - prefix, end - prefix))
+            })
+            .collect();
+
+        let span_tokens = span_tokens(&inputs, &offsets, &spans);
+        if let Some(i) = span_tokens.iter().position(Vec::is_empty) {
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            let message = format!(
+                "span {}..{} does not contain any token",
+                spans[i].start, spans[i].end
+            );
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Validation(message));
+        }
+
+        let mut response = self
+            .embed_all(
+                inputs,
+                false,
+                TruncationDirection::Right,
+                prompt_name,
+                permit,
+                None,
+            )
+            .await?;
+
+        if response.results.len() != offsets.len() {
+            let message = format!(
+                "backend returned {} token embeddings for {} tokens",
+                response.results.len(),
+                offsets.len()
+            );
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Backend(BackendError::Inference(
+                message,
+            )));
+        }
+
+        response.results = span_tokens
+            .iter()
+            .map(|tokens| {
+                let embeddings: Vec<&Vec<f32>> =
+                    tokens.iter().map(|i| &response.results[*i]).collect();
+                aggregate(&embeddings, None, normalize)
+            })
+            .collect();
+
+        Ok(response)
+    }
+
+    /// Get the projected and normalized token embeddings of a multi-vector (ColBERT) model,
+    /// without the special tokens and, if `skip_punctuation` is set, the punctuation tokens
+    ///
+    /// The inputs get the query or document marker of the model. Queries are padded with
+    /// `[MASK]` tokens up to the query length of the model and the embeddings of these tokens
+    /// are returned after the other ones.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, inputs, permit))]
+    pub async fn embed_multi_vector<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        truncate: bool,
+        truncation_direction: TruncationDirection,
+        prompt_name: Option<String>,
+        role: MultiVectorRole,
+        skip_punctuation: bool,
+        permit: OwnedSemaphorePermit,
+        batch_counter: Option<Arc<AtomicUsize>>,
+    ) -> Result<AllEmbeddingsInferResponse, TextEmbeddingsError> {
+        if !self.is_multi_vector() {
+            let counter = metrics::counter!("te_request_failure", "err" => "model_type");
+            counter.increment(1);
+            let message = "Model is not a multi-vector model".to_string();
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Backend(BackendError::Inference(
+                message,
+            )));
+        }
+
+        let inputs = match (inputs.into(), self.colbert.prefix(role)) {
+            (EncodingInput::Single(input), Some(prefix)) => {
+                EncodingInput::Single(format!("{prefix}{input}"))
+            }
+            (inputs, _) => inputs,
+        };
+        let (encoded_inputs, encoding) = self
+            .tokenize(inputs.clone(), true, prompt_name.clone())
+            .await?;
+
+        // The `[MASK]` tokens go after the special tokens of the encoding, once the prompt is
+        // applied
+        let expansion = self.colbert.expansion(role, encoding.len());
+        let mask_expansion = self
+            .colbert
+            .mask_token_id
+            .filter(|_| expansion > 0)
+            .map(|mask_token_id| (mask_token_id, expansion));
+
+        let special_tokens_mask = encoding.get_special_tokens_mask();
+        let leading = special_tokens_mask.iter().take_while(|s| **s == 1).count();
+        let trailing = special_tokens_mask
+            .iter()
+            .rev()
+            .take_while(|s| **s == 1)
+            .count()
+            .min(encoding.len() - leading);
+
+        let mut response = self
+            .embed_all_expanded(
+                inputs,
+                truncate,
+                truncation_direction,
+                prompt_name,
+                mask_expansion,
+                permit,
+                batch_counter,
+            )
+            .await?;
+
+        let mut results = std::mem::take(&mut response.results);
+        let expanded = results.split_off(results.len().min(encoding.len()));
+
+        let kept_tokens = kept_tokens(
+            encoding.len(),
+            leading,
+            trailing,
+            results.len(),
+            truncation_direction,
+        );
+
+        response.results = results
+            .into_iter()
+            .zip(kept_tokens)
+            .filter(|(_, i)| {
+                if special_tokens_mask[*i] == 1 {
+                    return false;
+                }
+                let (start, end) = encoding.get_offsets()[*i];
+                let token = encoded_inputs
+                    .as_deref()
+                    .and_then(|e| e.get(start..end))
+                    .unwrap_or_default();
+                !(skip_punctuation && is_punctuation(token))
+            })
+            .map(|(embedding, _)| embedding)
+            .chain(expanded)
+            .collect();
+
+        Ok(response)
+    }
+
     #[instrument(skip(self, inputs, permit))]
     pub async fn embed_sparse<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
@@ -198,6 +544,7 @@ impl Infer {
                 truncate,
                 truncation_direction,
                 prompt_name,
+                None,
                 true,
                 &start_time,
                 permit,
@@ -268,6 +615,7 @@ impl Infer {
                 truncate,
                 truncation_direction,
                 prompt_name,
+                None,
                 true,
                 &start_time,
                 permit,
@@ -329,6 +677,8 @@ impl Infer {
         Ok(response)
     }
 
+    /// `expansion` appends a number of copies of a token to the encoding, after the prompt and the
+    /// special tokens added by the tokenizer
     #[allow(clippy::too_many_arguments)]
     async fn embed<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
@@ -336,6 +686,7 @@ impl Infer {
         truncate: bool,
         truncation_direction: TruncationDirection,
         prompt_name: Option<String>,
+        expansion: Option<(u32, usize)>,
         pooling: bool,
         start_time: &Instant,
         _permit: OwnedSemaphorePermit,
@@ -354,8 +705,11 @@ impl Infer {
         let counter = metrics::counter!("te_embed_count");
         counter.increment(1);
 
+        // `encode` takes ownership of the prompt name, keep a copy for the cache key
+        let cache_prompt_name = prompt_name.clone();
+
         // Tokenization
-        let encoding = self
+        let mut encoding = self
             .tokenization
             .encode(inputs.into(), truncate, truncation_direction, prompt_name)
             .await
@@ -365,6 +719,26 @@ impl Infer {
                 tracing::error!("{err}");
                 err
             })?;
+        if let Some((token_id, count)) = expansion {
+            encoding.extend(token_id, count);
+        }
+
+        let cache_key = match (&self.cache, pooling) {
+            (Some(cache), true) => {
+                let key = CacheKey::new(CacheKind::PooledEmbedding, cache_prompt_name, &encoding);
+                if let Some(results) = self.cache_get(cache, &key, "embed") {
+                    self.notify_batching_task(batch_counter);
+                    return Ok(InferResult::PooledEmbedding(
+                        PooledEmbeddingsInferResponse {
+                            results,
+                            metadata: InferMetadata::cached(&encoding, start_time),
+                        },
+                    ));
+                }
+                Some(key)
+            }
+            _ => None,
+        };
 
         // MPSC channel to communicate with the background batching task
         let (response_tx, response_rx) = oneshot::channel();
@@ -377,18 +751,13 @@ impl Infer {
                 queue_time: Instant::now(),
                 prompt_tokens: encoding.input_ids.len(),
                 pooling,
+                priority: self.priority,
+                deadline: self.deadline,
             },
             encoding,
         });
 
-        match batch_counter {
-            None => self.notify_batching_task.notify_one(),
-            Some(counter) => {
-                if counter.fetch_sub(1, Ordering::SeqCst) == 1 {
-                    self.notify_batching_task.notify_one();
-                }
-            }
-        }
+        self.notify_batching_task(batch_counter);
 
         let response = response_rx
             .await
@@ -396,12 +765,22 @@ impl Infer {
                 "Infer batching task dropped the sender without sending a response. This is a bug.",
             )
             .map_err(|err| {
-                let counter = metrics::counter!("te_request_failure", "err" => "inference");
+                let label = match err {
+                    TextEmbeddingsError::Timeout(_) => "timeout",
+                    _ => "inference",
+                };
+                let counter = metrics::counter!("te_request_failure", "err" => label);
                 counter.increment(1);
                 tracing::error!("{err}");
                 err
             })?;
 
+        if let (Some(cache), Some(key), InferResult::PooledEmbedding(response)) =
+            (&self.cache, cache_key, &response)
+        {
+            cache.insert(key, response.results.clone());
+        }
+
         Ok(response)
     }
 
@@ -440,6 +819,27 @@ impl Infer {
                 err
             })?;
 
+        let cache_key = match &self.cache {
+            Some(cache) => {
+                let key = CacheKey::new(CacheKind::Classification, None, &encoding);
+                match self.cache_get(cache, &key, "predict") {
+                    Some(results) => {
+                        self.notify_batching_task(batch_counter);
+                        return Ok(Self::post_process_scores(
+                            ClassificationInferResponse {
+                                results,
+                                metadata: InferMetadata::cached(&encoding, &start_time),
+                            },
+                            raw_scores,
+                            &start_time,
+                        ));
+                    }
+                    None => Some(key),
+                }
+            }
+            None => None,
+        };
+
         // MPSC channel to communicate with the background batching task
         let (response_tx, response_rx) = oneshot::channel();
 
@@ -451,18 +851,13 @@ impl Infer {
                 queue_time: Instant::now(),
                 prompt_tokens: encoding.input_ids.len(),
                 pooling: true,
+                priority: self.priority,
+                deadline: self.deadline,
             },
             encoding,
         });
 
-        match batch_counter {
-            None => self.notify_batching_task.notify_one(),
-            Some(counter) => {
-                if counter.fetch_sub(1, Ordering::SeqCst) == 1 {
-                    self.notify_batching_task.notify_one();
-                }
-            }
-        }
+        self.notify_batching_task(batch_counter);
 
         let response = response_rx
             .await
@@ -470,16 +865,33 @@ impl Infer {
                 "Infer batching task dropped the sender without sending a response. This is a bug.",
             )
             .map_err(|err| {
-                let counter = metrics::counter!("te_request_failure", "err" => "inference");
+                let label = match err {
+                    TextEmbeddingsError::Timeout(_) => "timeout",
+                    _ => "inference",
+                };
+                let counter = metrics::counter!("te_request_failure", "err" => label);
                 counter.increment(1);
                 tracing::error!("{err}");
                 err
             })?;
 
-        let InferResult::Classification(mut response) = response else {
+        let InferResult::Classification(response) = response else {
             panic!("unexpected enum variant")
         };
 
+        if let (Some(cache), Some(key)) = (&self.cache, cache_key) {
+            cache.insert(key, response.results.clone());
+        }
+
+        Ok(Self::post_process_scores(response, raw_scores, &start_time))
+    }
+
+    /// Apply softmax or sigmoid to the raw scores and record the predict metrics
+    fn post_process_scores(
+        mut response: ClassificationInferResponse,
+        raw_scores: bool,
+        start_time: &Instant,
+    ) -> ClassificationInferResponse {
         if !raw_scores {
             // Softmax
             if response.results.len() > 1 {
@@ -519,7 +931,49 @@ impl Infer {
         let histogram = metrics::histogram!("te_predict_inference_duration");
         histogram.record(response.metadata.inference.as_secs_f64());
 
-        Ok(response)
+        response
+    }
+
+    /// Look up the cache and record hit and miss metrics
+    fn cache_get(
+        &self,
+        cache: &EmbeddingCache,
+        key: &CacheKey,
+        method: &'static str,
+    ) -> Option<Vec<f32>> {
+        match cache.get(key) {
+            Some(results) => {
+                metrics::counter!("te_cache_hit", "method" => method).increment(1);
+                Some(results.as_ref().clone())
+            }
+            None => {
+                metrics::counter!("te_cache_miss", "method" => method).increment(1);
+                None
+            }
+        }
+    }
+
+    fn notify_batching_task(&self, batch_counter: Option<Arc<AtomicUsize>>) {
+        match batch_counter {
+            None => self.notify_batching_task.notify_one(),
+            Some(counter) => {
+                if counter.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    self.notify_batching_task.notify_one();
+                }
+            }
+        }
+    }
+
+    /// Drop all the entries of the cache
+    #[instrument(skip(self))]
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    pub fn cache(&self) -> Option<&EmbeddingCache> {
+        self.cache.as_ref()
     }
 
     #[instrument(skip(self))]
@@ -527,6 +981,11 @@ impl Infer {
         matches!(self.backend.model_type, ModelType::Classifier)
     }
 
+    #[instrument(skip(self))]
+    pub fn is_multi_vector(&self) -> bool {
+        self.backend.multi_vector
+    }
+
     #[instrument(skip(self))]
     pub fn is_splade(&self) -> bool {
         matches!(
@@ -547,9 +1006,18 @@ impl Infer {
 }
 
 #[instrument(skip_all)]
-async fn batching_task(queue: Queue, notify: Arc<Notify>, embed_sender: mpsc::Sender<NextBatch>) {
+async fn batching_task(
+    queue: Queue,
+    notify: Arc<Notify>,
+    stopped: Arc<AtomicBool>,
+    embed_sender: mpsc::Sender<NextBatch>,
+) {
     loop {
         notify.notified().await;
+        if stopped.load(Ordering::SeqCst) {
+            // Every handle was dropped: no request can be queued anymore
+            break;
+        }
 
         {
             let mut permit = embed_sender
@@ -568,85 +1036,138 @@ async fn batching_task(queue: Queue, notify: Arc<Notify>, embed_sender: mpsc::Se
     }
 }
 
+/// Results of a batch, keyed by the index of the entry in the batch
+enum BatchResults {
+    Classification(Predictions),
+    Embedding(Embeddings),
+}
+
+async fn compute(
+    backend: &Backend,
+    batch: Batch,
+    retry: bool,
+) -> Result<(BatchResults, Duration), BackendError> {
+    match (&backend.model_type, retry) {
+        (ModelType::Classifier, false) => backend
+            .predict(batch)
+            .await
+            .map(|(p, d)| (BatchResults::Classification(p), d)),
+        (ModelType::Classifier, true) => backend
+            .predict_retry(batch)
+            .await
+            .map(|(p, d)| (BatchResults::Classification(p), d)),
+        (ModelType::Embedding(_), false) => backend
+            .embed(batch)
+            .await
+            .map(|(e, d)| (BatchResults::Embedding(e), d)),
+        (ModelType::Embedding(_), true) => backend
+            .embed_retry(batch)
+            .await
+            .map(|(e, d)| (BatchResults::Embedding(e), d)),
+    }
+}
+
 #[instrument(skip_all)]
-async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextBatch>) {
-    while let Some(batch) = embed_receiver.recv().await {
-        match &backend.model_type {
-            ModelType::Classifier => {
-                let results = backend.predict(batch.1).await;
-
-                // Handle sending responses in a blocking task to avoid starving the backend
-                tokio::task::spawn_blocking(move || match results {
-                    Ok((mut predictions, inference_duration)) => {
-                        batch.0.into_iter().enumerate().for_each(|(i, m)| {
-                            let infer_metadata = InferMetadata {
-                                prompt_tokens: m.prompt_tokens,
-                                tokenization: m.tokenization,
-                                queue: m.queue_time.elapsed() - inference_duration,
-                                inference: inference_duration,
-                            };
-
-                            let _ = m.response_tx.send(Ok(InferResult::Classification(
-                                ClassificationInferResponse {
-                                    results: predictions.remove(&i).expect(
-                                        "prediction not found in results. This is a backend bug.",
-                                    ),
-                                    metadata: infer_metadata,
-                                },
-                            )));
-                        });
-                    }
-                    Err(err) => {
-                        batch.0.into_iter().for_each(|m| {
-                            let _ = m.response_tx.send(Err(err.clone()));
-                        });
+async fn backend_task(
+    backend: Backend,
+    bisect_failed_batches: bool,
+    mut embed_receiver: mpsc::Receiver<NextBatch>,
+) {
+    while let Some((metadata, batch)) = embed_receiver.recv().await {
+        let len = batch.len();
+        // Copy of the batch to retry its parts if it fails
+        let retry_batch = (bisect_failed_batches && len > 1).then(|| sub_batch(&batch, 0..len));
+
+        let results = compute(&backend, batch, false).await;
+        let Some(retry_batch) = retry_batch else {
+            send_results(metadata, results);
+            continue;
+        };
+
+        // Bisect the failed parts of the batch until the failing entries are isolated, so that
+        // they do not fail their neighbours
+        let mut metadata: Vec<Option<Metadata>> = metadata.into_iter().map(Some).collect();
+        let mut pending = vec![(0..len, results)];
+        while let Some((range, results)) = pending.pop() {
+            match results {
+                Err(err) if range.len() > 1 && !matches!(err, BackendError::Unhealthy) => {
+                    let middle = range.start + range.len() / 2;
+                    for half in [middle..range.end, range.start..middle] {
+                        metrics::counter!("te_batch_retry").increment(1);
+                        let results =
+                            compute(&backend, sub_batch(&retry_batch, half.clone()), true).await;
+                        pending.push((half, results));
                     }
-                });
+                }
+                results => {
+                    let metadata = metadata[range]
+                        .iter_mut()
+                        .map(|m| m.take().expect("entry answered twice. This is a bug."))
+                        .collect();
+                    send_results(metadata, results);
+                }
             }
-            ModelType::Embedding(_) => {
-                let results = backend.embed(batch.1).await;
-
-                // Handle sending responses in a blocking task to avoid starving the backend
-                tokio::task::spawn_blocking(move || match results {
-                    Ok((mut embeddings, inference_duration)) => {
-                        batch.0.into_iter().enumerate().for_each(|(i, m)| {
-                            let metadata = InferMetadata {
-                                prompt_tokens: m.prompt_tokens,
-                                tokenization: m.tokenization,
-                                queue: m.queue_time.elapsed() - inference_duration,
-                                inference: inference_duration,
-                            };
-
-                            let results = match embeddings
-                                .remove(&i)
-                                .expect("embedding not found in results. This is a backend bug.")
-                            {
-                                Embedding::Pooled(e) => {
-                                    InferResult::PooledEmbedding(PooledEmbeddingsInferResponse {
-                                        results: e,
-                                        metadata,
-                                    })
-                                }
-                                Embedding::All(e) => {
-                                    InferResult::AllEmbedding(AllEmbeddingsInferResponse {
-                                        results: e,
-                                        metadata,
-                                    })
-                                }
-                            };
-
-                            let _ = m.response_tx.send(Ok(results));
+        }
+    }
+}
+
+/// Send the results of a batch to its entries
+fn send_results(metadata: Vec<Metadata>, results: Result<(BatchResults, Duration), BackendError>) {
+    // Handle sending responses in a blocking task to avoid starving the backend
+    tokio::task::spawn_blocking(move || match results {
+        Ok((BatchResults::Classification(mut predictions), inference_duration)) => {
+            metadata.into_iter().enumerate().for_each(|(i, m)| {
+                let infer_metadata = InferMetadata {
+                    prompt_tokens: m.prompt_tokens,
+                    tokenization: m.tokenization,
+                    queue: m.queue_time.elapsed() - inference_duration,
+                    inference: inference_duration,
+                };
+
+                let _ = m.response_tx.send(Ok(InferResult::Classification(
+                    ClassificationInferResponse {
+                        results: predictions
+                            .remove(&i)
+                            .expect("prediction not found in results. This is a backend bug."),
+                        metadata: infer_metadata,
+                    },
+                )));
+            });
+        }
+        Ok((BatchResults::Embedding(mut embeddings), inference_duration)) => {
+            metadata.into_iter().enumerate().for_each(|(i, m)| {
+                let metadata = InferMetadata {
+                    prompt_tokens: m.prompt_tokens,
+                    tokenization: m.tokenization,
+                    queue: m.queue_time.elapsed() - inference_duration,
+                    inference: inference_duration,
+                };
+
+                let results = match embeddings
+                    .remove(&i)
+                    .expect("embedding not found in results. This is a backend bug.")
+                {
+                    Embedding::Pooled(e) => {
+                        InferResult::PooledEmbedding(PooledEmbeddingsInferResponse {
+                            results: e,
+                            metadata,
                         })
                     }
-                    Err(err) => {
-                        batch.0.into_iter().for_each(|m| {
-                            let _ = m.response_tx.send(Err(err.clone()));
-                        });
-                    }
-                });
-            }
-        };
-    }
+                    Embedding::All(e) => InferResult::AllEmbedding(AllEmbeddingsInferResponse {
+                        results: e,
+                        metadata,
+                    }),
+                };
+
+                let _ = m.response_tx.send(Ok(results));
+            })
+        }
+        Err(err) => {
+            metadata.into_iter().for_each(|m| {
+                let _ = m.response_tx.send(Err(err.clone().into()));
+            });
+        }
+    });
 }
 
 #[derive(Debug)]
@@ -657,6 +1178,18 @@ pub struct InferMetadata {
     pub inference: Duration,
 }
 
+impl InferMetadata {
+    /// Metadata of a result served from the cache: it never reached the queue or the backend
+    fn cached(encoding: &ValidEncoding, start_time: &Instant) -> Self {
+        Self {
+            prompt_tokens: encoding.input_ids.len(),
+            tokenization: start_time.elapsed(),
+            queue: Duration::ZERO,
+            inference: Duration::ZERO,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum InferResult {
     Classification(ClassificationInferResponse),
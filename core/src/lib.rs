@@ -1,5 +1,8 @@
+pub mod cache;
+pub mod chunking;
 pub mod download;
 pub mod infer;
+pub mod multi_vector;
 pub mod queue;
 pub mod templates;
 pub mod tokenization;
@@ -20,4 +23,6 @@ pub enum TextEmbeddingsError {
     Overloaded(#[from] TryAcquireError),
     #[error("Backend error: {0}")]
     Backend(#[from] BackendError),
+    #[error("Request timed out: {0}")]
+    Timeout(String),
 }
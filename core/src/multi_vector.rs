@@ -0,0 +1,148 @@
+//! Late interaction (ColBERT) logic
+use tokenizers::TruncationDirection;
+
+/// Markers and query augmentation of a ColBERT model
+#[derive(Debug, Clone, Default)]
+pub struct ColBERTConfig {
+    /// Text prepended to the queries, e.g. `[Q] `
+    pub query_prefix: Option<String>,
+    /// Text prepended to the documents, e.g. `[D] `
+    pub document_prefix: Option<String>,
+    /// Queries shorter than this number of tokens are padded with `[MASK]` tokens
+    pub query_length: Option<usize>,
+    /// Id of the `[MASK]` token of the tokenizer
+    pub mask_token_id: Option<u32>,
+}
+
+/// Whether a multi-vector input is encoded as a query or as a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiVectorRole {
+    Query,
+    Document,
+}
+
+impl ColBERTConfig {
+    /// Marker prepended to the inputs of `role`
+    pub fn prefix(&self, role: MultiVectorRole) -> Option<&str> {
+        match role {
+            MultiVectorRole::Query => self.query_prefix.as_deref(),
+            MultiVectorRole::Document => self.document_prefix.as_deref(),
+        }
+    }
+
+    /// Number of `[MASK]` tokens appended to an input of `num_tokens` tokens
+    pub fn expansion(&self, role: MultiVectorRole, num_tokens: usize) -> usize {
+        match (role, self.query_length, self.mask_token_id) {
+            (MultiVectorRole::Query, Some(query_length), Some(_)) => {
+                query_length.saturating_sub(num_tokens)
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Whether a token only contains punctuation. ColBERT skips these tokens in documents.
+pub fn is_punctuation(token: &str) -> bool {
+    let token = token.trim();
+    !token.is_empty() && token.chars().all(|c| c.is_ascii_punctuation())
+}
+
+/// Index in the untruncated encoding of each token that went through the model
+///
+/// Truncation keeps the `leading` and `trailing` special tokens and drops content tokens from
+/// the end or the start of the encoding.
+pub fn kept_tokens(
+    num_tokens: usize,
+    leading: usize,
+    trailing: usize,
+    num_kept: usize,
+    truncation_direction: TruncationDirection,
+) -> Vec<usize> {
+    if num_kept >= num_tokens {
+        return (0..num_tokens).collect();
+    }
+
+    let content = num_kept.saturating_sub(leading + trailing);
+    let content_start = match truncation_direction {
+        TruncationDirection::Right => leading,
+        TruncationDirection::Left => num_tokens - trailing - content,
+    };
+
+    (0..leading)
+        .chain(content_start..content_start + content)
+        .chain(num_tokens - trailing..num_tokens)
+        .collect()
+}
+
+/// Late interaction score: sum over the query tokens of their maximum similarity with the
+/// document tokens
+pub fn maxsim(query: &[Vec<f32>], document: &[Vec<f32>]) -> f32 {
+    query
+        .iter()
+        .map(|q| {
+            document
+                .iter()
+                .map(|d| q.iter().zip(d).map(|(a, b)| a * b).sum::<f32>())
+                .fold(f32::NEG_INFINITY, f32::max)
+        })
+        .filter(|score| score.is_finite())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expansion() {
+        let config = ColBERTConfig {
+            query_prefix: Some("[Q] ".to_string()),
+            document_prefix: Some("[D] ".to_string()),
+            query_length: Some(32),
+            mask_token_id: Some(103),
+        };
+        assert_eq!(config.prefix(MultiVectorRole::Document), Some("[D] "));
+        assert_eq!(config.expansion(MultiVectorRole::Query, 10), 22);
+        assert_eq!(config.expansion(MultiVectorRole::Query, 40), 0);
+        assert_eq!(config.expansion(MultiVectorRole::Document, 10), 0);
+
+        // Queries are not expanded without a `[MASK]` token
+        let config = ColBERTConfig {
+            mask_token_id: None,
+            ..config
+        };
+        assert_eq!(config.expansion(MultiVectorRole::Query, 10), 0);
+    }
+
+    #[test]
+    fn test_is_punctuation() {
+        assert!(is_punctuation("."));
+        assert!(is_punctuation(" ?!"));
+        assert!(!is_punctuation("a."));
+        assert!(!is_punctuation(""));
+    }
+
+    #[test]
+    fn test_kept_tokens() {
+        assert_eq!(
+            kept_tokens(4, 1, 1, 4, TruncationDirection::Right),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(
+            kept_tokens(6, 1, 1, 4, TruncationDirection::Right),
+            vec![0, 1, 2, 5]
+        );
+        assert_eq!(
+            kept_tokens(6, 1, 1, 4, TruncationDirection::Left),
+            vec![0, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_maxsim() {
+        let query = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+        let document = vec![vec![0.5, 0.5], vec![1.0, 0.0]];
+        assert_eq!(maxsim(&query, &document), 1.5);
+        assert_eq!(maxsim(&query, &[]), 0.0);
+    }
+}
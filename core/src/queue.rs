@@ -1,12 +1,63 @@
 use crate::infer::InferResult;
 use crate::tokenization::ValidEncoding;
+use crate::TextEmbeddingsError;
 use std::cmp::max;
 use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::mpsc;
 use std::time::{Duration, Instant};
-use text_embeddings_backend::{BackendError, Batch};
-use tokio::sync::{mpsc, oneshot};
+use text_embeddings_backend::Batch;
+use tokio::sync::oneshot;
 use tracing::{instrument, Span};
 
+/// Interval between two evictions of the expired entries
+const EVICTION_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Request priority class
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    /// All priority classes, from the highest to the lowest
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "high" => Ok(Priority::High),
+            "normal" => Ok(Priority::Normal),
+            "low" => Ok(Priority::Low),
+            _ => Err(format!(
+                "invalid priority `{s}`: expected `high`, `normal` or `low`"
+            )),
+        }
+    }
+}
+
 /// Queue entry
 #[derive(Debug)]
 pub struct Entry {
@@ -20,7 +71,7 @@ pub struct Entry {
 #[derive(Debug)]
 pub struct Metadata {
     /// InferResponse sender to communicate between the Infer struct and the batching_task
-    pub(crate) response_tx: oneshot::Sender<Result<InferResult, BackendError>>,
+    pub(crate) response_tx: oneshot::Sender<Result<InferResult, TextEmbeddingsError>>,
     /// Tokenization duration
     pub(crate) tokenization: Duration,
     /// Instant when this entry was queued
@@ -29,13 +80,23 @@ pub struct Metadata {
     pub(crate) prompt_tokens: usize,
     /// Pooled embedding
     pub(crate) pooling: bool,
+    /// Priority class of the request
+    pub(crate) priority: Priority,
+    /// Instant after which the request is evicted from the queue
+    pub(crate) deadline: Option<Instant>,
+}
+
+impl Metadata {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
 }
 
 /// Request Queue
 #[derive(Debug, Clone)]
 pub struct Queue {
     /// Channel to communicate with the background queue task
-    queue_sender: mpsc::Sender<QueueCommand>,
+    queue_sender: mpsc::SyncSender<QueueCommand>,
 }
 
 impl Queue {
@@ -44,11 +105,13 @@ impl Queue {
         max_batch_tokens: usize,
         max_batch_requests: Option<usize>,
         max_concurrent_requests: usize,
+        max_queue_time: Option<Duration>,
+        starvation_timeout: Duration,
     ) -> Self {
         // Create channels
         // The queue rarely fails to send the QueueCommand due to a lack of buffer size.
         // So, naively increasing the buffer size to twice than `max_concurrent_requests` to prevent the failure temporarily
-        let (queue_sender, queue_receiver) = mpsc::channel(2 * max_concurrent_requests);
+        let (queue_sender, queue_receiver) = mpsc::sync_channel(2 * max_concurrent_requests);
 
         // Launch background queue task
         std::thread::spawn(move || {
@@ -57,6 +120,8 @@ impl Queue {
                 max_batch_tokens,
                 max_batch_requests,
                 max_concurrent_requests,
+                max_queue_time,
+                starvation_timeout,
                 queue_receiver,
             )
         });
@@ -101,16 +166,55 @@ fn queue_blocking_task(
     max_batch_tokens: usize,
     max_batch_requests: Option<usize>,
     max_concurrent_requests: usize,
-    mut queue_receiver: mpsc::Receiver<QueueCommand>,
+    max_queue_time: Option<Duration>,
+    starvation_timeout: Duration,
+    queue_receiver: mpsc::Receiver<QueueCommand>,
 ) {
     let capacity = max_batch_requests.unwrap_or(max_concurrent_requests);
 
-    let mut entries: VecDeque<Entry> = VecDeque::with_capacity(max_concurrent_requests);
+    let mut entries = PriorityEntries::new(max_concurrent_requests);
+
+    let mut last_eviction = Instant::now();
+
+    loop {
+        let timeout = EVICTION_INTERVAL.saturating_sub(last_eviction.elapsed());
+        let cmd = match queue_receiver.recv_timeout(timeout) {
+            Ok(cmd) => Some(cmd),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            // The queue was dropped
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Regularly evict the expired entries, even when the queue does not receive any command
+        if last_eviction.elapsed() >= EVICTION_INTERVAL {
+            if entries.evict(Instant::now()) > 0 {
+                entries.record_sizes();
+            }
+            last_eviction = Instant::now();
+        }
+
+        let Some(cmd) = cmd else {
+            continue;
+        };
 
-    while let Some(cmd) = queue_receiver.blocking_recv() {
         match cmd {
-            QueueCommand::Append(entry, span) => {
+            QueueCommand::Append(mut entry, span) => {
                 let _span = span.entered();
+
+                // The server-wide queue time budget caps the deadline of the request
+                if let Some(max_queue_time) = max_queue_time {
+                    let budget = entry.metadata.queue_time + max_queue_time;
+                    entry.metadata.deadline = Some(match entry.metadata.deadline {
+                        Some(deadline) => deadline.min(budget),
+                        None => budget,
+                    });
+                }
+
+                if entry.metadata.is_expired(Instant::now()) {
+                    expire(*entry);
+                    continue;
+                }
+
                 entries.push_back(*entry);
                 let gauge = metrics::gauge!("te_queue_size");
                 gauge.increment(1.0);
@@ -136,7 +240,8 @@ fn queue_blocking_task(
 
                 let mut entry_index = 0;
 
-                while let Some(entry) = entries.pop_front() {
+                let now = Instant::now();
+                while let Some(entry) = entries.pop_front(starvation_timeout) {
                     // Filter entries where the response receiver was dropped (== entries where the request
                     // was dropped by the client)
                     if entry.metadata.response_tx.is_closed() {
@@ -145,6 +250,11 @@ fn queue_blocking_task(
                         continue;
                     }
 
+                    if entry.metadata.is_expired(now) {
+                        expire(entry);
+                        continue;
+                    }
+
                     let entry_tokens = entry.encoding.input_ids.len();
 
                     let total_tokens = if padded_model {
@@ -166,6 +276,12 @@ fn queue_blocking_task(
 
                     max_length = max(max_length, entry_tokens as u32);
 
+                    let histogram = metrics::histogram!(
+                        "te_queue_priority_duration",
+                        "priority" => entry.metadata.priority.as_str()
+                    );
+                    histogram.record(entry.metadata.queue_time.elapsed().as_secs_f64());
+
                     input_ids.extend(entry.encoding.input_ids);
                     token_type_ids.extend(entry.encoding.token_type_ids);
                     position_ids.extend(entry.encoding.position_ids);
@@ -205,15 +321,154 @@ fn queue_blocking_task(
                 histogram.record(batch_size as f64);
                 let histogram = metrics::histogram!("te_batch_next_tokens");
                 histogram.record(current_tokens as f64);
-                let gauge = metrics::gauge!("te_queue_size");
-                gauge.set(entries.len() as f64)
+                entries.record_sizes();
+            }
+        }
+    }
+}
+
+/// Answer an expired entry with a timeout error
+fn expire(entry: Entry) {
+    let metadata = entry.metadata;
+    let counter = metrics::counter!("te_request_expired", "priority" => metadata.priority.as_str());
+    counter.increment(1);
+
+    let message = format!(
+        "request expired after {:?} in the queue",
+        metadata.queue_time.elapsed()
+    );
+    let _ = metadata
+        .response_tx
+        .send(Err(TextEmbeddingsError::Timeout(message)));
+}
+
+/// Queue entries, one FIFO per priority class
+#[derive(Debug)]
+struct PriorityEntries {
+    queues: [VecDeque<Entry>; 3],
+}
+
+impl PriorityEntries {
+    fn new(capacity: usize) -> Self {
+        Self {
+            queues: std::array::from_fn(|_| VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn push_back(&mut self, entry: Entry) {
+        let priority = entry.metadata.priority;
+        self.queues[priority as usize].push_back(entry);
+        let gauge = metrics::gauge!("te_queue_priority_size", "priority" => priority.as_str());
+        gauge.increment(1.0);
+    }
+
+    /// Put back an entry that did not fit in the batch
+    fn push_front(&mut self, entry: Entry) {
+        self.queues[entry.metadata.priority as usize].push_front(entry);
+    }
+
+    /// Pop the next entry to batch
+    ///
+    /// Entries are served by priority class. To avoid starving lower priority classes, the
+    /// oldest entry that waited longer than `starvation_timeout` is served first.
+    fn pop_front(&mut self, starvation_timeout: Duration) -> Option<Entry> {
+        let starved = self
+            .queues
+            .iter()
+            .enumerate()
+            .filter_map(|(i, queue)| queue.front().map(|entry| (i, entry.metadata.queue_time)))
+            .filter(|(_, queue_time)| queue_time.elapsed() > starvation_timeout)
+            .min_by_key(|(_, queue_time)| *queue_time)
+            .map(|(i, _)| i);
+
+        let index = starved.or_else(|| self.queues.iter().position(|queue| !queue.is_empty()))?;
+        self.queues[index].pop_front()
+    }
+
+    /// Remove the expired entries and the entries of dropped requests, returning how many were
+    /// removed
+    fn evict(&mut self, now: Instant) -> usize {
+        let mut evicted = 0;
+        for queue in self.queues.iter_mut() {
+            if !queue
+                .iter()
+                .any(|e| e.metadata.is_expired(now) || e.metadata.response_tx.is_closed())
+            {
+                continue;
+            }
+
+            let mut kept = VecDeque::with_capacity(queue.capacity());
+            for entry in queue.drain(..) {
+                if entry.metadata.response_tx.is_closed() {
+                    let counter = metrics::counter!("te_request_failure", "err" => "dropped");
+                    counter.increment(1);
+                } else if entry.metadata.is_expired(now) {
+                    expire(entry);
+                } else {
+                    kept.push_back(entry);
+                    continue;
+                }
+                evicted += 1;
             }
+            *queue = kept;
         }
+        evicted
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    fn record_sizes(&self) {
+        let gauge = metrics::gauge!("te_queue_size");
+        gauge.set(self.len() as f64);
+        for priority in Priority::ALL {
+            let gauge = metrics::gauge!("te_queue_priority_size", "priority" => priority.as_str());
+            gauge.set(self.len_of(priority) as f64);
+        }
+    }
+
+    fn len_of(&self, priority: Priority) -> usize {
+        self.queues[priority as usize].len()
     }
 }
 
 pub type NextBatch = (Vec<Metadata>, Batch);
 
+/// Copy the entries of `batch` in `range` to a new batch
+pub(crate) fn sub_batch(batch: &Batch, range: Range<usize>) -> Batch {
+    let offset = batch.cumulative_seq_lengths[range.start];
+    let tokens = offset as usize..batch.cumulative_seq_lengths[range.end] as usize;
+
+    let cumulative_seq_lengths: Vec<u32> = batch.cumulative_seq_lengths[range.start..=range.end]
+        .iter()
+        .map(|length| length - offset)
+        .collect();
+    let max_length = cumulative_seq_lengths
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .max()
+        .unwrap_or_default();
+
+    let indices = |indices: &[u32]| -> Vec<u32> {
+        indices
+            .iter()
+            .filter(|&&i| range.contains(&(i as usize)))
+            .map(|i| i - range.start as u32)
+            .collect()
+    };
+
+    Batch {
+        input_ids: batch.input_ids[tokens.clone()].to_vec(),
+        token_type_ids: batch.token_type_ids[tokens.clone()].to_vec(),
+        position_ids: batch.position_ids[tokens].to_vec(),
+        cumulative_seq_lengths,
+        max_length,
+        pooled_indices: indices(&batch.pooled_indices),
+        raw_indices: indices(&batch.raw_indices),
+    }
+}
+
 #[derive(Debug)]
 enum QueueCommand {
     Append(Box<Entry>, Span),
@@ -222,3 +477,118 @@ enum QueueCommand {
         span: Span,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(priority: Priority, queue_time: Instant) -> Entry {
+        let (response_tx, _) = oneshot::channel();
+        Entry {
+            encoding: ValidEncoding {
+                input_ids: vec![0],
+                token_type_ids: vec![0],
+                position_ids: vec![0],
+            },
+            metadata: Metadata {
+                response_tx,
+                tokenization: Duration::ZERO,
+                queue_time,
+                prompt_tokens: 1,
+                pooling: true,
+                priority,
+                deadline: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_priority_from_str() {
+        assert_eq!("HIGH".parse::<Priority>().unwrap(), Priority::High);
+        assert_eq!(" low".parse::<Priority>().unwrap(), Priority::Low);
+        assert!("urgent".parse::<Priority>().is_err());
+    }
+
+    #[test]
+    fn test_priority_entries() {
+        let now = Instant::now();
+        let mut entries = PriorityEntries::new(4);
+        entries.push_back(entry(Priority::Low, now));
+        entries.push_back(entry(Priority::Normal, now));
+        entries.push_back(entry(Priority::High, now));
+        assert_eq!(entries.len(), 3);
+
+        let order: Vec<Priority> = std::iter::from_fn(|| entries.pop_front(Duration::MAX))
+            .map(|entry| entry.metadata.priority)
+            .collect();
+        assert_eq!(order, vec![Priority::High, Priority::Normal, Priority::Low]);
+    }
+
+    #[test]
+    fn test_priority_entries_starvation() {
+        let now = Instant::now();
+        let mut entries = PriorityEntries::new(4);
+        entries.push_back(entry(Priority::Low, now - Duration::from_secs(10)));
+        entries.push_back(entry(Priority::High, now));
+
+        let first = entries.pop_front(Duration::from_secs(5)).unwrap();
+        assert_eq!(first.metadata.priority, Priority::Low);
+        assert_eq!(entries.len_of(Priority::High), 1);
+    }
+
+    #[test]
+    fn test_evict() {
+        let now = Instant::now();
+        let mut entries = PriorityEntries::new(4);
+
+        let (response_tx, mut expired_rx) = oneshot::channel();
+        let mut expired = entry(Priority::Normal, now);
+        expired.metadata.response_tx = response_tx;
+        expired.metadata.deadline = Some(now);
+        entries.push_back(expired);
+
+        let (response_tx, _kept_rx) = oneshot::channel();
+        let mut kept = entry(Priority::Normal, now);
+        kept.metadata.response_tx = response_tx;
+        kept.metadata.deadline = Some(now + Duration::from_secs(10));
+        entries.push_back(kept);
+
+        // The receiver of this entry was dropped
+        entries.push_back(entry(Priority::Low, now));
+
+        assert_eq!(entries.evict(now), 2);
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(
+            expired_rx.try_recv(),
+            Ok(Err(TextEmbeddingsError::Timeout(_)))
+        ));
+    }
+
+    #[test]
+    fn test_sub_batch() {
+        let batch = Batch {
+            input_ids: vec![1, 2, 3, 4, 5, 6],
+            token_type_ids: vec![0; 6],
+            position_ids: vec![0, 1, 0, 1, 2, 0],
+            cumulative_seq_lengths: vec![0, 2, 5, 6],
+            max_length: 3,
+            pooled_indices: vec![0, 2],
+            raw_indices: vec![1],
+        };
+
+        let right = sub_batch(&batch, 1..3);
+        assert_eq!(right.input_ids, vec![3, 4, 5, 6]);
+        assert_eq!(right.position_ids, vec![0, 1, 2, 0]);
+        assert_eq!(right.cumulative_seq_lengths, vec![0, 3, 4]);
+        assert_eq!(right.max_length, 3);
+        assert_eq!(right.pooled_indices, vec![1]);
+        assert_eq!(right.raw_indices, vec![0]);
+
+        let left = sub_batch(&batch, 0..1);
+        assert_eq!(left.input_ids, vec![1, 2]);
+        assert_eq!(left.cumulative_seq_lengths, vec![0, 2]);
+        assert_eq!(left.max_length, 2);
+        assert_eq!(left.pooled_indices, vec![0]);
+        assert!(left.raw_indices.is_empty());
+    }
+}
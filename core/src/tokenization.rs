@@ -418,7 +418,21 @@ pub struct ValidEncoding {
     pub position_ids: Vec<u32>,
 }
 
-#[derive(Debug)]
+impl ValidEncoding {
+    /// Append `count` copies of `token_id`, with the token type of the last token
+    pub fn extend(&mut self, token_id: u32, count: usize) {
+        let token_type_id = self.token_type_ids.last().copied().unwrap_or(0);
+        let next_position = self.position_ids.last().map_or(0, |position| position + 1);
+        self.input_ids
+            .extend(std::iter::repeat(token_id).take(count));
+        self.token_type_ids
+            .extend(std::iter::repeat(token_type_id).take(count));
+        self.position_ids
+            .extend(next_position..next_position + count as u32);
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum EncodingInput {
     Single(String),
     Dual(String, String),
@@ -536,6 +550,19 @@ mod tests {
     use super::*;
     use hf_hub::api::sync::ApiBuilder;
 
+    #[test]
+    fn test_extend() {
+        let mut encoding = ValidEncoding {
+            input_ids: vec![101, 7, 102],
+            token_type_ids: vec![0, 0, 0],
+            position_ids: vec![2, 3, 4],
+        };
+        encoding.extend(103, 2);
+        assert_eq!(encoding.input_ids, vec![101, 7, 102, 103, 103]);
+        assert_eq!(encoding.token_type_ids, vec![0; 5]);
+        assert_eq!(encoding.position_ids, vec![2, 3, 4, 5, 6]);
+    }
+
     #[test]
     fn tokenizer() {
         let api = ApiBuilder::from_env().build().unwrap();
@@ -39,6 +39,13 @@ fn main() -> Result<(), Box<dyn Error>> {
             .include_file("mod.rs")
             .compile(&["../proto/tei.proto"], &["../proto"])
             .unwrap_or_else(|e| panic!("protobuf compilation failed: {}", e));
+
+        // Client of the integration tests, included from `OUT_DIR`
+        tonic_build::configure()
+            .build_client(true)
+            .build_server(false)
+            .compile(&["../proto/tei.proto"], &["../proto"])
+            .unwrap_or_else(|e| panic!("protobuf compilation failed: {}", e));
     }
 
     Ok(())
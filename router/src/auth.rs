@@ -0,0 +1,366 @@
+//! Multi-tenant API keys with per-key rate limits and token quotas
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use veil::Redact;
+
+/// Entry of the API keys file
+#[derive(Deserialize, Redact)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ApiKeyEntry {
+    /// Bearer token of the tenant
+    #[redact]
+    key: String,
+    /// Name of the tenant, used as the `tenant` label of the Prometheus metrics
+    tenant: String,
+    /// Maximum number of requests per second
+    #[serde(default)]
+    requests_per_second: Option<f64>,
+    /// Maximum number of input tokens per minute
+    #[serde(default)]
+    tokens_per_minute: Option<u64>,
+    /// Routes the key can call. A trailing `*` matches any route with the same prefix.
+    /// Defaults to every route.
+    #[serde(default)]
+    routes: Option<Vec<String>>,
+}
+
+/// Token bucket refilled continuously up to its capacity
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    /// Refill rate per second
+    rate: f64,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, rate: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            rate,
+            available: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.available = (self.available + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Take `amount` from the bucket, or return how long to wait until it is available
+    fn try_take(&mut self, amount: f64, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.available >= amount {
+            self.available -= amount;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (amount - self.available) / self.rate,
+            ))
+        }
+    }
+
+    /// Take `amount` from the bucket even if it goes in debt
+    fn take(&mut self, amount: f64, now: Instant) {
+        self.refill(now);
+        self.available -= amount;
+    }
+}
+
+/// Tenant owning an API key
+#[derive(Debug)]
+pub struct Tenant {
+    pub name: String,
+    requests: Option<Mutex<TokenBucket>>,
+    tokens: Option<Mutex<TokenBucket>>,
+    routes: Option<Vec<String>>,
+}
+
+impl Tenant {
+    fn new(entry: ApiKeyEntry, now: Instant) -> Self {
+        // Allow bursts of one second of requests and one minute of tokens
+        let requests = entry
+            .requests_per_second
+            .map(|rps| Mutex::new(TokenBucket::new(rps.max(1.0), rps, now)));
+        let tokens = entry.tokens_per_minute.map(|tpm| {
+            let tpm = tpm as f64;
+            Mutex::new(TokenBucket::new(tpm, tpm / 60.0, now))
+        });
+
+        Self {
+            name: entry.tenant,
+            requests,
+            tokens,
+            routes: entry.routes,
+        }
+    }
+
+    /// Tenant identified by its client certificate, without limits
+    pub fn identified(name: String) -> Arc<Self> {
+        let counter = metrics::counter!("te_tenant_request_count", "tenant" => name.clone());
+        counter.increment(1);
+
+        Arc::new(Self {
+            name,
+            requests: None,
+            tokens: None,
+            routes: None,
+        })
+    }
+
+    fn allows(&self, route: &str) -> bool {
+        match &self.routes {
+            None => true,
+            Some(routes) => routes
+                .iter()
+                .any(|allowed| match allowed.strip_suffix('*') {
+                    Some(prefix) => route.starts_with(prefix),
+                    None => route == allowed,
+                }),
+        }
+    }
+
+    /// Check the rate limits before running a request
+    ///
+    /// The token quota is charged once the number of tokens of the request is known, so a
+    /// request is only rejected once the quota is exhausted.
+    fn check(&self, now: Instant) -> Result<(), Duration> {
+        if let Some(tokens) = &self.tokens {
+            tokens.lock().unwrap().try_take(0.0, now)?;
+        }
+        if let Some(requests) = &self.requests {
+            requests.lock().unwrap().try_take(1.0, now)?;
+        }
+        Ok(())
+    }
+
+    /// Charge the input tokens of a request to the tenant
+    pub fn consume_tokens(&self, tokens: usize) {
+        if let Some(bucket) = &self.tokens {
+            bucket.lock().unwrap().take(tokens as f64, Instant::now());
+        }
+        let counter = metrics::counter!("te_tenant_tokens", "tenant" => self.name.clone());
+        counter.increment(tokens as u64);
+    }
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// Missing or unknown API key
+    Unauthorized,
+    /// The API key cannot call this route
+    Forbidden,
+    /// The tenant is over its limits and can retry after the given duration
+    RateLimited(Duration),
+}
+
+/// API keys loaded from `--api-keys-file`
+#[derive(Debug, Clone)]
+pub struct ApiKeys {
+    path: PathBuf,
+    tenants: Arc<RwLock<HashMap<String, Arc<Tenant>>>>,
+}
+
+impl ApiKeys {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            tenants: Arc::new(RwLock::new(Self::read(path)?)),
+        })
+    }
+
+    fn read(path: &Path) -> anyhow::Result<HashMap<String, Arc<Tenant>>> {
+        // The keys are either a JSON list or the `api_keys` section of a configuration file
+        let is_config = path
+            .extension()
+            .is_some_and(|ext| ext == "toml" || ext == "yaml" || ext == "yml");
+        let entries: Vec<ApiKeyEntry> = if is_config {
+            crate::config::read_api_keys(path)
+                .context(format!("Failed to read API keys of `{}`", path.display()))?
+        } else {
+            let content = std::fs::read_to_string(path)
+                .context(format!("Failed to read `{}`", path.display()))?;
+            serde_json::from_str(&content)
+                .context(format!("Failed to parse `{}`", path.display()))?
+        };
+        Self::from_entries(entries).context(format!("`{}` is invalid", path.display()))
+    }
+
+    fn from_entries(entries: Vec<ApiKeyEntry>) -> anyhow::Result<HashMap<String, Arc<Tenant>>> {
+        let now = Instant::now();
+        let mut tenants = HashMap::with_capacity(entries.len());
+        for entry in entries {
+            if entry.requests_per_second.is_some_and(|rps| rps <= 0.0) {
+                anyhow::bail!("`requests_per_second` of `{}` must be > 0", entry.tenant);
+            }
+            if entry.tokens_per_minute == Some(0) {
+                anyhow::bail!("`tokens_per_minute` of `{}` must be > 0", entry.tenant);
+            }
+
+            let key = format!("Bearer {}", entry.key);
+            if tenants
+                .insert(key, Arc::new(Tenant::new(entry, now)))
+                .is_some()
+            {
+                anyhow::bail!("duplicate API key");
+            }
+        }
+        Ok(tenants)
+    }
+
+    /// Reload the keys file. Rate limits restart from full buckets.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let tenants = Self::read(&self.path)?;
+        let num_keys = tenants.len();
+        *self.tenants.write().unwrap() = tenants;
+        tracing::info!(
+            "Reloaded {num_keys} API keys from `{}`",
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    /// Reload the keys file every time the process receives SIGHUP
+    pub fn reload_on_sighup(&self) {
+        #[cfg(unix)]
+        {
+            let api_keys = self.clone();
+            tokio::spawn(async move {
+                let mut hangup =
+                    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                        .expect("failed to install SIGHUP handler");
+                while hangup.recv().await.is_some() {
+                    if let Err(err) = api_keys.reload() {
+                        tracing::error!("Keeping the previous API keys: {err:#}");
+                    }
+                }
+            });
+        }
+    }
+
+    /// Find the tenant of the `Authorization` header and check that it can call `route`
+    pub fn authorize(
+        &self,
+        authorization: Option<&str>,
+        route: &str,
+    ) -> Result<Arc<Tenant>, AuthError> {
+        let tenant = authorization
+            .and_then(|authorization| self.tenants.read().unwrap().get(authorization).cloned())
+            .ok_or(AuthError::Unauthorized)?;
+
+        let counter = metrics::counter!("te_tenant_request_count", "tenant" => tenant.name.clone());
+        counter.increment(1);
+
+        let err = if !tenant.allows(route) {
+            AuthError::Forbidden
+        } else {
+            match tenant.check(Instant::now()) {
+                Ok(()) => return Ok(tenant),
+                Err(retry_after) => AuthError::RateLimited(retry_after),
+            }
+        };
+
+        let reason = match err {
+            AuthError::Forbidden => "forbidden",
+            _ => "rate_limited",
+        };
+        tracing::warn!("Rejected request of tenant `{}`: {reason}", tenant.name);
+        let counter = metrics::counter!(
+            "te_tenant_request_failure",
+            "tenant" => tenant.name.clone(),
+            "err" => reason
+        );
+        counter.increment(1);
+        Err(err)
+    }
+}
+
+/// Number of seconds of a `Retry-After` header
+pub fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, routes: Option<Vec<&str>>) -> ApiKeyEntry {
+        ApiKeyEntry {
+            key: key.to_string(),
+            tenant: "team".to_string(),
+            requests_per_second: Some(2.0),
+            tokens_per_minute: Some(60),
+            routes: routes.map(|r| r.into_iter().map(String::from).collect()),
+        }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 2.0, now);
+        assert!(bucket.try_take(1.0, now).is_ok());
+        assert!(bucket.try_take(1.0, now).is_ok());
+        assert_eq!(bucket.try_take(1.0, now), Err(Duration::from_millis(500)));
+        assert!(bucket
+            .try_take(1.0, now + Duration::from_millis(500))
+            .is_ok());
+
+        // Token quotas can go in debt
+        bucket.take(3.0, now + Duration::from_millis(500));
+        assert_eq!(
+            bucket.try_take(0.0, now + Duration::from_millis(500)),
+            Err(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn test_routes() {
+        let tenant = Tenant::new(
+            entry("a", Some(vec!["/embed", "/tei.v1.Embed/*"])),
+            Instant::now(),
+        );
+        assert!(tenant.allows("/embed"));
+        assert!(!tenant.allows("/embed_all"));
+        assert!(tenant.allows("/tei.v1.Embed/EmbedStream"));
+
+        let tenant = Tenant::new(entry("a", None), Instant::now());
+        assert!(tenant.allows("/rerank"));
+    }
+
+    #[test]
+    fn test_authorize() {
+        let tenants = ApiKeys::from_entries(vec![entry("a", Some(vec!["/embed"]))]).unwrap();
+        let api_keys = ApiKeys {
+            path: PathBuf::new(),
+            tenants: Arc::new(RwLock::new(tenants)),
+        };
+
+        assert!(matches!(
+            api_keys.authorize(Some("Bearer b"), "/embed"),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(matches!(
+            api_keys.authorize(Some("Bearer a"), "/rerank"),
+            Err(AuthError::Forbidden)
+        ));
+
+        let tenant = api_keys.authorize(Some("Bearer a"), "/embed").unwrap();
+        assert_eq!(tenant.name, "team");
+        tenant.consume_tokens(100);
+        assert!(matches!(
+            api_keys.authorize(Some("Bearer a"), "/embed"),
+            Err(AuthError::RateLimited(_))
+        ));
+
+        assert!(ApiKeys::from_entries(vec![entry("a", None), entry("a", None)]).is_err());
+    }
+}
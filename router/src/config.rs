@@ -0,0 +1,222 @@
+//! Router configuration file
+//!
+//! Every top-level key of the file sets the argument of the same name, as if it were its
+//! default value. Environment variables and flags therefore take precedence over the file.
+use crate::auth::ApiKeyEntry;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Keys of the file that are nested settings rather than arguments
+const API_KEYS: &str = "api_keys";
+
+/// Arguments that cannot be set from the file
+const RESERVED: [&str; 4] = ["config", "print_config", "help", "version"];
+
+#[derive(Debug)]
+pub struct Config {
+    path: PathBuf,
+    values: serde_json::Map<String, Value>,
+    api_keys: Option<Vec<ApiKeyEntry>>,
+}
+
+impl Config {
+    /// Load a TOML file, or a YAML file if its extension is `.yaml` or `.yml`
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut values =
+            read(path).context(format!("Failed to read config `{}`", path.display()))?;
+
+        // Both `max_batch_tokens` and `max-batch-tokens` are accepted
+        values = values
+            .into_iter()
+            .map(|(key, value)| (key.replace('-', "_"), value))
+            .collect();
+
+        let api_keys = values
+            .remove(API_KEYS)
+            .map(serde_json::from_value)
+            .transpose()
+            .context(format!("`{API_KEYS}` of `{}` is invalid", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            values,
+            api_keys,
+        })
+    }
+
+    /// Find the `--config` path in the command line arguments or the `CONFIG` environment
+    /// variable, before the arguments are parsed
+    pub fn find_path(args: &[String]) -> Option<PathBuf> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            }
+            if arg == "--config" {
+                return args.next().map(PathBuf::from);
+            }
+            if let Some(path) = arg.strip_prefix("--config=") {
+                return Some(PathBuf::from(path));
+            }
+        }
+        std::env::var_os("CONFIG").map(PathBuf::from)
+    }
+
+    /// Set the values of the file as the defaults of the arguments of `command`
+    pub fn apply(&self, mut command: clap::Command) -> Result<clap::Command> {
+        for (key, value) in &self.values {
+            let known = !RESERVED.contains(&key.as_str())
+                && command.get_arguments().any(|arg| arg.get_id() == key);
+            if !known {
+                anyhow::bail!("Unknown key `{key}` in config `{}`", self.path.display());
+            }
+
+            let values: Vec<String> = match value {
+                Value::Null => continue,
+                Value::Array(values) => values
+                    .iter()
+                    .map(|value| scalar(key, value))
+                    .collect::<Result<_>>()?,
+                value => vec![scalar(key, value)?],
+            };
+            // Leak to get the `'static` default values of clap
+            let values: Vec<&'static str> = values.into_iter().map(|v| &*v.leak()).collect();
+            command = command.mut_arg(key, |arg| arg.default_values(values));
+        }
+        Ok(command)
+    }
+
+    /// Path of the file the API keys are loaded from, if the file lists API keys
+    pub fn api_keys_file(&self) -> Option<String> {
+        self.api_keys
+            .as_ref()
+            .map(|_| self.path.to_string_lossy().into_owned())
+    }
+
+    /// API keys of the file, with their keys redacted
+    pub fn api_keys(&self) -> Option<impl std::fmt::Debug + '_> {
+        self.api_keys.as_ref()
+    }
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Number(value) => Ok(value.to_string()),
+        _ => anyhow::bail!("`{key}` must be a string, a number, a boolean or a list of them"),
+    }
+}
+
+fn read(path: &Path) -> Result<serde_json::Map<String, Value>> {
+    let content = std::fs::read_to_string(path)?;
+    let is_yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    if is_yaml {
+        Ok(serde_yaml::from_str(&content)?)
+    } else {
+        Ok(toml::from_str(&content)?)
+    }
+}
+
+/// Read the `api_keys` section of a configuration file
+pub(crate) fn read_api_keys(path: &Path) -> Result<Vec<ApiKeyEntry>> {
+    let mut values = read(path)?;
+    let api_keys = values
+        .remove(API_KEYS)
+        .context(format!("`{API_KEYS}` is missing"))?;
+    Ok(serde_json::from_value(api_keys)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn command() -> clap::Command {
+        clap::Command::new("router")
+            .arg(Arg::new("model_id").long("model-id").value_delimiter(','))
+            .arg(Arg::new("port").long("port"))
+            .arg(
+                Arg::new("json_output")
+                    .long("json-output")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("config").long("config"))
+    }
+
+    fn load(content: &str, extension: &str) -> Result<Config> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tei-config-{}-{}.{extension}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, content).unwrap();
+        let config = Config::load(&path);
+        std::fs::remove_file(path).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_precedence() {
+        let config = load(
+            "model-id = [\"a\", \"b\"]\nport = 8080\njson_output = true\n",
+            "toml",
+        )
+        .unwrap();
+        let command = config.apply(command()).unwrap();
+
+        let matches = command.clone().get_matches_from(["router"]);
+        let models: Vec<&String> = matches.get_many("model_id").unwrap().collect();
+        assert_eq!(models, ["a", "b"]);
+        assert_eq!(matches.get_one::<String>("port").unwrap(), "8080");
+        assert!(matches.get_flag("json_output"));
+
+        let matches = command.get_matches_from(["router", "--port", "3000"]);
+        assert_eq!(matches.get_one::<String>("port").unwrap(), "3000");
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let config = load("prot: 8080\n", "yaml").unwrap();
+        assert!(config.apply(command()).is_err());
+
+        let config = load("config: other.yaml\n", "yaml").unwrap();
+        assert!(config.apply(command()).is_err());
+
+        let config = load("[port]\nvalue = 1\n", "toml").unwrap();
+        assert!(config.apply(command()).is_err());
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let config = load(
+            "[[api_keys]]\nkey = \"secret\"\ntenant = \"team\"\nroutes = [\"/embed\"]\n",
+            "toml",
+        )
+        .unwrap();
+        assert!(config.api_keys_file().is_some());
+        let debug = format!("{:?}", config.api_keys().unwrap());
+        assert!(debug.contains("team"));
+        assert!(!debug.contains("secret"));
+
+        assert!(load("[[api_keys]]\nkey = \"secret\"\n", "toml").is_err());
+    }
+
+    #[test]
+    fn test_find_path() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            Config::find_path(&args(&["router", "--config", "a.toml"])),
+            Some(PathBuf::from("a.toml"))
+        );
+        assert_eq!(
+            Config::find_path(&args(&["router", "--port", "1", "--config=b.yaml"])),
+            Some(PathBuf::from("b.yaml"))
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::auth::{retry_after_secs, ApiKeys, AuthError, Tenant};
 use crate::grpc::pb::tei::v1::{
     EmbedAllRequest, EmbedAllResponse, EmbedSparseRequest, EmbedSparseResponse, EncodeRequest,
     EncodeResponse, PredictPairRequest, RerankStreamRequest, SimpleToken, SparseValue,
@@ -6,29 +7,51 @@ use crate::grpc::pb::tei::v1::{
 use crate::grpc::{
     DecodeRequest, DecodeResponse, EmbedRequest, EmbedResponse, InfoRequest, InfoResponse,
     PredictRequest, PredictResponse, Prediction, Rank, RerankRequest, RerankResponse,
+    SimilarityRequest, SimilarityResponse, SimilarityScores, VertexPrediction, VertexRequest,
+    VertexResponse,
 };
+use crate::quantization::{quantize, Precision, QuantizedEmbedding};
+use crate::shutdown::{InFlight, Lifecycle};
+use crate::similarity::{SimilarityInputs, SimilarityMetric};
+use crate::tls::{ClientIdentity, TlsAcceptor, TlsConnectInfo, TlsFiles};
+use crate::uds::UnixSocket;
 use crate::ResponseMetadata;
-use crate::{grpc, shutdown, ErrorResponse, ErrorType, Info, ModelType};
-use futures::future::join_all;
-use metrics_exporter_prometheus::PrometheusBuilder;
+use crate::{grpc, rerank_input, ErrorResponse, ErrorType, ModelType, Models, ServedModel};
+use anyhow::Context;
+use futures::future::{join_all, BoxFuture};
 use std::future::Future;
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::{atomic::AtomicUsize, Arc};
+use std::task::Poll;
 use std::time::{Duration, Instant};
 use text_embeddings_core::infer::Infer;
+use text_embeddings_core::queue::Priority;
 use text_embeddings_core::tokenization::{
     into_tokens, EncodingInput, SimpleToken as CoreSimpleToken,
 };
 use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_stream::StreamExt;
-use tonic::codegen::http::HeaderMap;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{self, HeaderMap};
+use tonic::codegen::{Body as HttpBody, Bytes};
 use tonic::metadata::MetadataMap;
 use tonic::server::NamedService;
 use tonic::transport::Server;
 use tonic::{Code, Extensions, Request, Response, Status, Streaming};
 use tonic_health::ServingStatus;
-use tracing::{instrument, Span};
+use tower::{Layer, Service};
+use tracing::{instrument, Instrument, Span};
+
+impl From<&ResponseMetadata> for grpc::Usage {
+    fn from(value: &ResponseMetadata) -> Self {
+        Self {
+            prompt_tokens: u32::try_from(value.compute_tokens).unwrap_or(u32::MAX),
+            total_tokens: u32::try_from(value.compute_tokens).unwrap_or(u32::MAX),
+        }
+    }
+}
 
 impl From<&ResponseMetadata> for grpc::Metadata {
     fn from(value: &ResponseMetadata) -> Self {
@@ -43,22 +66,48 @@ impl From<&ResponseMetadata> for grpc::Metadata {
     }
 }
 
+/// Requests that can select one of the served models
+trait ModelRequest {
+    fn model(&self) -> Option<&str>;
+}
+
+macro_rules! impl_model_request {
+    ($($request:ty),*) => {
+        $(impl ModelRequest for $request {
+            fn model(&self) -> Option<&str> {
+                self.model.as_deref()
+            }
+        })*
+    };
+}
+
+impl_model_request!(
+    SimilarityRequest,
+    EmbedRequest,
+    EmbedSparseRequest,
+    EmbedAllRequest,
+    PredictRequest,
+    PredictPairRequest,
+    RerankRequest,
+    RerankStreamRequest,
+    EncodeRequest,
+    DecodeRequest
+);
+
 #[derive(Debug, Clone)]
 struct TextEmbeddingsService {
-    infer: Infer,
-    info: Info,
+    models: Models,
     max_parallel_stream_requests: usize,
 }
 
 impl TextEmbeddingsService {
-    fn new(infer: Infer, info: Info) -> Self {
+    fn new(models: Models) -> Self {
         let max_parallel_stream_requests = std::env::var("GRPC_MAX_PARALLEL_STREAM_REQUESTS")
             .ok()
             .and_then(|s| s.parse::<usize>().ok())
             .unwrap_or(1024);
         Self {
-            infer,
-            info,
+            models,
             max_parallel_stream_requests,
         }
     }
@@ -76,6 +125,7 @@ impl TextEmbeddingsService {
     )]
     async fn embed_pooled_inner(
         &self,
+        model: &ServedModel,
         request: EmbedRequest,
         permit: OwnedSemaphorePermit,
     ) -> Result<(EmbedResponse, ResponseMetadata), Status> {
@@ -84,7 +134,8 @@ impl TextEmbeddingsService {
 
         let compute_chars = request.inputs.chars().count();
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
-        let response = self
+        let precision = convert_precision(request.precision)?;
+        let response = model
             .infer
             .embed_pooled(
                 request.inputs,
@@ -110,12 +161,23 @@ impl TextEmbeddingsService {
         response_metadata.record_span(&span);
         response_metadata.record_metrics();
 
+        let (embeddings, quantized_embeddings) = match quantize(
+            response.results,
+            precision,
+            model.quantization_ranges.as_deref(),
+        )? {
+            QuantizedEmbedding::Float(embeddings) => (embeddings, Vec::new()),
+            quantized => (Vec::new(), quantized.to_le_bytes()),
+        };
+
         tracing::info!("Success");
 
         Ok((
             EmbedResponse {
-                embeddings: response.results,
+                embeddings,
                 metadata: Some(grpc::Metadata::from(&response_metadata)),
+                usage: Some(grpc::Usage::from(&response_metadata)),
+                quantized_embeddings,
             },
             response_metadata,
         ))
@@ -134,6 +196,7 @@ impl TextEmbeddingsService {
     )]
     async fn embed_sparse_inner(
         &self,
+        model: &ServedModel,
         request: EmbedSparseRequest,
         permit: OwnedSemaphorePermit,
     ) -> Result<(EmbedSparseResponse, ResponseMetadata), Status> {
@@ -142,7 +205,7 @@ impl TextEmbeddingsService {
 
         let compute_chars = request.inputs.chars().count();
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
-        let response = self
+        let response = model
             .infer
             .embed_sparse(
                 request.inputs,
@@ -183,6 +246,7 @@ impl TextEmbeddingsService {
             EmbedSparseResponse {
                 sparse_embeddings: sparse_values,
                 metadata: Some(grpc::Metadata::from(&response_metadata)),
+                usage: Some(grpc::Usage::from(&response_metadata)),
             },
             response_metadata,
         ))
@@ -201,6 +265,7 @@ impl TextEmbeddingsService {
     )]
     async fn embed_all_inner(
         &self,
+        model: &ServedModel,
         request: EmbedAllRequest,
         permit: OwnedSemaphorePermit,
     ) -> Result<(EmbedAllResponse, ResponseMetadata), Status> {
@@ -209,7 +274,7 @@ impl TextEmbeddingsService {
 
         let compute_chars = request.inputs.chars().count();
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
-        let response = self
+        let response = model
             .infer
             .embed_all(
                 request.inputs,
@@ -245,11 +310,13 @@ impl TextEmbeddingsService {
             EmbedAllResponse {
                 token_embeddings,
                 metadata: Some(grpc::Metadata::from(&response_metadata)),
+                usage: Some(grpc::Usage::from(&response_metadata)),
             },
             response_metadata,
         ))
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[instrument(
         skip_all,
         fields(
@@ -263,6 +330,7 @@ impl TextEmbeddingsService {
     )]
     async fn predict_inner<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
+        model: &ServedModel,
         inputs: I,
         truncate: bool,
         truncation_direction: tokenizers::TruncationDirection,
@@ -280,7 +348,7 @@ impl TextEmbeddingsService {
             EncodingInput::Ids(_) => unreachable!(),
         };
 
-        let response = self
+        let response = model
             .infer
             .predict(
                 inputs,
@@ -293,7 +361,7 @@ impl TextEmbeddingsService {
             .await
             .map_err(ErrorResponse::from)?;
 
-        let id2label = match &self.info.model_type {
+        let id2label = match &model.info.model_type {
             ModelType::Classifier(classifier) => &classifier.id2label,
             ModelType::Reranker(classifier) => &classifier.id2label,
             _ => panic!(),
@@ -336,6 +404,7 @@ impl TextEmbeddingsService {
             PredictResponse {
                 predictions,
                 metadata: Some(grpc::Metadata::from(&response_metadata)),
+                usage: Some(grpc::Usage::from(&response_metadata)),
             },
             response_metadata,
         ))
@@ -343,8 +412,9 @@ impl TextEmbeddingsService {
 
     #[instrument(skip_all)]
     async fn tokenize_inner(&self, request: EncodeRequest) -> Result<EncodeResponse, Status> {
+        let model = self.models.get(request.model())?;
         let inputs = request.inputs;
-        let (encoded_inputs, encoding) = self
+        let (encoded_inputs, encoding) = model
             .infer
             .tokenize(
                 inputs.clone(),
@@ -379,8 +449,9 @@ impl TextEmbeddingsService {
 
     #[instrument(skip_all)]
     async fn decode_inner(&self, request: DecodeRequest) -> Result<DecodeResponse, Status> {
+        let model = self.models.get(request.model())?;
         let ids = request.ids;
-        let text = self
+        let text = model
             .infer
             .decode(ids, request.skip_special_tokens)
             .await
@@ -395,11 +466,14 @@ impl TextEmbeddingsService {
         function: F,
     ) -> Result<Response<UnboundedReceiverStream<Result<Res, Status>>>, Status>
     where
-        Req: Send + 'static,
+        Req: ModelRequest + Send + 'static,
         Res: Send + 'static,
-        F: FnOnce(Req, OwnedSemaphorePermit) -> Fut + Send + Clone + 'static,
+        F: FnOnce(Req, ServedModel, OwnedSemaphorePermit) -> Fut + Send + Clone + 'static,
         Fut: Future<Output = Result<(Res, ResponseMetadata), Status>> + Send,
     {
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
+        // Streamed responses carry no metadata: the tokens of each message are charged here
+        let tenant = request.extensions().get::<Arc<Tenant>>().cloned();
         let mut request_stream = request.into_inner();
 
         // Create bounded channel to have an upper bound of spawned tasks
@@ -415,18 +489,34 @@ impl TextEmbeddingsService {
         // Background task that uses the bounded channel
         tokio::spawn(async move {
             while let Some((request, mut sender)) = internal_receiver.recv().await {
+                // Each message of the stream can target a different model
+                let model = match local.models.get(request.model()) {
+                    Ok(model) => queue_metadata.apply(&model),
+                    Err(err) => {
+                        let _ = sender.send(Err(err.into()));
+                        continue;
+                    }
+                };
+
                 // Wait on permit before spawning the task to avoid creating more tasks than needed
-                let permit = local.infer.acquire_permit().await;
+                let permit = model.infer.acquire_permit().await;
 
                 // Required for the async move below
                 let function_local = function.clone();
+                let tenant = tenant.clone();
 
                 // Create async task for this specific input
                 tokio::spawn(async move {
                     // Select on closed to cancel work if the stream was closed
                     tokio::select! {
-                    response = function_local(request, permit) => {
-                        let _ = sender.send(response.map(|(r, _m)| r));
+                    response = function_local(request, model, permit) => {
+                        let response = response.map(|(r, m)| {
+                            if let Some(tenant) = &tenant {
+                                tenant.consume_tokens(m.compute_tokens);
+                            }
+                            r
+                        });
+                        let _ = sender.send(response);
                     }
                     _ = sender.closed() => {}
                     }
@@ -571,27 +661,28 @@ impl TextEmbeddingsService {
 
 #[tonic::async_trait]
 impl grpc::info_server::Info for TextEmbeddingsService {
-    async fn info(&self, _request: Request<InfoRequest>) -> Result<Response<InfoResponse>, Status> {
-        let model_type = match self.info.model_type {
+    async fn info(&self, request: Request<InfoRequest>) -> Result<Response<InfoResponse>, Status> {
+        let info = self.models.get(request.get_ref().model.as_deref())?.info;
+        let model_type = match info.model_type {
             ModelType::Classifier(_) => grpc::ModelType::Classifier,
             ModelType::Embedding(_) => grpc::ModelType::Embedding,
             ModelType::Reranker(_) => grpc::ModelType::Reranker,
         };
 
         Ok(Response::new(InfoResponse {
-            version: self.info.version.to_string(),
-            sha: self.info.sha.map(|s| s.to_string()),
-            docker_label: self.info.docker_label.map(|s| s.to_string()),
-            model_id: self.info.model_id.clone(),
-            model_sha: self.info.model_sha.clone(),
-            model_dtype: self.info.model_dtype.clone(),
+            version: info.version.to_string(),
+            sha: info.sha.map(|s| s.to_string()),
+            docker_label: info.docker_label.map(|s| s.to_string()),
+            model_id: info.model_id.clone(),
+            model_sha: info.model_sha.clone(),
+            model_dtype: info.model_dtype.clone(),
             model_type: model_type.into(),
-            max_concurrent_requests: self.info.max_concurrent_requests as u32,
-            max_input_length: self.info.max_input_length as u32,
-            max_batch_tokens: self.info.max_batch_tokens as u32,
-            max_batch_requests: self.info.max_batch_requests.map(|v| v as u32),
-            max_client_batch_size: self.info.max_client_batch_size as u32,
-            tokenization_workers: self.info.tokenization_workers as u32,
+            max_concurrent_requests: info.max_concurrent_requests as u32,
+            max_input_length: info.max_input_length as u32,
+            max_batch_tokens: info.max_batch_tokens as u32,
+            max_batch_requests: info.max_batch_requests.map(|v| v as u32),
+            max_client_batch_size: info.max_client_batch_size as u32,
+            tokenization_workers: info.tokenization_workers as u32,
         }))
     }
 }
@@ -605,13 +696,15 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
     ) -> Result<Response<EmbedResponse>, Status> {
         metrics::counter!("te_request_count", "method" => "single").increment(1);
 
-        let permit = self
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
+        let request = request.into_inner();
+        let model = &queue_metadata.apply(&self.models.get(request.model())?);
+        let permit = model
             .infer
             .try_acquire_permit()
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
-        let (response, metadata) = self.embed_pooled_inner(request, permit).await?;
+        let (response, metadata) = self.embed_pooled_inner(model, request, permit).await?;
         let headers = HeaderMap::from(metadata);
 
         metrics::counter!("te_request_success", "method" => "single").increment(1);
@@ -632,8 +725,8 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
     ) -> Result<Response<Self::EmbedStreamStream>, Status> {
         // Clone for move below
         let clone = self.clone();
-        let function = |req: EmbedRequest, permit: OwnedSemaphorePermit| async move {
-            clone.embed_pooled_inner(req, permit).await
+        let function = |req: EmbedRequest, model: ServedModel, permit: OwnedSemaphorePermit| async move {
+            clone.embed_pooled_inner(&model, req, permit).await
         };
 
         self.stream(request, function).await
@@ -646,13 +739,15 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         let counter = metrics::counter!("te_request_count", "method" => "single");
         counter.increment(1);
 
-        let permit = self
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
+        let request = request.into_inner();
+        let model = &queue_metadata.apply(&self.models.get(request.model())?);
+        let permit = model
             .infer
             .try_acquire_permit()
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
-        let (response, metadata) = self.embed_sparse_inner(request, permit).await?;
+        let (response, metadata) = self.embed_sparse_inner(model, request, permit).await?;
         let headers = HeaderMap::from(metadata);
 
         let counter = metrics::counter!("te_request_count", "method" => "single");
@@ -673,8 +768,10 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
     ) -> Result<Response<Self::EmbedSparseStreamStream>, Status> {
         // Clone for move below
         let clone = self.clone();
-        let function = |req: EmbedSparseRequest, permit: OwnedSemaphorePermit| async move {
-            clone.embed_sparse_inner(req, permit).await
+        let function = |req: EmbedSparseRequest,
+                        model: ServedModel,
+                        permit: OwnedSemaphorePermit| async move {
+            clone.embed_sparse_inner(&model, req, permit).await
         };
 
         self.stream(request, function).await
@@ -688,13 +785,15 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         let counter = metrics::counter!("te_request_count", "method" => "single");
         counter.increment(1);
 
-        let permit = self
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
+        let request = request.into_inner();
+        let model = &queue_metadata.apply(&self.models.get(request.model())?);
+        let permit = model
             .infer
             .try_acquire_permit()
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
-        let (response, metadata) = self.embed_all_inner(request, permit).await?;
+        let (response, metadata) = self.embed_all_inner(model, request, permit).await?;
         let headers = HeaderMap::from(metadata);
 
         let counter = metrics::counter!("te_request_count", "method" => "single");
@@ -716,8 +815,8 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
     ) -> Result<Response<Self::EmbedAllStreamStream>, Status> {
         // Clone for move below
         let clone = self.clone();
-        let function = |req: EmbedAllRequest, permit: OwnedSemaphorePermit| async move {
-            clone.embed_all_inner(req, permit).await
+        let function = |req: EmbedAllRequest, model: ServedModel, permit: OwnedSemaphorePermit| async move {
+            clone.embed_all_inner(&model, req, permit).await
         };
 
         self.stream(request, function).await
@@ -733,15 +832,18 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
     ) -> Result<Response<PredictResponse>, Status> {
         metrics::counter!("te_request_count", "method" => "single").increment(1);
 
-        let permit = self
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
+        let request = request.into_inner();
+        let model = &queue_metadata.apply(&self.models.get(request.model())?);
+        let permit = model
             .infer
             .try_acquire_permit()
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
         let (response, metadata) = self
             .predict_inner(
+                model,
                 request.inputs,
                 request.truncate,
                 truncation_direction,
@@ -767,6 +869,7 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
     ) -> Result<Response<PredictResponse>, Status> {
         metrics::counter!("te_request_count", "method" => "single").increment(1);
 
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
         let request = request.into_inner();
 
         let mut inputs = request.inputs;
@@ -785,7 +888,8 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
             }
         };
 
-        let permit = self
+        let model = &queue_metadata.apply(&self.models.get(request.model())?);
+        let permit = model
             .infer
             .try_acquire_permit()
             .map_err(ErrorResponse::from)?;
@@ -793,6 +897,7 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
         let (response, metadata) = self
             .predict_inner(
+                model,
                 inputs,
                 request.truncate,
                 truncation_direction,
@@ -821,10 +926,11 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
     ) -> Result<Response<Self::PredictStreamStream>, Status> {
         // Clone for move below
         let clone = self.clone();
-        let function = |req: PredictRequest, permit: OwnedSemaphorePermit| async move {
+        let function = |req: PredictRequest, model: ServedModel, permit: OwnedSemaphorePermit| async move {
             let truncation_direction = convert_truncation_direction(req.truncation_direction);
             clone
                 .predict_inner(
+                    &model,
                     req.inputs,
                     req.truncate,
                     truncation_direction,
@@ -846,7 +952,9 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
     ) -> Result<Response<Self::PredictPairStreamStream>, Status> {
         // Clone for move below
         let clone = self.clone();
-        let function = |req: PredictPairRequest, permit: OwnedSemaphorePermit| async move {
+        let function = |req: PredictPairRequest,
+                        model: ServedModel,
+                        permit: OwnedSemaphorePermit| async move {
             let mut inputs = req.inputs;
 
             let inputs = match inputs.len() {
@@ -866,6 +974,7 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
             let truncation_direction = convert_truncation_direction(req.truncation_direction);
             clone
                 .predict_inner(
+                    &model,
                     inputs,
                     req.truncate,
                     truncation_direction,
@@ -900,21 +1009,23 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let span = Span::current();
         let start_time = Instant::now();
 
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
         let request = request.into_inner();
+        let model = &queue_metadata.apply(&self.models.get(request.model())?);
 
         if request.texts.is_empty() {
             let message = "`texts` cannot be empty".to_string();
             tracing::error!("{message}");
             let err = ErrorResponse {
                 error: message,
-                error_type: ErrorType::Validation,
+                error_type: ErrorType::Empty,
             };
             let counter = metrics::counter!("te_request_failure", "err" => "validation");
             counter.increment(1);
             Err(err)?;
         }
 
-        match &self.info.model_type {
+        match &model.info.model_type {
             ModelType::Classifier(_) => {
                 let counter = metrics::counter!("te_request_failure", "err" => "model_type");
                 counter.increment(1);
@@ -934,8 +1045,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
 
         // Closure for rerank
         let rerank_inner =
-            move |query: String,
-                  text: String,
+            move |input: EncodingInput,
                   truncate: bool,
                   truncation_direction: tokenizers::TruncationDirection,
                   raw_scores: bool,
@@ -945,7 +1055,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
 
                 let response = infer
                     .predict(
-                        (query, text),
+                        input,
                         truncate,
                         truncation_direction,
                         raw_scores,
@@ -970,10 +1080,10 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         counter.increment(1);
 
         let batch_size = request.texts.len();
-        if batch_size > self.info.max_client_batch_size {
+        if batch_size > model.info.max_client_batch_size {
             let message = format!(
                 "batch size {batch_size} > maximum allowed batch size {}",
-                self.info.max_client_batch_size
+                model.info.max_client_batch_size
             );
             tracing::error!("{message}");
             let err = ErrorResponse {
@@ -997,11 +1107,18 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
 
         for text in &request.texts {
             total_compute_chars += text.chars().count();
-            let local_infer = self.infer.clone();
+            let local_infer = model.infer.clone();
             let local_batch_counter = batch_counter.clone();
-            futures.push(rerank_inner(
+            // Apply the prompt template of Qwen3 rerankers
+            let input = rerank_input(
+                &model.info.model_id,
+                request.use_template,
                 request.query.clone(),
                 text.clone(),
+                request.instruction.as_deref(),
+            );
+            futures.push(rerank_inner(
+                input,
                 request.truncate,
                 truncation_direction,
                 request.raw_scores,
@@ -1070,6 +1187,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let message = RerankResponse {
             ranks,
             metadata: Some(grpc::Metadata::from(&response_metadata)),
+            usage: Some(grpc::Usage::from(&response_metadata)),
         };
 
         let headers = HeaderMap::from(response_metadata);
@@ -1101,8 +1219,20 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let span = Span::current();
         let start_time = Instant::now();
 
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
+        let mut request_stream = request.into_inner();
+
+        // The first message selects the model used for the whole stream
+        let first_request = request_stream.next().await.transpose()?;
+        let model = queue_metadata.apply(
+            self.models
+                .get(first_request.as_ref().and_then(|r| r.model()))?,
+        );
+        let mut request_stream =
+            tokio_stream::iter(first_request.into_iter().map(Ok)).chain(request_stream);
+
         // Check model type
-        match &self.info.model_type {
+        match &model.info.model_type {
             ModelType::Classifier(_) => {
                 let counter = metrics::counter!("te_request_failure", "err" => "model_type");
                 counter.increment(1);
@@ -1123,7 +1253,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         // Closure for rerank
         let rerank_inner =
             move |index: usize,
-                  query: String,
+                  input: EncodingInput,
                   text: String,
                   truncate: bool,
                   truncation_direction: tokenizers::TruncationDirection,
@@ -1133,7 +1263,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                   batch_counter: Option<Arc<AtomicUsize>>| async move {
                 let response = infer
                     .predict(
-                        (query, text.clone()),
+                        input,
                         truncate,
                         truncation_direction,
                         raw_scores,
@@ -1159,14 +1289,12 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let counter = metrics::counter!("te_request_count", "method" => "batch");
         counter.increment(1);
 
-        let mut request_stream = request.into_inner();
-
         // Create bounded channel to have an upper bound of spawned tasks
         // We will have at most `max_parallel_stream_requests` messages from this stream in the queue
         let (rerank_sender, mut rerank_receiver) = mpsc::channel::<(
             (
                 usize,
-                String,
+                EncodingInput,
                 String,
                 bool,
                 tokenizers::TruncationDirection,
@@ -1178,12 +1306,12 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         )>(self.max_parallel_stream_requests);
 
         // Required for the async move below
-        let local_infer = self.infer.clone();
+        let local_infer = model.infer.clone();
 
         // Background task that uses the bounded channel
         tokio::spawn(async move {
             while let Some((
-                (index, query, text, truncate, truncation_direction, raw_scores),
+                (index, input, text, truncate, truncation_direction, raw_scores),
                 mut sender,
             )) = rerank_receiver.recv().await
             {
@@ -1197,7 +1325,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                 tokio::spawn(async move {
                     // Select on closed to cancel work if the stream was closed
                     tokio::select! {
-                    result = rerank_inner(index, query, text, truncate, truncation_direction, raw_scores, task_infer, permit, None) => {
+                    result = rerank_inner(index, input, text, truncate, truncation_direction, raw_scores, task_infer, permit, None) => {
                         let _ = sender.send(result);
                     }
                     _ = sender.closed() => {}
@@ -1236,12 +1364,20 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
             total_compute_chars += request.query.chars().count();
             total_compute_chars += request.text.chars().count();
 
+            // Apply the prompt template of Qwen3 rerankers
+            let input = rerank_input(
+                &model.info.model_id,
+                request.use_template,
+                request.query,
+                request.text.clone(),
+                request.instruction.as_deref(),
+            );
             let truncation_direction = convert_truncation_direction(request.truncation_direction);
             rerank_sender
                 .send((
                     (
                         index,
-                        request.query,
+                        input,
                         request.text,
                         request.truncate,
                         truncation_direction,
@@ -1334,6 +1470,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let message = RerankResponse {
             ranks,
             metadata: Some(grpc::Metadata::from(&response_metadata)),
+            usage: Some(grpc::Usage::from(&response_metadata)),
         };
 
         let headers = HeaderMap::from(response_metadata);
@@ -1395,16 +1532,197 @@ impl grpc::tokenize_server::Tokenize for TextEmbeddingsService {
     }
 }
 
+#[tonic::async_trait]
+impl grpc::similarity_server::Similarity for TextEmbeddingsService {
+    #[instrument(
+        skip_all,
+        fields(
+            compute_chars,
+            compute_tokens,
+            total_time,
+            tokenization_time,
+            queue_time,
+            inference_time,
+        )
+    )]
+    async fn similarity(
+        &self,
+        request: Request<SimilarityRequest>,
+    ) -> Result<Response<SimilarityResponse>, Status> {
+        let span = Span::current();
+        let start_time = Instant::now();
+
+        let queue_metadata = QueueMetadata::parse(request.metadata())?;
+        let request = request.into_inner();
+        let model = &queue_metadata.apply(&self.models.get(request.model())?);
+
+        let counter = metrics::counter!("te_request_count", "method" => "batch");
+        counter.increment(1);
+
+        let metric = convert_similarity_metric(request.metric)?;
+        // The sources are compared against each other if no sentences are given
+        let sentences = (!request.sentences.is_empty()).then_some(request.sentences);
+        let inputs = SimilarityInputs::new(
+            request.source_sentences,
+            sentences,
+            model.info.max_client_batch_size,
+        )?;
+
+        let batch_size = inputs.texts.len();
+        let batch_counter = if batch_size == 1 {
+            None
+        } else {
+            Some(Arc::new(AtomicUsize::new(batch_size)))
+        };
+        let truncation_direction = convert_truncation_direction(request.truncation_direction);
+        let mut futures = Vec::with_capacity(batch_size);
+        let mut total_compute_chars = 0;
+
+        for text in &inputs.texts {
+            total_compute_chars += text.chars().count();
+            let permit = model
+                .infer
+                .try_acquire_permit()
+                .map_err(ErrorResponse::from)?;
+            futures.push(model.infer.embed_pooled(
+                text.clone(),
+                request.truncate,
+                truncation_direction,
+                request.prompt_name.clone(),
+                false,
+                None,
+                permit,
+                batch_counter.clone(),
+            ))
+        }
+        let results = join_all(futures)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ErrorResponse::from)?;
+
+        let mut embeddings = Vec::with_capacity(batch_size);
+        let mut total_tokenization_time = 0;
+        let mut total_queue_time = 0;
+        let mut total_inference_time = 0;
+        let mut total_compute_tokens = 0;
+
+        for r in results {
+            total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+            total_queue_time += r.metadata.queue.as_nanos() as u64;
+            total_inference_time += r.metadata.inference.as_nanos() as u64;
+            total_compute_tokens += r.metadata.prompt_tokens;
+            embeddings.push(r.results);
+        }
+
+        let scores = inputs
+            .scores(metric, &embeddings)
+            .into_iter()
+            .map(|scores| SimilarityScores { scores })
+            .collect();
+
+        let batch_size = batch_size as u64;
+
+        let counter = metrics::counter!("te_request_success", "method" => "batch");
+        counter.increment(1);
+
+        let response_metadata = ResponseMetadata::new(
+            total_compute_chars,
+            total_compute_tokens,
+            start_time,
+            Duration::from_nanos(total_tokenization_time / batch_size),
+            Duration::from_nanos(total_queue_time / batch_size),
+            Duration::from_nanos(total_inference_time / batch_size),
+        );
+        response_metadata.record_span(&span);
+        response_metadata.record_metrics();
+
+        let message = SimilarityResponse {
+            scores,
+            metadata: Some(grpc::Metadata::from(&response_metadata)),
+            usage: Some(grpc::Usage::from(&response_metadata)),
+        };
+
+        let headers = HeaderMap::from(response_metadata);
+
+        tracing::info!("Success");
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            message,
+            Extensions::default(),
+        ))
+    }
+}
+
+#[tonic::async_trait]
+impl grpc::vertex_server::Vertex for TextEmbeddingsService {
+    #[instrument(skip_all)]
+    async fn predict(
+        &self,
+        request: Request<VertexRequest>,
+    ) -> Result<Response<VertexResponse>, Status> {
+        use grpc::vertex_instance::Instance;
+        use grpc::vertex_prediction::Prediction as Output;
+
+        // Each instance is a request of the `Embed`, `Predict` or `Rerank` services, with the
+        // metadata of the Vertex request
+        let (metadata, _, request) = request.into_parts();
+        let futures = request.instances.into_iter().map(|instance| {
+            let metadata = metadata.clone();
+            async move {
+                let output = match instance.instance {
+                    Some(Instance::Embed(req)) => {
+                        let req = Request::from_parts(metadata, Extensions::default(), req);
+                        let response = grpc::embed_server::Embed::embed(self, req).await?;
+                        Output::Embed(response.into_inner())
+                    }
+                    Some(Instance::EmbedSparse(req)) => {
+                        let req = Request::from_parts(metadata, Extensions::default(), req);
+                        let response = grpc::embed_server::Embed::embed_sparse(self, req).await?;
+                        Output::EmbedSparse(response.into_inner())
+                    }
+                    Some(Instance::Predict(req)) => {
+                        let req = Request::from_parts(metadata, Extensions::default(), req);
+                        let response = grpc::predict_server::Predict::predict(self, req).await?;
+                        Output::Predict(response.into_inner())
+                    }
+                    Some(Instance::Rerank(req)) => {
+                        let req = Request::from_parts(metadata, Extensions::default(), req);
+                        let response = grpc::rerank_server::Rerank::rerank(self, req).await?;
+                        Output::Rerank(response.into_inner())
+                    }
+                    None => {
+                        let counter =
+                            metrics::counter!("te_request_failure", "err" => "validation");
+                        counter.increment(1);
+                        return Err(Status::invalid_argument("`instance` cannot be empty"));
+                    }
+                };
+                Ok(VertexPrediction {
+                    prediction: Some(output),
+                })
+            }
+        });
+
+        let predictions = join_all(futures)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<VertexPrediction>, Status>>()?;
+
+        Ok(Response::new(VertexResponse { predictions }))
+    }
+}
+
 pub async fn run(
-    infer: Infer,
-    info: Info,
-    addr: SocketAddr,
-    prom_builder: PrometheusBuilder,
+    models: Models,
+    addr: Option<SocketAddr>,
+    uds: Option<UnixSocket>,
     api_key: Option<String>,
+    api_keys: Option<ApiKeys>,
+    lifecycle: Lifecycle,
+    tls: Option<TlsFiles>,
 ) -> Result<(), anyhow::Error> {
-    prom_builder.install()?;
-    tracing::info!("Serving Prometheus metrics: 0.0.0.0:9000");
-
     // Liveness service
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     // Info is always serving
@@ -1415,8 +1733,11 @@ pub async fn run(
     health_reporter
         .set_serving::<grpc::TokenizeServer<TextEmbeddingsService>>()
         .await;
-    // Set all other services to not serving
-    // Their health will be updated in the task below
+    // Set the server and all other services to not serving
+    // Their health will be updated in the tasks below
+    health_reporter
+        .set_service_status("", ServingStatus::NotServing)
+        .await;
     health_reporter
         .set_not_serving::<grpc::EmbedServer<TextEmbeddingsService>>()
         .await;
@@ -1426,123 +1747,515 @@ pub async fn run(
     health_reporter
         .set_not_serving::<grpc::PredictServer<TextEmbeddingsService>>()
         .await;
+    health_reporter
+        .set_not_serving::<grpc::SimilarityServer<TextEmbeddingsService>>()
+        .await;
+    health_reporter
+        .set_not_serving::<grpc::VertexServer<TextEmbeddingsService>>()
+        .await;
 
-    // Backend health watcher
-    let mut health_watcher = infer.health_watcher();
+    // Last known backend health of each served model
+    let models_health = Arc::new(std::sync::Mutex::new(vec![false; models.len()]));
 
-    // Clone model_type and move it to the task
-    let health_watcher_model_type = info.model_type.clone();
+    for index in 0..models.len() {
+        // Required for the async move below
+        let mut health_reporter = health_reporter.clone();
+        let models_health = models_health.clone();
+        let models = models.clone();
+        let lifecycle = lifecycle.clone();
 
-    // Update services health
-    tokio::spawn(async move {
-        while health_watcher.changed().await.is_ok() {
-            let health = *health_watcher.borrow_and_update();
-            let status = match health {
-                true => ServingStatus::Serving,
-                false => ServingStatus::NotServing,
-            };
+        // Update services health, starting from the current health of the backend
+        tokio::spawn(async move {
+            // Models can be swapped while serving: the backend health watcher of the model at
+            // `index` is subscribed again after each swap
+            let mut swapped = models.swapped();
+            'models: loop {
+                swapped.borrow_and_update();
+                let Some(model) = models.iter().nth(index) else {
+                    break;
+                };
+                let mut health_watcher = model.infer.health_watcher();
+                // Holding the model would keep its backend running after it is replaced
+                drop(model);
+
+                loop {
+                    let health = *health_watcher.borrow_and_update();
+
+                    let statuses = {
+                        let model_types: Vec<ModelType> =
+                            models.iter().map(|m| m.info.model_type).collect();
+                        let mut models_health = models_health.lock().unwrap();
+                        models_health[index] = health;
+                        services_status(&model_types, &models_health)
+                    };
+                    if lifecycle.is_draining() {
+                        break 'models;
+                    }
 
-            // Match on model type and set the health of the correct service(s)
-            //
-            // If Reranker, we have both a predict and rerank service
-            //
-            // This logic hints back to the user that if they try using the wrong service
-            // given the model type, it will always return an error.
-            //
-            // For example if the model type is `Embedding`, sending requests to `Rerank` will
-            // always return an `UNIMPLEMENTED` Status and both the `Rerank` and `Predict` services
-            // will have a `NOT_SERVING` ServingStatus.
-            match health_watcher_model_type {
-                ModelType::Classifier(_) => {
-                    health_reporter
-                        .set_service_status(
-                            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await
-                }
-                ModelType::Embedding(_) => {
-                    health_reporter
-                        .set_service_status(
-                            <grpc::EmbedServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await
-                }
-                ModelType::Reranker(_) => {
-                    // Reranker has both a predict and rerank service
-                    health_reporter
-                        .set_service_status(
-                            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await;
-                    health_reporter
-                        .set_service_status(
-                            <grpc::RerankServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await;
+                    for (service_name, status) in statuses {
+                        health_reporter
+                            .set_service_status(service_name, status)
+                            .await;
+                    }
+
+                    // `Watch` streams receive the new statuses of the services
+                    tokio::select! {
+                        changed = health_watcher.changed() => {
+                            // The backend of a replaced model stops once its last request is done
+                            if changed.is_err() {
+                                if swapped.changed().await.is_err() {
+                                    break 'models;
+                                }
+                                continue 'models;
+                            }
+                        }
+                        changed = swapped.changed() => {
+                            if changed.is_err() {
+                                break 'models;
+                            }
+                            continue 'models;
+                        }
+                    }
                 }
-            };
-        }
-    });
+            }
+        });
+    }
+
+    // Every service stops serving when draining
+    {
+        let mut health_reporter = health_reporter.clone();
+        let lifecycle = lifecycle.clone();
+        tokio::spawn(async move {
+            lifecycle.draining().await;
+            health_reporter
+                .set_service_status("", ServingStatus::NotServing)
+                .await;
+            health_reporter
+                .set_not_serving::<grpc::InfoServer<TextEmbeddingsService>>()
+                .await;
+            health_reporter
+                .set_not_serving::<grpc::TokenizeServer<TextEmbeddingsService>>()
+                .await;
+            health_reporter
+                .set_not_serving::<grpc::EmbedServer<TextEmbeddingsService>>()
+                .await;
+            health_reporter
+                .set_not_serving::<grpc::RerankServer<TextEmbeddingsService>>()
+                .await;
+            health_reporter
+                .set_not_serving::<grpc::PredictServer<TextEmbeddingsService>>()
+                .await;
+            health_reporter
+                .set_not_serving::<grpc::SimilarityServer<TextEmbeddingsService>>()
+                .await;
+            health_reporter
+                .set_not_serving::<grpc::VertexServer<TextEmbeddingsService>>()
+                .await;
+        });
+    }
 
-    // gRPC reflection
+    // gRPC reflection of the `tei.v1`, health and reflection services
     let file_descriptor_set: &[u8] = tonic::include_file_descriptor_set!("descriptor");
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(file_descriptor_set)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_reflection::pb::FILE_DESCRIPTOR_SET)
         .build()?;
 
     // Main service
-    let service = TextEmbeddingsService::new(infer, info);
+    let service = TextEmbeddingsService::new(models);
+
+    // Per tenant authorization and rate limits. Client certificates identify the tenant when
+    // there are no API keys.
+    let client_auth = tls.as_ref().is_some_and(|tls| tls.client_ca.is_some());
+    let tenant_layer = tower::util::option_layer(
+        (api_keys.is_some() || client_auth).then_some(TenantLayer { api_keys }),
+    );
+    let drain_layer = DrainLayer {
+        lifecycle: lifecycle.clone(),
+    };
 
-    // Create gRPC server
-    let server = if let Some(api_key) = api_key {
+    // Leak to allow FnMut
+    let api_key: Option<&'static str> = api_key.map(|api_key| {
         let mut prefix = "Bearer ".to_string();
         prefix.push_str(&api_key);
+        &*prefix.leak()
+    });
+
+    // Create gRPC server. The TCP listener and the Unix socket each need their own router.
+    let router = || {
+        let drain_layer = drain_layer.clone();
+        let tenant_layer = tenant_layer.clone();
+        let health_service = health_service.clone();
+        let reflection_service = reflection_service.clone();
+        let service = service.clone();
+
+        if let Some(api_key) = api_key {
+            let auth = move |req: Request<()>| -> Result<Request<()>, Status> {
+                match req.metadata().get("authorization") {
+                    Some(t) if t == api_key => Ok(req),
+                    _ => Err(Status::unauthenticated("No valid auth token")),
+                }
+            };
 
-        // Leak to allow FnMut
-        let api_key: &'static str = prefix.leak();
+            Server::builder()
+                .layer(drain_layer)
+                .layer(tenant_layer)
+                .add_service(health_service)
+                .add_service(reflection_service)
+                .add_service(grpc::InfoServer::with_interceptor(service.clone(), auth))
+                .add_service(grpc::TokenizeServer::with_interceptor(
+                    service.clone(),
+                    auth,
+                ))
+                .add_service(grpc::EmbedServer::with_interceptor(service.clone(), auth))
+                .add_service(grpc::PredictServer::with_interceptor(service.clone(), auth))
+                .add_service(grpc::RerankServer::with_interceptor(service.clone(), auth))
+                .add_service(grpc::SimilarityServer::with_interceptor(
+                    service.clone(),
+                    auth,
+                ))
+                .add_service(grpc::VertexServer::with_interceptor(service, auth))
+        } else {
+            Server::builder()
+                .layer(drain_layer)
+                .layer(tenant_layer)
+                .add_service(health_service)
+                .add_service(reflection_service)
+                .add_service(grpc::InfoServer::new(service.clone()))
+                .add_service(grpc::TokenizeServer::new(service.clone()))
+                .add_service(grpc::EmbedServer::new(service.clone()))
+                .add_service(grpc::PredictServer::new(service.clone()))
+                .add_service(grpc::RerankServer::new(service.clone()))
+                .add_service(grpc::SimilarityServer::new(service.clone()))
+                .add_service(grpc::VertexServer::new(service))
+        }
+    };
 
-        let auth = move |req: Request<()>| -> Result<Request<()>, Status> {
-            match req.metadata().get("authorization") {
-                Some(t) if t == api_key => Ok(req),
-                _ => Err(Status::unauthenticated("No valid auth token")),
+    let mut servers: Vec<BoxFuture<Result<(), tonic::transport::Error>>> = Vec::with_capacity(2);
+    if let Some(addr) = addr {
+        match tls {
+            Some(tls) => {
+                let acceptor = TlsAcceptor::new(tls, &[b"h2"])?;
+                let listener = tokio::net::TcpListener::bind(&addr)
+                    .await
+                    .context(format!("Could not bind TCP Listener on {addr}"))?;
+                let incoming = UnboundedReceiverStream::new(acceptor.incoming(listener))
+                    .map(Ok::<_, std::io::Error>);
+                tracing::info!("Starting gRPC server with TLS: {}", &addr);
+                servers.push(Box::pin(
+                    router().serve_with_incoming_shutdown(incoming, lifecycle.clone().drained()),
+                ));
             }
-        };
+            None => {
+                tracing::info!("Starting gRPC server: {}", &addr);
+                servers.push(Box::pin(
+                    router().serve_with_shutdown(addr, lifecycle.clone().drained()),
+                ));
+            }
+        }
+    }
 
-        Server::builder()
-            .add_service(health_service)
-            .add_service(reflection_service)
-            .add_service(grpc::InfoServer::with_interceptor(service.clone(), auth))
-            .add_service(grpc::TokenizeServer::with_interceptor(
-                service.clone(),
-                auth,
-            ))
-            .add_service(grpc::EmbedServer::with_interceptor(service.clone(), auth))
-            .add_service(grpc::PredictServer::with_interceptor(service.clone(), auth))
-            .add_service(grpc::RerankServer::with_interceptor(service, auth))
-            .serve_with_shutdown(addr, shutdown::shutdown_signal())
-    } else {
-        Server::builder()
-            .add_service(health_service)
-            .add_service(reflection_service)
-            .add_service(grpc::InfoServer::new(service.clone()))
-            .add_service(grpc::TokenizeServer::new(service.clone()))
-            .add_service(grpc::EmbedServer::new(service.clone()))
-            .add_service(grpc::PredictServer::new(service.clone()))
-            .add_service(grpc::RerankServer::new(service))
-            .serve_with_shutdown(addr, shutdown::shutdown_signal())
-    };
+    // The socket file is removed once the server stops
+    #[cfg(unix)]
+    let mut socket_file = None;
+    #[cfg(unix)]
+    if let Some(uds) = uds {
+        let (listener, file) = uds.bind()?;
+        socket_file = Some(file);
+        let incoming = tokio_stream::wrappers::UnixListenerStream::new(listener);
+        tracing::info!("Starting gRPC server: {}", uds.path.display());
+        servers.push(Box::pin(
+            router().serve_with_incoming_shutdown(incoming, lifecycle.clone().drained()),
+        ));
+    }
+    // `--listen-uds` is rejected on other platforms
+    #[cfg(not(unix))]
+    let _ = uds;
 
-    tracing::info!("Starting gRPC server: {}", &addr);
     tracing::info!("Ready");
-    server.await?;
+    tokio::select! {
+        result = futures::future::try_join_all(servers) => {
+            result?;
+        }
+        _ = lifecycle.deadline() => {},
+    }
+    #[cfg(unix)]
+    drop(socket_file);
 
     Ok(())
 }
 
+/// Authorize the requests with the API keys file and charge their tokens to the tenant
+#[derive(Debug, Clone)]
+struct TenantLayer {
+    api_keys: Option<ApiKeys>,
+}
+
+impl<S> Layer<S> for TenantLayer {
+    type Service = TenantService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantService {
+            inner,
+            api_keys: self.api_keys.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TenantService<S> {
+    inner: S,
+    api_keys: Option<ApiKeys>,
+}
+
+impl<S, B> Service<http::Request<B>> for TenantService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        // Health and reflection services are public
+        let path = request.uri().path();
+        if path.starts_with("/grpc.") {
+            return Box::pin(self.inner.call(request));
+        }
+
+        let tenant = match self.api_keys.clone() {
+            Some(api_keys) => {
+                let authorization = request
+                    .headers()
+                    .get("authorization")
+                    .and_then(|v| v.to_str().ok());
+                match api_keys.authorize(authorization, path) {
+                    Ok(tenant) => tenant,
+                    Err(err) => {
+                        let status = match err {
+                            AuthError::Unauthorized => {
+                                Status::unauthenticated("No valid auth token")
+                            }
+                            AuthError::Forbidden => {
+                                Status::permission_denied(format!("API key cannot call `{path}`"))
+                            }
+                            AuthError::RateLimited(retry_after) => {
+                                let mut status = Status::resource_exhausted("Rate limit exceeded");
+                                status
+                                    .metadata_mut()
+                                    .insert("retry-after", retry_after_secs(retry_after).into());
+                                status
+                            }
+                        };
+                        return Box::pin(async move { Ok(status.to_http()) });
+                    }
+                }
+            }
+            // Without API keys, the tenant is the client certificate of the connection
+            None => {
+                let client_identity = request
+                    .extensions()
+                    .get::<TlsConnectInfo>()
+                    .and_then(|info| info.client_identity.clone());
+                match client_identity {
+                    Some(ClientIdentity(name)) => Tenant::identified(name),
+                    None => return Box::pin(self.inner.call(request)),
+                }
+            }
+        };
+
+        // Streaming handlers charge the tokens of each message themselves
+        request.extensions_mut().insert(tenant.clone());
+
+        // The service that was ready is the one that must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let span = tracing::info_span!("tenant", tenant = %tenant.name);
+        Box::pin(async move {
+            let response = inner.call(request).instrument(span).await?;
+
+            // Unary handlers report the number of tokens they computed in their metadata
+            let compute_tokens = response
+                .headers()
+                .get("x-compute-tokens")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok());
+            if let Some(compute_tokens) = compute_tokens {
+                tenant.consume_tokens(compute_tokens);
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// Reject new requests while draining and track the in flight ones
+#[derive(Debug, Clone)]
+struct DrainLayer {
+    lifecycle: Lifecycle,
+}
+
+impl<S> Layer<S> for DrainLayer {
+    type Service = DrainService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DrainService {
+            inner,
+            lifecycle: self.lifecycle.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DrainService<S> {
+    inner: S,
+    lifecycle: Lifecycle,
+}
+
+impl<S, B> Service<http::Request<B>> for DrainService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        // Health and reflection services keep answering while draining
+        if request.uri().path().starts_with("/grpc.") {
+            return Box::pin(self.inner.call(request));
+        }
+
+        let Some(in_flight) = self.lifecycle.start_request() else {
+            metrics::counter!("te_request_failure", "err" => "draining").increment(1);
+            let status = Status::from(ErrorResponse {
+                error: "The router is draining".to_string(),
+                error_type: ErrorType::Overloaded,
+            });
+            return Box::pin(async move { Ok(status.to_http()) });
+        };
+
+        // The service that was ready is the one that must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            // Streamed responses are still in flight once the handler returned
+            let response = inner.call(request).await?;
+            Ok(response.map(|body| {
+                BoxBody::new(InFlightBody {
+                    body,
+                    _in_flight: in_flight,
+                })
+            }))
+        })
+    }
+}
+
+/// Response body that keeps its request in flight until it is sent or dropped
+struct InFlightBody {
+    body: BoxBody,
+    _in_flight: InFlight,
+}
+
+impl HttpBody for InFlightBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Status>>> {
+        Pin::new(&mut self.body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Status>> {
+        Pin::new(&mut self.body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+}
+
+/// Compute the health of the model-dependent services
+///
+/// A service is serving as long as one healthy model can answer its requests.
+/// If Reranker, we have both a predict and rerank service.
+///
+/// This logic hints back to the user that if they try using the wrong service
+/// given the model types, it will always return an error.
+///
+/// For example if all models are of type `Embedding`, sending requests to `Rerank` will
+/// always return an `UNIMPLEMENTED` Status and both the `Rerank` and `Predict` services
+/// will have a `NOT_SERVING` ServingStatus.
+///
+/// The overall status of the server, the empty service name used by the Kubernetes gRPC probes,
+/// is `SERVING` when every model is healthy, like the HTTP `/health` route.
+fn services_status(
+    model_types: &[ModelType],
+    models_health: &[bool],
+) -> [(&'static str, ServingStatus); 6] {
+    let mut embed = false;
+    let mut predict = false;
+    let mut rerank = false;
+
+    for (model_type, health) in model_types.iter().zip(models_health) {
+        match model_type {
+            ModelType::Classifier(_) => predict |= *health,
+            ModelType::Embedding(_) => embed |= *health,
+            ModelType::Reranker(_) => {
+                predict |= *health;
+                rerank |= *health;
+            }
+        }
+    }
+
+    let status = |serving: bool| match serving {
+        true => ServingStatus::Serving,
+        false => ServingStatus::NotServing,
+    };
+
+    [
+        ("", status(models_health.iter().all(|health| *health))),
+        (
+            <grpc::EmbedServer<TextEmbeddingsService>>::NAME,
+            status(embed),
+        ),
+        (
+            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
+            status(predict),
+        ),
+        (
+            <grpc::RerankServer<TextEmbeddingsService>>::NAME,
+            status(rerank),
+        ),
+        (
+            <grpc::SimilarityServer<TextEmbeddingsService>>::NAME,
+            status(embed),
+        ),
+        (
+            <grpc::VertexServer<TextEmbeddingsService>>::NAME,
+            status(embed || predict),
+        ),
+    ]
+}
+
 impl From<ErrorResponse> for Status {
     fn from(value: ErrorResponse) -> Self {
         let code = match value.error_type {
@@ -1552,15 +2265,190 @@ impl From<ErrorResponse> for Status {
             ErrorType::Validation => Code::InvalidArgument,
             ErrorType::Tokenizer => Code::FailedPrecondition,
             ErrorType::Empty => Code::InvalidArgument,
+            ErrorType::NotFound => Code::NotFound,
+            ErrorType::Timeout => Code::DeadlineExceeded,
+            ErrorType::Unauthorized => Code::Unauthenticated,
+            ErrorType::Forbidden => Code::PermissionDenied,
+            ErrorType::Internal => Code::Internal,
         };
 
         Status::new(code, value.error)
     }
 }
 
+/// Queue options of a request set by its metadata
+#[derive(Debug, Clone, Copy)]
+struct QueueMetadata {
+    /// `x-priority` metadata
+    priority: Priority,
+    /// `x-request-timeout` metadata, in milliseconds
+    timeout: Option<Duration>,
+}
+
+impl QueueMetadata {
+    fn parse(metadata: &MetadataMap) -> Result<Self, Status> {
+        fn parse<T: std::str::FromStr>(
+            metadata: &MetadataMap,
+            name: &str,
+        ) -> Result<Option<T>, Status> {
+            let Some(value) = metadata.get(name) else {
+                return Ok(None);
+            };
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .map(Some)
+                .ok_or_else(|| {
+                    let message = format!("invalid `{name}` metadata: {value:?}");
+                    tracing::error!("{message}");
+                    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                    counter.increment(1);
+                    Status::new(Code::InvalidArgument, message)
+                })
+        }
+
+        let timeout = match parse::<u64>(metadata, "x-request-timeout")? {
+            Some(0) => {
+                let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                counter.increment(1);
+                return Err(Status::new(
+                    Code::InvalidArgument,
+                    "`x-request-timeout` must be > 0",
+                ));
+            }
+            Some(timeout) => {
+                let duration = Duration::from_millis(timeout);
+                if Instant::now().checked_add(duration).is_none() {
+                    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                    counter.increment(1);
+                    return Err(Status::new(
+                        Code::InvalidArgument,
+                        format!("`x-request-timeout` {timeout} is too large"),
+                    ));
+                }
+                Some(duration)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            priority: parse(metadata, "x-priority")?.unwrap_or_default(),
+            timeout,
+        })
+    }
+
+    /// Handle to the model queueing the request with its options
+    fn apply(&self, model: &ServedModel) -> ServedModel {
+        let deadline = self
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        ServedModel {
+            infer: model
+                .infer
+                .with_priority(self.priority)
+                .with_deadline(deadline),
+            ..model.clone()
+        }
+    }
+}
+
+fn convert_precision(value: i32) -> Result<Precision, Status> {
+    let precision = grpc::Precision::try_from(value)
+        .map_err(|_| Status::invalid_argument(format!("Unknown `precision` value {value}")))?;
+    Ok(match precision {
+        grpc::Precision::Float => Precision::Float,
+        grpc::Precision::Float16 => Precision::Float16,
+        grpc::Precision::Int8 => Precision::Int8,
+        grpc::Precision::Uint8 => Precision::Uint8,
+        grpc::Precision::Binary => Precision::Binary,
+        grpc::Precision::Ubinary => Precision::Ubinary,
+    })
+}
+
+fn convert_similarity_metric(value: i32) -> Result<SimilarityMetric, Status> {
+    let metric = grpc::SimilarityMetric::try_from(value)
+        .map_err(|_| Status::invalid_argument(format!("Unknown `metric` value {value}")))?;
+    Ok(match metric {
+        grpc::SimilarityMetric::Cosine => SimilarityMetric::Cosine,
+        grpc::SimilarityMetric::Dot => SimilarityMetric::Dot,
+        grpc::SimilarityMetric::Euclidean => SimilarityMetric::Euclidean,
+        grpc::SimilarityMetric::Manhattan => SimilarityMetric::Manhattan,
+    })
+}
+
 fn convert_truncation_direction(value: i32) -> tokenizers::TruncationDirection {
     match TruncationDirection::try_from(value).expect("Unexpected enum value") {
         TruncationDirection::Right => tokenizers::TruncationDirection::Right,
         TruncationDirection::Left => tokenizers::TruncationDirection::Left,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClassifierModel, EmbeddingModel};
+
+    #[test]
+    fn test_services_status() {
+        let embedding = ModelType::Embedding(EmbeddingModel {
+            pooling: "cls".to_string(),
+        });
+        let reranker = ModelType::Reranker(ClassifierModel {
+            id2label: Default::default(),
+            label2id: Default::default(),
+        });
+        let model_types = [embedding, reranker];
+
+        let serving = |statuses: [(&'static str, ServingStatus); 6]| {
+            statuses
+                .into_iter()
+                .filter(|(_, status)| *status == ServingStatus::Serving)
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            serving(services_status(&model_types, &[true, true])),
+            vec![
+                "",
+                "tei.v1.Embed",
+                "tei.v1.Predict",
+                "tei.v1.Rerank",
+                "tei.v1.Similarity",
+                "tei.v1.Vertex"
+            ]
+        );
+        // The server is not serving as soon as one model is unhealthy
+        assert_eq!(
+            serving(services_status(&model_types, &[true, false])),
+            vec!["tei.v1.Embed", "tei.v1.Similarity", "tei.v1.Vertex"]
+        );
+        assert!(serving(services_status(&model_types, &[false, false])).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drain_streamed_response() {
+        let lifecycle = Lifecycle::new(Duration::from_secs(60));
+        let mut service = DrainLayer {
+            lifecycle: lifecycle.clone(),
+        }
+        .layer(tower::service_fn(|_: http::Request<()>| async {
+            Ok::<_, Status>(http::Response::new(tonic::body::empty_body()))
+        }));
+
+        let request = http::Request::builder()
+            .uri("/tei.v1.Embed/EmbedStream")
+            .body(())
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+
+        assert!(lifecycle.drain());
+        let drained = tokio::spawn(lifecycle.clone().drained());
+        tokio::task::yield_now().await;
+        // The request is in flight until its response body is sent
+        assert!(!drained.is_finished());
+        drop(response);
+        drained.await.unwrap();
+    }
+}
@@ -0,0 +1,430 @@
+//! `Accept` content negotiation of the embedding routes
+use crate::http::types::{EmbedAllResponse, EmbedResponse, EmbedSparseResponse};
+use crate::{ErrorResponse, ErrorType};
+use arrow_array::builder::{
+    FixedSizeListBuilder, Float32Builder, ListBuilder, PrimitiveBuilder, UInt32Builder,
+};
+use arrow_array::types::{ArrowPrimitiveType, Float32Type, Int8Type, UInt8Type};
+use arrow_array::{ArrayRef, RecordBatch};
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use std::sync::Arc;
+
+pub(crate) const MSGPACK: &str = "application/msgpack";
+pub(crate) const RAW: &str = "application/octet-stream";
+pub(crate) const ARROW_STREAM: &str = "application/vnd.apache.arrow.stream";
+
+/// Dimensions of a raw response, e.g. `32,1024`
+pub(crate) const EMBEDDING_SHAPE: &str = "x-embedding-shape";
+/// Element type of a raw response: `float32`, `int8`, `uint8`, or `uint32+float32` for the
+/// indices followed by the values of sparse embeddings
+pub(crate) const EMBEDDING_DTYPE: &str = "x-embedding-dtype";
+/// Number of rows of each input of a raw response, when it differs between inputs
+pub(crate) const EMBEDDING_LENGTHS: &str = "x-embedding-lengths";
+
+/// Encoding of a response, negotiated from the `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) enum ResponseFormat {
+    #[default]
+    Json,
+    MessagePack,
+    /// Little-endian values, described by the `X-Embedding-*` headers
+    Raw,
+    /// Apache Arrow IPC stream
+    Arrow,
+}
+
+impl ResponseFormat {
+    /// Supported media type with the highest quality in `accept`, the first one on ties
+    fn negotiate(accept: &str) -> Option<Self> {
+        let mut best: Option<(f32, Self)> = None;
+        for media_range in accept.split(',') {
+            let mut params = media_range.split(';');
+            let media_type = params
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            let quality = params
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let format = match media_type.as_str() {
+                "application/json" | "application/*" | "*/*" => Self::Json,
+                "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                    Self::MessagePack
+                }
+                RAW => Self::Raw,
+                ARROW_STREAM => Self::Arrow,
+                _ => continue,
+            };
+            if !best.is_some_and(|(best_quality, _)| best_quality >= quality) {
+                best = Some((quality, format));
+            }
+        }
+        best.map(|(_, format)| format)
+    }
+
+    /// Reject the binary formats for chunk embeddings, before running the inference
+    pub(crate) fn check_chunked(self) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        match self {
+            Self::Json | Self::MessagePack => Ok(()),
+            Self::Raw | Self::Arrow => {
+                let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                counter.increment(1);
+                Err(not_acceptable(CHUNKED_ERROR.to_string()))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ResponseFormat {
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let accept: Vec<&str> = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if accept.is_empty() {
+            return Ok(Self::Json);
+        }
+
+        let accept = accept.join(",");
+        Self::negotiate(&accept).ok_or_else(|| {
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            not_acceptable(format!(
+                "`Accept: {accept}` is not supported. Supported media types are \
+                 `application/json`, `{MSGPACK}`, `{RAW}` and `{ARROW_STREAM}`"
+            ))
+        })
+    }
+}
+
+fn not_acceptable(message: String) -> (StatusCode, Json<ErrorResponse>) {
+    tracing::error!("{message}");
+    let err = ErrorResponse {
+        error: message,
+        error_type: ErrorType::Validation,
+    };
+    (StatusCode::NOT_ACCEPTABLE, Json(err))
+}
+
+/// Response body, encoded in the negotiated format when the response is sent
+pub(crate) struct Negotiated<T> {
+    pub format: ResponseFormat,
+    pub body: T,
+}
+
+impl<T: BinaryEncoding> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let encoded = match self.format {
+            ResponseFormat::Json => return Json(self.body).into_response(),
+            ResponseFormat::MessagePack => rmp_serde::to_vec_named(&self.body)
+                .map(|body| ([(CONTENT_TYPE, MSGPACK)], body).into_response())
+                .map_err(|err| err.to_string()),
+            ResponseFormat::Raw => self
+                .body
+                .raw()
+                .map(|raw| (raw.headers(), [(CONTENT_TYPE, RAW)], raw.data).into_response()),
+            ResponseFormat::Arrow => self
+                .body
+                .arrow()
+                .and_then(|batch| write_ipc_stream(&batch).map_err(|err| err.to_string()))
+                .map(|body| ([(CONTENT_TYPE, ARROW_STREAM)], body).into_response()),
+        };
+        // The formats are checked before the inference, the encoding itself failed
+        encoded.unwrap_or_else(|err| {
+            let message = format!("Failed to encode the response: {err}");
+            tracing::error!("{message}");
+            let err = ErrorResponse {
+                error: message,
+                error_type: ErrorType::Backend,
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(err)).into_response()
+        })
+    }
+}
+
+fn write_ipc_stream(batch: &RecordBatch) -> Result<Vec<u8>, arrow_schema::ArrowError> {
+    let mut writer = arrow_ipc::writer::StreamWriter::try_new(Vec::new(), &batch.schema())?;
+    writer.write(batch)?;
+    writer.into_inner()
+}
+
+/// Little-endian buffer of a raw response
+#[derive(Debug, PartialEq)]
+pub(crate) struct RawEmbeddings {
+    data: Vec<u8>,
+    shape: Vec<usize>,
+    dtype: &'static str,
+    /// Number of rows of each input when they are not all the same
+    lengths: Option<Vec<usize>>,
+}
+
+impl RawEmbeddings {
+    fn headers(&self) -> HeaderMap {
+        let join = |values: &[usize]| {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            HeaderValue::from_str(&values.join(",")).unwrap()
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(EMBEDDING_SHAPE, join(&self.shape));
+        headers.insert(EMBEDDING_DTYPE, HeaderValue::from_static(self.dtype));
+        if let Some(lengths) = &self.lengths {
+            headers.insert(EMBEDDING_LENGTHS, join(lengths));
+        }
+        headers
+    }
+}
+
+/// Binary encodings of a response, next to its JSON and MessagePack serialization
+pub(crate) trait BinaryEncoding: Serialize {
+    fn raw(&self) -> Result<RawEmbeddings, String>;
+
+    fn arrow(&self) -> Result<RecordBatch, String>;
+}
+
+fn matrix<T: Copy, const N: usize>(
+    rows: &[Vec<T>],
+    dtype: &'static str,
+    to_le_bytes: impl Fn(T) -> [u8; N],
+) -> RawEmbeddings {
+    let dim = rows.first().map_or(0, |row| row.len());
+    RawEmbeddings {
+        data: rows
+            .iter()
+            .flatten()
+            .flat_map(|v| to_le_bytes(*v))
+            .collect(),
+        shape: vec![rows.len(), dim],
+        dtype,
+        lengths: None,
+    }
+}
+
+fn fixed_size_list<T: ArrowPrimitiveType>(rows: &[Vec<T::Native>]) -> ArrayRef {
+    let dim = rows.first().map_or(0, |row| row.len());
+    let mut builder = FixedSizeListBuilder::new(PrimitiveBuilder::<T>::new(), dim as i32);
+    for row in rows {
+        builder.values().append_slice(row);
+        builder.append(true);
+    }
+    Arc::new(builder.finish())
+}
+
+const CHUNKED_ERROR: &str = "chunked embeddings can only be encoded as JSON or MessagePack";
+
+impl BinaryEncoding for EmbedResponse {
+    fn raw(&self) -> Result<RawEmbeddings, String> {
+        match self {
+            EmbedResponse::Float(rows) => Ok(matrix(rows, "float32", f32::to_le_bytes)),
+            EmbedResponse::Int8(rows) => Ok(matrix(rows, "int8", i8::to_le_bytes)),
+            EmbedResponse::Uint8(rows) => Ok(matrix(rows, "uint8", u8::to_le_bytes)),
+            EmbedResponse::Chunked(_) => Err(CHUNKED_ERROR.to_string()),
+        }
+    }
+
+    fn arrow(&self) -> Result<RecordBatch, String> {
+        let embeddings = match self {
+            EmbedResponse::Float(rows) => fixed_size_list::<Float32Type>(rows),
+            EmbedResponse::Int8(rows) => fixed_size_list::<Int8Type>(rows),
+            EmbedResponse::Uint8(rows) => fixed_size_list::<UInt8Type>(rows),
+            EmbedResponse::Chunked(_) => return Err(CHUNKED_ERROR.to_string()),
+        };
+        RecordBatch::try_from_iter([("embedding", embeddings)]).map_err(|err| err.to_string())
+    }
+}
+
+impl BinaryEncoding for EmbedAllResponse {
+    /// The token embeddings of all inputs, one row per token
+    fn raw(&self) -> Result<RawEmbeddings, String> {
+        let dim = self
+            .0
+            .iter()
+            .find_map(|tokens| tokens.first())
+            .map_or(0, |token| token.len());
+        let lengths: Vec<usize> = self.0.iter().map(|tokens| tokens.len()).collect();
+        Ok(RawEmbeddings {
+            data: self
+                .0
+                .iter()
+                .flatten()
+                .flatten()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+            shape: vec![lengths.iter().sum(), dim],
+            dtype: "float32",
+            lengths: Some(lengths),
+        })
+    }
+
+    fn arrow(&self) -> Result<RecordBatch, String> {
+        let dim = self
+            .0
+            .iter()
+            .find_map(|tokens| tokens.first())
+            .map_or(0, |token| token.len());
+        let mut builder =
+            ListBuilder::new(FixedSizeListBuilder::new(Float32Builder::new(), dim as i32));
+        for tokens in &self.0 {
+            for token in tokens {
+                builder.values().values().append_slice(token);
+                builder.values().append(true);
+            }
+            builder.append(true);
+        }
+        let embeddings: ArrayRef = Arc::new(builder.finish());
+        RecordBatch::try_from_iter([("embeddings", embeddings)]).map_err(|err| err.to_string())
+    }
+}
+
+impl BinaryEncoding for EmbedSparseResponse {
+    /// The `u32` indices of the non-zero values of all inputs, followed by their `f32` values
+    fn raw(&self) -> Result<RawEmbeddings, String> {
+        let values = self.0.iter().flatten();
+        let data = values
+            .clone()
+            .flat_map(|v| (v.index as u32).to_le_bytes())
+            .chain(values.clone().flat_map(|v| v.value.to_le_bytes()))
+            .collect();
+        let lengths: Vec<usize> = self.0.iter().map(|values| values.len()).collect();
+        Ok(RawEmbeddings {
+            data,
+            shape: vec![values.count()],
+            dtype: "uint32+float32",
+            lengths: Some(lengths),
+        })
+    }
+
+    fn arrow(&self) -> Result<RecordBatch, String> {
+        let mut indices = ListBuilder::new(UInt32Builder::new());
+        let mut values = ListBuilder::new(Float32Builder::new());
+        for sparse in &self.0 {
+            for value in sparse {
+                indices.values().append_value(value.index as u32);
+                values.values().append_value(value.value);
+            }
+            indices.append(true);
+            values.append(true);
+        }
+        let indices: ArrayRef = Arc::new(indices.finish());
+        let values: ArrayRef = Arc::new(values.finish());
+        RecordBatch::try_from_iter([("indices", indices), ("values", values)])
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::types::SparseValue;
+    use arrow_array::cast::AsArray;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            ResponseFormat::negotiate("application/json"),
+            Some(ResponseFormat::Json)
+        );
+        assert_eq!(ResponseFormat::negotiate("*/*"), Some(ResponseFormat::Json));
+        assert_eq!(
+            ResponseFormat::negotiate("application/x-msgpack"),
+            Some(ResponseFormat::MessagePack)
+        );
+        assert_eq!(
+            ResponseFormat::negotiate("application/json;q=0.5, application/octet-stream"),
+            Some(ResponseFormat::Raw)
+        );
+        assert_eq!(
+            ResponseFormat::negotiate("application/vnd.apache.arrow.stream, */*;q=0.1"),
+            Some(ResponseFormat::Arrow)
+        );
+        // Ties keep the first media type
+        assert_eq!(
+            ResponseFormat::negotiate("application/msgpack, application/json"),
+            Some(ResponseFormat::MessagePack)
+        );
+        assert_eq!(ResponseFormat::negotiate("text/html"), None);
+        assert_eq!(ResponseFormat::negotiate("application/json;q=0"), None);
+
+        assert!(ResponseFormat::MessagePack.check_chunked().is_ok());
+        assert_eq!(
+            ResponseFormat::Raw.check_chunked().unwrap_err().0,
+            StatusCode::NOT_ACCEPTABLE
+        );
+    }
+
+    #[test]
+    fn test_raw() {
+        let raw = EmbedResponse::Float(vec![vec![1.0, 2.0], vec![3.0, 4.0]])
+            .raw()
+            .unwrap();
+        assert_eq!(raw.shape, vec![2, 2]);
+        assert_eq!(raw.dtype, "float32");
+        assert_eq!(&raw.data[4..8], &2.0f32.to_le_bytes());
+        assert_eq!(raw.headers()[EMBEDDING_SHAPE], "2,2");
+
+        let raw = EmbedAllResponse(vec![vec![vec![1.0; 3]; 2], vec![vec![2.0; 3]]])
+            .raw()
+            .unwrap();
+        assert_eq!(raw.shape, vec![3, 3]);
+        assert_eq!(raw.data.len(), 9 * 4);
+        assert_eq!(raw.headers()[EMBEDDING_LENGTHS], "2,1");
+
+        let sparse = EmbedSparseResponse(vec![
+            vec![SparseValue {
+                index: 7,
+                value: 0.5,
+            }],
+            vec![],
+        ]);
+        let raw = sparse.raw().unwrap();
+        assert_eq!(raw.shape, vec![1]);
+        assert_eq!(
+            raw.data,
+            [7u32.to_le_bytes(), 0.5f32.to_le_bytes()].concat()
+        );
+        assert_eq!(raw.lengths, Some(vec![1, 0]));
+        assert_eq!(raw.headers()[EMBEDDING_DTYPE], "uint32+float32");
+
+        assert!(EmbedResponse::Chunked(vec![]).raw().is_err());
+    }
+
+    #[test]
+    fn test_arrow() {
+        let batch = EmbedResponse::Int8(vec![vec![1, 2], vec![3, 4]])
+            .arrow()
+            .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let embeddings = batch.column(0).as_fixed_size_list();
+        assert_eq!(embeddings.value_length(), 2);
+
+        let batch = EmbedAllResponse(vec![vec![vec![1.0; 3]; 2], vec![vec![2.0; 3]]])
+            .arrow()
+            .unwrap();
+        assert_eq!(batch.column(0).as_list::<i32>().value_length(1), 1);
+
+        // The IPC stream can be read back
+        let body = write_ipc_stream(&batch).unwrap();
+        let reader =
+            arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(body), None).unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches, vec![batch]);
+    }
+}
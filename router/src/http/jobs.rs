@@ -0,0 +1,799 @@
+//! Asynchronous batch jobs stored in a local directory
+use crate::http::types::{
+    JobInfo, JobParameters, JobRecord, JobResult, JobResultsFormat, JobStatus,
+};
+use crate::npy::NpyWriter;
+use crate::{ErrorResponse, ErrorType, Models, ServedModel};
+use anyhow::Context;
+use arrow_array::builder::{Float32Builder, ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use axum::body::Body;
+use futures::{Stream, StreamExt};
+use parquet::arrow::ArrowWriter;
+use reqwest::Url;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use text_embeddings_core::queue::Priority;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+const JOB_FILE: &str = "job.json";
+const INPUT_FILE: &str = "input.jsonl";
+const RESULTS_FILE: &str = "results.jsonl";
+/// Interval between two writes of the progress of a running job
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of rows of the Parquet record batches
+const PARQUET_BATCH_SIZE: usize = 1024;
+
+/// Job store and its worker
+///
+/// Jobs run one at a time, in submission order, with a low queue priority and at most
+/// `max_concurrency` records in flight, so that they never hold every permit of the model. Each
+/// job has its own directory with its state, its uploaded input and its results. Jobs that were
+/// queued or running when the router stopped are resumed on startup, after the records that
+/// already have a result. Jobs are only visible to the tenant that submitted them.
+#[derive(Debug, Clone)]
+pub(crate) struct Jobs {
+    dir: PathBuf,
+    /// Root of the input files of `parameters.path`, which is rejected if not set
+    input_dir: Option<PathBuf>,
+    /// Maximum size in bytes of an uploaded input
+    max_input_size: usize,
+    models: Models,
+    sender: mpsc::UnboundedSender<String>,
+    counter: Arc<AtomicUsize>,
+    conversions: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Jobs {
+    /// Open the job store in `dir` and start its worker
+    pub async fn start(
+        dir: PathBuf,
+        input_dir: Option<PathBuf>,
+        max_concurrency: Option<usize>,
+        max_input_size: usize,
+        models: Models,
+    ) -> anyhow::Result<Self> {
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .context(format!("Failed to create `{}`", dir.display()))?;
+        let input_dir = match input_dir {
+            Some(input_dir) => Some(
+                tokio::fs::canonicalize(&input_dir)
+                    .await
+                    .context(format!("Failed to open `{}`", input_dir.display()))?,
+            ),
+            None => None,
+        };
+
+        let mut pending = Vec::new();
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let job_dir = entry.path();
+            match read_info(&job_dir).await {
+                Ok(info) => {
+                    if matches!(info.status, JobStatus::Queued | JobStatus::Running) {
+                        pending.push(info);
+                    }
+                }
+                Err(err) => tracing::warn!("Ignoring `{}`: {err:#}", job_dir.display()),
+            }
+        }
+        pending.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        for info in pending {
+            tracing::info!("Resuming job `{}`", info.id);
+            sender.send(info.id)?;
+        }
+        tokio::spawn(job_task(
+            dir.clone(),
+            models.clone(),
+            max_concurrency,
+            receiver,
+        ));
+
+        Ok(Self {
+            dir,
+            input_dir,
+            max_input_size,
+            models,
+            sender,
+            counter: Arc::new(AtomicUsize::new(0)),
+            conversions: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    /// Register a job of `tenant` and queue it. The input is either `parameters.path` or the
+    /// request body.
+    pub async fn submit(
+        &self,
+        mut parameters: JobParameters,
+        tenant: Option<&str>,
+        body: Body,
+    ) -> Result<JobInfo, ErrorResponse> {
+        self.models.get(parameters.model.as_deref())?;
+        if let Some(webhook) = &parameters.webhook {
+            validate_webhook(webhook).map_err(validation_error)?;
+        }
+        if let Some(path) = &parameters.path {
+            let path = resolve_path(self.input_dir.as_deref(), path).await?;
+            parameters.path = Some(path.display().to_string());
+        }
+
+        let id = format!(
+            "{:x}-{}",
+            unix_time().as_nanos(),
+            self.counter.fetch_add(1, Ordering::Relaxed)
+        );
+        let job_dir = self.dir.join(&id);
+        let info = JobInfo {
+            id: id.clone(),
+            status: JobStatus::Queued,
+            parameters,
+            tenant: tenant.map(String::from),
+            total: None,
+            processed: 0,
+            failed: 0,
+            error: None,
+            created_at: unix_timestamp(),
+            finished_at: None,
+        };
+
+        let result = async {
+            tokio::fs::create_dir(&job_dir)
+                .await
+                .map_err(|err| internal_error(format!("Failed to store job: {err}")))?;
+            if info.parameters.path.is_none() {
+                write_body(body, &job_dir.join(INPUT_FILE), self.max_input_size).await?;
+            }
+            write_info(&job_dir, &info)
+                .await
+                .map_err(|err| internal_error(format!("Failed to store job: {err:#}")))
+        }
+        .await;
+        if let Err(err) = result {
+            let _ = tokio::fs::remove_dir_all(&job_dir).await;
+            tracing::error!("{}", err.error);
+            return Err(err);
+        }
+
+        self.sender
+            .send(id)
+            .expect("the job worker is never dropped");
+        tracing::info!("Queued job `{}`", info.id);
+        Ok(info)
+    }
+
+    /// Job `id` of `tenant`
+    pub async fn get(&self, id: &str, tenant: Option<&str>) -> Result<JobInfo, ErrorResponse> {
+        // Job ids are generated by the router, anything else could escape the jobs directory
+        let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+        let info = if valid {
+            read_info(&self.dir.join(id))
+                .await
+                .ok()
+                .filter(|info| info.tenant.as_deref() == tenant)
+        } else {
+            None
+        };
+        info.ok_or_else(|| ErrorResponse {
+            error: format!("Job `{id}` not found"),
+            error_type: ErrorType::NotFound,
+        })
+    }
+
+    /// All jobs of `tenant`, most recent first
+    pub async fn list(&self, tenant: Option<&str>) -> Result<Vec<JobInfo>, ErrorResponse> {
+        let list_error =
+            |err: std::io::Error| internal_error(format!("Failed to list jobs: {err}"));
+        let mut entries = tokio::fs::read_dir(&self.dir).await.map_err(list_error)?;
+        let mut jobs = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(list_error)? {
+            let info = read_info(&entry.path()).await.ok();
+            jobs.extend(info.filter(|info| info.tenant.as_deref() == tenant));
+        }
+        jobs.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+        Ok(jobs)
+    }
+
+    /// Path of the results of a completed job of `tenant` in the requested format
+    pub async fn results(
+        &self,
+        id: &str,
+        tenant: Option<&str>,
+        format: JobResultsFormat,
+    ) -> Result<PathBuf, ErrorResponse> {
+        let info = self.get(id, tenant).await?;
+        if info.status != JobStatus::Completed {
+            return Err(validation_error(format!("Job `{id}` is not completed")));
+        }
+
+        let job_dir = self.dir.join(id);
+        let results = job_dir.join(RESULTS_FILE);
+        let (output, convert): (_, fn(&Path, &Path) -> anyhow::Result<()>) = match format {
+            JobResultsFormat::Jsonl => return Ok(results),
+            JobResultsFormat::Npy => (job_dir.join("results.npy"), write_npy),
+            JobResultsFormat::Parquet => (job_dir.join("results.parquet"), write_parquet),
+        };
+
+        // Results of completed jobs do not change, conversions are cached next to them
+        let _guard = self.conversions.lock().await;
+        if !output.is_file() {
+            let local_output = output.clone();
+            tokio::task::spawn_blocking(move || {
+                let tmp = local_output.with_extension("tmp");
+                convert(&results, &tmp)?;
+                std::fs::rename(&tmp, &local_output)?;
+                Ok::<_, anyhow::Error>(())
+            })
+            .await
+            .unwrap_or_else(|err| Err(anyhow::anyhow!("conversion panicked: {err}")))
+            .map_err(|err| {
+                tracing::error!("Failed to convert results of job `{id}`: {err:#}");
+                internal_error(format!("Failed to convert results: {err:#}"))
+            })?;
+        }
+        Ok(output)
+    }
+}
+
+fn validation_error(error: String) -> ErrorResponse {
+    ErrorResponse {
+        error,
+        error_type: ErrorType::Validation,
+    }
+}
+
+fn internal_error(error: String) -> ErrorResponse {
+    ErrorResponse {
+        error,
+        error_type: ErrorType::Internal,
+    }
+}
+
+/// Number of records of a job in flight at once: `max_concurrency`, or half of the permits of
+/// the model if not set, so that interactive requests are never starved by a job
+fn job_concurrency(max_concurrency: Option<usize>, max_concurrent_requests: usize) -> usize {
+    let max_concurrent_requests = max_concurrent_requests.max(1);
+    max_concurrency
+        .unwrap_or(max_concurrent_requests / 2)
+        .clamp(1, max_concurrent_requests)
+}
+
+/// Resolve the `path` of a submission, which must be a file in `--jobs-input-dir`
+async fn resolve_path(input_dir: Option<&Path>, path: &str) -> Result<PathBuf, ErrorResponse> {
+    let Some(input_dir) = input_dir else {
+        return Err(validation_error(
+            "`path` is disabled, set `--jobs-input-dir` to read the input from a file".to_string(),
+        ));
+    };
+    // Symlinks and `..` components are resolved before checking the root
+    let invalid = || {
+        validation_error(format!(
+            "`{path}` is not a file in `{}`",
+            input_dir.display()
+        ))
+    };
+    let path = tokio::fs::canonicalize(input_dir.join(path))
+        .await
+        .map_err(|_| invalid())?;
+    let is_file = tokio::fs::metadata(&path)
+        .await
+        .is_ok_and(|metadata| metadata.is_file());
+    if !path.starts_with(input_dir) || !is_file {
+        return Err(invalid());
+    }
+    Ok(path)
+}
+
+fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+}
+
+fn unix_timestamp() -> u64 {
+    unix_time().as_secs()
+}
+
+/// Webhooks can only target the local host
+fn validate_webhook(webhook: &str) -> Result<(), String> {
+    let url = Url::parse(webhook).map_err(|err| format!("Invalid webhook `{webhook}`: {err}"))?;
+    let local = match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback()),
+        None => false,
+    };
+    if !matches!(url.scheme(), "http" | "https") || !local {
+        return Err(format!(
+            "Webhook `{webhook}` must be an http(s) URL on localhost"
+        ));
+    }
+    Ok(())
+}
+
+async fn read_info(job_dir: &Path) -> anyhow::Result<JobInfo> {
+    let content = tokio::fs::read(job_dir.join(JOB_FILE)).await?;
+    Ok(serde_json::from_slice(&content)?)
+}
+
+/// Atomically replace the state of a job
+async fn write_info(job_dir: &Path, info: &JobInfo) -> anyhow::Result<()> {
+    let tmp = job_dir.join(format!("{JOB_FILE}.tmp"));
+    tokio::fs::write(&tmp, serde_json::to_vec_pretty(info)?).await?;
+    tokio::fs::rename(&tmp, job_dir.join(JOB_FILE)).await?;
+    Ok(())
+}
+
+/// Write an uploaded input, which is rejected once it exceeds `max_size` bytes
+async fn write_body(body: Body, path: &Path, max_size: usize) -> Result<(), ErrorResponse> {
+    let store_error = |err: std::io::Error| internal_error(format!("Failed to store job: {err}"));
+    let mut file = tokio::fs::File::create(path).await.map_err(store_error)?;
+    let mut data = body.into_data_stream();
+    let mut size = 0;
+    while let Some(chunk) = data.next().await {
+        let chunk =
+            chunk.map_err(|err| validation_error(format!("Failed to read input: {err}")))?;
+        size += chunk.len();
+        if size > max_size {
+            return Err(validation_error(format!(
+                "Input is larger than `--jobs-max-input-size` ({max_size} bytes)"
+            )));
+        }
+        file.write_all(&chunk).await.map_err(store_error)?;
+    }
+    file.flush().await.map_err(store_error)?;
+    Ok(())
+}
+
+fn input_path(job_dir: &Path, parameters: &JobParameters) -> PathBuf {
+    match &parameters.path {
+        Some(path) => PathBuf::from(path),
+        None => job_dir.join(INPUT_FILE),
+    }
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Number of records of an input file
+fn count_records(path: &Path) -> anyhow::Result<usize> {
+    let mut total = 0;
+    for line in BufReader::new(std::fs::File::open(path)?).lines() {
+        if !is_blank(&line?) {
+            total += 1;
+        }
+    }
+    Ok(total)
+}
+
+/// Drop the trailing partial line of a results file and count its results and errors
+fn recover_results(path: &Path) -> anyhow::Result<(usize, usize)> {
+    let mut file = match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(err) => return Err(err.into()),
+    };
+
+    let (mut processed, mut failed, mut complete_len) = (0, 0, 0);
+    let mut reader = BufReader::new(&mut file);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 || line.last() != Some(&b'\n') {
+            break;
+        }
+        complete_len += read as u64;
+        processed += 1;
+        let result: JobResult = serde_json::from_slice(&line)?;
+        if result.error.is_some() {
+            failed += 1;
+        }
+    }
+    drop(reader);
+
+    file.set_len(complete_len)?;
+    Ok((processed, failed))
+}
+
+async fn job_task(
+    dir: PathBuf,
+    models: Models,
+    max_concurrency: Option<usize>,
+    mut receiver: mpsc::UnboundedReceiver<String>,
+) {
+    while let Some(id) = receiver.recv().await {
+        let job_dir = dir.join(&id);
+        let info = match run_job(&job_dir, &models, max_concurrency).await {
+            Ok(info) => {
+                tracing::info!("Job `{id}` completed");
+                info
+            }
+            Err(err) => {
+                tracing::error!("Job `{id}` failed: {err:#}");
+                let Ok(mut info) = read_info(&job_dir).await else {
+                    continue;
+                };
+                info.status = JobStatus::Failed;
+                info.error = Some(format!("{err:#}"));
+                info.finished_at = Some(unix_timestamp());
+                if let Err(err) = write_info(&job_dir, &info).await {
+                    tracing::error!("Failed to store the state of job `{id}`: {err:#}");
+                }
+                info
+            }
+        };
+
+        if let Some(webhook) = &info.parameters.webhook {
+            notify(webhook, &info).await;
+        }
+    }
+}
+
+async fn run_job(
+    job_dir: &Path,
+    models: &Models,
+    max_concurrency: Option<usize>,
+) -> anyhow::Result<JobInfo> {
+    let mut info = read_info(job_dir).await?;
+    let ServedModel {
+        infer,
+        info: model_info,
+        ..
+    } = models
+        .get(info.parameters.model.as_deref())
+        .map_err(|err| anyhow::anyhow!(err.error))?
+        .clone();
+    let infer = infer.with_priority(Priority::Low);
+
+    let input = input_path(job_dir, &info.parameters);
+    let results_path = job_dir.join(RESULTS_FILE);
+    let (total, (processed, failed)) = {
+        let input = input.clone();
+        let results_path = results_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let total =
+                count_records(&input).context(format!("Failed to read `{}`", input.display()))?;
+            Ok::<_, anyhow::Error>((total, recover_results(&results_path)?))
+        })
+        .await??
+    };
+
+    info.status = JobStatus::Running;
+    info.total = Some(total);
+    info.processed = processed;
+    info.failed = failed;
+    write_info(job_dir, &info).await?;
+
+    let mut results = tokio::io::BufWriter::new(
+        tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&results_path)
+            .await?,
+    );
+
+    // Records are embedded concurrently but their results are written in input order, so the
+    // number of results is enough to resume the job
+    let parameters = info.parameters.clone();
+    let truncate = parameters.truncate.unwrap_or(model_info.auto_truncate);
+    let mut records = input_lines(&input)
+        .await?
+        .filter(|line| futures::future::ready(!matches!(line, Ok(line) if is_blank(line))))
+        .skip(processed)
+        .map(|line| {
+            let infer = infer.clone();
+            let parameters = parameters.clone();
+            async move {
+                let record: JobRecord = match serde_json::from_str(&line?) {
+                    Ok(record) => record,
+                    Err(err) => {
+                        return Ok(JobResult {
+                            id: serde_json::Value::Null,
+                            embedding: None,
+                            error: Some(format!("Invalid record: {err}")),
+                        })
+                    }
+                };
+
+                let permit = infer.acquire_permit().await;
+                let result = infer
+                    .embed_pooled(
+                        record.inputs,
+                        truncate,
+                        parameters.truncation_direction.into(),
+                        parameters.prompt_name,
+                        parameters.normalize,
+                        parameters.dimensions,
+                        permit,
+                        None,
+                    )
+                    .await;
+                Ok::<_, std::io::Error>(match result {
+                    Ok(response) => JobResult {
+                        id: record.id,
+                        embedding: Some(response.results),
+                        error: None,
+                    },
+                    Err(err) => JobResult {
+                        id: record.id,
+                        embedding: None,
+                        error: Some(err.to_string()),
+                    },
+                })
+            }
+        })
+        .buffered(job_concurrency(
+            max_concurrency,
+            model_info.max_concurrent_requests,
+        ));
+
+    let mut last_progress = Instant::now();
+    while let Some(result) = records.next().await {
+        let result = result.context(format!("Failed to read `{}`", input.display()))?;
+        if result.error.is_some() {
+            info.failed += 1;
+        }
+        let mut line = serde_json::to_vec(&result)?;
+        line.push(b'\n');
+        results.write_all(&line).await?;
+        info.processed += 1;
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            results.flush().await?;
+            write_info(job_dir, &info).await?;
+            last_progress = Instant::now();
+        }
+    }
+    results.flush().await?;
+    results.get_ref().sync_all().await?;
+
+    info.status = JobStatus::Completed;
+    info.finished_at = Some(unix_timestamp());
+    write_info(job_dir, &info).await?;
+    Ok(info)
+}
+
+async fn input_lines(
+    path: &Path,
+) -> anyhow::Result<impl Stream<Item = Result<String, std::io::Error>>> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .context(format!("Failed to open `{}`", path.display()))?;
+    let lines = tokio::io::BufReader::new(file).lines();
+    Ok(futures::stream::unfold(lines, |mut lines| async move {
+        lines
+            .next_line()
+            .await
+            .transpose()
+            .map(|line| (line, lines))
+    }))
+}
+
+async fn notify(webhook: &str, info: &JobInfo) {
+    let response = reqwest::Client::new()
+        .post(webhook)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(info).expect("job info is serializable"))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = response {
+        tracing::warn!("Failed to notify `{webhook}` of job `{}`: {err}", info.id);
+    }
+}
+
+fn read_results(path: &Path) -> anyhow::Result<impl Iterator<Item = anyhow::Result<JobResult>>> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    Ok(reader
+        .lines()
+        .map(|line| Ok(serde_json::from_str::<JobResult>(&line?)?)))
+}
+
+/// Write the results as a matrix with one row per record
+fn write_npy(results: &Path, output: &Path) -> anyhow::Result<()> {
+    // Rows of failed records are filled with `NaN` so rows match records
+    let mut cols = 0;
+    for result in read_results(results)? {
+        if let Some(embedding) = result?.embedding {
+            cols = embedding.len();
+            break;
+        }
+    }
+
+    let mut writer = NpyWriter::new(BufWriter::new(std::fs::File::create(output)?))?;
+    let nan_row = vec![f32::NAN; cols];
+    for result in read_results(results)? {
+        let embedding = result?.embedding;
+        writer.write_row(embedding.as_deref().unwrap_or(&nan_row))?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Write the results as a table with `id`, `embedding` and `error` columns
+fn write_parquet(results: &Path, output: &Path) -> anyhow::Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, true),
+        Field::new(
+            "embedding",
+            DataType::List(Arc::new(Field::new("item", DataType::Float32, true))),
+            true,
+        ),
+        Field::new("error", DataType::Utf8, true),
+    ]));
+    let mut writer = ArrowWriter::try_new(std::fs::File::create(output)?, schema.clone(), None)?;
+
+    let mut ids = StringBuilder::new();
+    let mut embeddings = ListBuilder::new(Float32Builder::new());
+    let mut errors = StringBuilder::new();
+    let mut rows = 0;
+    let mut results = read_results(results)?.peekable();
+    while let Some(result) = results.next() {
+        let result = result?;
+        match result.id {
+            serde_json::Value::Null => ids.append_null(),
+            serde_json::Value::String(id) => ids.append_value(id),
+            id => ids.append_value(id.to_string()),
+        }
+        match result.embedding {
+            Some(embedding) => {
+                embeddings.values().append_slice(&embedding);
+                embeddings.append(true);
+            }
+            None => embeddings.append(false),
+        }
+        errors.append_option(result.error);
+        rows += 1;
+
+        if rows == PARQUET_BATCH_SIZE || results.peek().is_none() {
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(ids.finish()),
+                Arc::new(embeddings.finish()),
+                Arc::new(errors.finish()),
+            ];
+            writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+            rows = 0;
+        }
+    }
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tei-jobs-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_recover_results() {
+        let dir = temp_dir("recover");
+        let path = dir.join(RESULTS_FILE);
+        assert_eq!(recover_results(&path).unwrap(), (0, 0));
+
+        std::fs::write(
+            &path,
+            "{\"id\":1,\"embedding\":[0.5]}\n{\"id\":2,\"error\":\"failed\"}\n{\"id\":3,\"embe",
+        )
+        .unwrap();
+        assert_eq!(recover_results(&path).unwrap(), (2, 1));
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.ends_with("\"failed\"}\n"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_npy() {
+        let dir = temp_dir("npy");
+        let results = dir.join(RESULTS_FILE);
+        std::fs::write(
+            &results,
+            "{\"id\":1,\"error\":\"failed\"}\n{\"id\":2,\"embedding\":[0.5,1.0]}\n",
+        )
+        .unwrap();
+
+        let output = dir.join("results.npy");
+        write_npy(&results, &output).unwrap();
+        let data = std::fs::read(&output).unwrap();
+        assert!(std::str::from_utf8(&data[10..128])
+            .unwrap()
+            .contains("'shape': (2, 2)"));
+        let values: Vec<f32> = data[128..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert!(values[0].is_nan() && values[1].is_nan());
+        assert_eq!(&values[2..], &[0.5, 1.0]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolve_path() {
+        let dir = temp_dir("input");
+        let input_dir = dir.join("input");
+        std::fs::create_dir(&input_dir).unwrap();
+        std::fs::write(input_dir.join("corpus.jsonl"), "").unwrap();
+        std::fs::write(dir.join("secret.jsonl"), "").unwrap();
+        let input_dir = std::fs::canonicalize(input_dir).unwrap();
+
+        let path = resolve_path(Some(&input_dir), "corpus.jsonl")
+            .await
+            .unwrap();
+        assert_eq!(path, input_dir.join("corpus.jsonl"));
+        let absolute = input_dir.join("corpus.jsonl").display().to_string();
+        assert!(resolve_path(Some(&input_dir), &absolute).await.is_ok());
+
+        // Files outside of the input directory are rejected
+        assert!(resolve_path(Some(&input_dir), "../secret.jsonl")
+            .await
+            .is_err());
+        assert!(resolve_path(Some(&input_dir), "/etc/passwd").await.is_err());
+        assert!(resolve_path(Some(&input_dir), ".").await.is_err());
+        assert!(resolve_path(None, "corpus.jsonl").await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_job_concurrency() {
+        assert_eq!(job_concurrency(None, 512), 256);
+        assert_eq!(job_concurrency(None, 1), 1);
+        assert_eq!(job_concurrency(Some(8), 512), 8);
+        assert_eq!(job_concurrency(Some(0), 512), 1);
+        assert_eq!(job_concurrency(Some(1024), 512), 512);
+    }
+
+    #[tokio::test]
+    async fn test_write_body() {
+        let dir = temp_dir("body");
+        let path = dir.join(INPUT_FILE);
+        write_body(Body::from("{\"inputs\":\"a\"}\n"), &path, 64)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"inputs\":\"a\"}\n"
+        );
+
+        let err = write_body(Body::from(vec![b'a'; 65]), &path, 64)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Validation));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_webhook() {
+        assert!(validate_webhook("http://localhost:8000/done").is_ok());
+        assert!(validate_webhook("http://127.0.0.1/done").is_ok());
+        assert!(validate_webhook("http://[::1]:9000").is_ok());
+        assert!(validate_webhook("http://example.com/done").is_err());
+        assert!(validate_webhook("file:///etc/passwd").is_err());
+    }
+}
@@ -1,2 +1,5 @@
 pub mod server;
+mod encoding;
+mod jobs;
+mod model_swap;
 mod types;
@@ -0,0 +1,206 @@
+//! Replacement of a served model while the router keeps serving
+use crate::http::types::{ModelSwapRequest, ModelSwapState, ModelSwapStatus};
+use crate::{ErrorResponse, ErrorType, ModelLoader, Models};
+use std::sync::{Arc, Mutex};
+
+/// Loads a model in the background and swaps it with a served model once it is warmed up
+///
+/// Requests that already selected the replaced model complete on it. Its batching and backend
+/// tasks stop once the last of them is done, which releases the backend. If loading fails, the
+/// replaced model keeps serving.
+#[derive(Debug, Clone)]
+pub(crate) struct ModelSwap {
+    models: Models,
+    loader: ModelLoader,
+    status: Arc<Mutex<ModelSwapStatus>>,
+}
+
+impl ModelSwap {
+    pub fn new(models: Models, loader: ModelLoader) -> Self {
+        Self {
+            models,
+            loader,
+            status: Arc::new(Mutex::new(ModelSwapStatus::default())),
+        }
+    }
+
+    pub fn status(&self) -> ModelSwapStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Start loading the model of `request`. Only one swap can run at a time.
+    pub fn start(&self, request: ModelSwapRequest) -> Result<ModelSwapStatus, ErrorResponse> {
+        let mut status = self.status.lock().unwrap();
+        if status.state == ModelSwapState::Loading {
+            return Err(ErrorResponse {
+                error: format!(
+                    "Model `{}` is already loading",
+                    status.model_id.as_deref().unwrap_or_default()
+                ),
+                error_type: ErrorType::Overloaded,
+            });
+        }
+
+        let replace = match request.replace {
+            Some(replace) => replace,
+            None => self.models.default_model().info.model_id,
+        };
+        if !self.models.names().contains(&replace) {
+            return Err(ErrorResponse {
+                error: format!("Model `{replace}` is not served"),
+                error_type: ErrorType::NotFound,
+            });
+        }
+
+        *status = ModelSwapStatus {
+            state: ModelSwapState::Loading,
+            model_id: Some(request.model_id.clone()),
+            revision: request.revision.clone(),
+            replace: Some(replace.clone()),
+            error: None,
+        };
+        let started = status.clone();
+        drop(status);
+
+        let local = self.clone();
+        tokio::spawn(async move {
+            // Loading runs in its own task so that a panic fails the swap instead of leaving it
+            // loading forever
+            let load = {
+                let local = local.clone();
+                let replace = replace.clone();
+                let model_id = request.model_id.clone();
+                let revision = request.revision.clone();
+                tokio::spawn(async move {
+                    let model = local.loader.load(model_id, revision).await?;
+                    local.models.swap(Some(&replace), model)
+                })
+            };
+            let result = load
+                .await
+                .unwrap_or_else(|err| Err(anyhow::anyhow!("loading panicked: {err}")));
+
+            let mut status = local.status.lock().unwrap();
+            match result {
+                Ok(_) => {
+                    tracing::info!("Model `{replace}` swapped with `{}`", request.model_id);
+                    metrics::counter!("te_model_swap", "status" => "success").increment(1);
+                    status.state = ModelSwapState::Completed;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to swap model `{replace}`: {err:#}");
+                    metrics::counter!("te_model_swap", "status" => "failure").increment(1);
+                    status.state = ModelSwapState::Failed;
+                    status.error = Some(format!("{err:#}"));
+                }
+            }
+        });
+
+        Ok(started)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelSettings;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+    use text_embeddings_backend::DType;
+
+    fn loader() -> ModelLoader {
+        ModelLoader {
+            settings: ModelSettings {
+                revision: None,
+                tokenization_workers: Some(1),
+                dtype: Some(DType::Float32),
+                pooling: None,
+                max_concurrent_requests: 4,
+                max_batch_tokens: 1024,
+                max_batch_requests: None,
+                max_queue_time: None,
+                max_client_batch_size: 32,
+                auto_truncate: false,
+                embedding_cache_size: None,
+                bisect_failed_batches: false,
+                default_prompt: None,
+                default_prompt_name: None,
+                dense_path: None,
+                multi_vector: false,
+                hf_token: None,
+                uds_path: "/tmp/text-embeddings-inference-swap".to_string(),
+                huggingface_hub_cache: None,
+                otlp_endpoint: None,
+                otlp_service_name: "text-embeddings-inference.server".to_string(),
+            },
+            loaded: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn request(model_id: &str) -> ModelSwapRequest {
+        ModelSwapRequest {
+            model_id: model_id.to_string(),
+            revision: None,
+            replace: None,
+        }
+    }
+
+    async fn wait(swap: &ModelSwap) -> ModelSwapStatus {
+        for _ in 0..600 {
+            let status = swap.status();
+            if status.state != ModelSwapState::Loading {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the swap did not complete");
+    }
+
+    #[tokio::test]
+    async fn test_model_swap() {
+        let loader = loader();
+        let model = loader
+            .load("sentence-transformers/all-MiniLM-L6-v2".to_string(), None)
+            .await
+            .unwrap();
+        let models = Models::new(vec![model]).unwrap();
+        let swap = ModelSwap::new(models.clone(), loader);
+
+        // A directory without a model fails to load and the replaced model keeps serving
+        let dir = std::env::temp_dir().join(format!("tei-swap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        swap.start(request(&dir.display().to_string())).unwrap();
+        let status = wait(&swap).await;
+        assert_eq!(status.state, ModelSwapState::Failed);
+        assert!(status.error.is_some());
+        assert_eq!(models.names(), ["sentence-transformers/all-MiniLM-L6-v2"]);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        // Only one swap runs at a time
+        let mut swapped = models.swapped();
+        let started = swap.start(request("BAAI/bge-small-en-v1.5")).unwrap();
+        assert_eq!(started.state, ModelSwapState::Loading);
+        assert_eq!(
+            started.replace.as_deref(),
+            Some("sentence-transformers/all-MiniLM-L6-v2")
+        );
+        let err = swap
+            .start(request("sentence-transformers/all-MiniLM-L6-v2"))
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Overloaded));
+
+        let status = wait(&swap).await;
+        assert_eq!(status.state, ModelSwapState::Completed, "{status:?}");
+        assert_eq!(models.names(), ["BAAI/bge-small-en-v1.5"]);
+        assert!(swapped.has_changed().unwrap());
+
+        // Unknown models cannot be replaced
+        let err = swap
+            .start(ModelSwapRequest {
+                replace: Some("unknown".to_string()),
+                ..request("sentence-transformers/all-MiniLM-L6-v2")
+            })
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::NotFound));
+    }
+}
@@ -1,45 +1,77 @@
+use crate::auth::{retry_after_secs, ApiKeys, AuthError, Tenant};
 /// HTTP Server logic
+use crate::http::encoding::{
+    Negotiated, ResponseFormat, EMBEDDING_DTYPE, EMBEDDING_LENGTHS, EMBEDDING_SHAPE,
+};
+use crate::http::jobs::Jobs;
+use crate::http::model_swap::ModelSwap;
 use crate::http::types::{
-    DecodeRequest, DecodeResponse, EmbedAllRequest, EmbedAllResponse, EmbedRequest, EmbedResponse,
-    EmbedSparseRequest, EmbedSparseResponse, Embedding, EncodingFormat, Input, InputIds, InputType,
+    ChunkAggregation, ChunkEmbedding, ChunkSpan, ChunkingParameters, DecodeRequest, DecodeResponse,
+    EmbedAllRequest, EmbedAllResponse, EmbedLateChunkingRequest, EmbedLateChunkingResponse,
+    EmbedMultiVectorRequest, EmbedRequest, EmbedResponse, EmbedSparseRequest, EmbedSparseResponse,
+    EmbedStreamRecord, EmbedStreamResult, Embedding, EncodingFormat, InfoResponse, Input, InputIds,
+    InputType, JobInfo, JobParameters, JobRecord, JobResult, JobResultsFormat, JobResultsQuery,
+    JobStatus, MaxSimRequest, ModelSwapRequest, ModelSwapState, ModelSwapStatus,
     OpenAICompatEmbedding, OpenAICompatErrorResponse, OpenAICompatRequest, OpenAICompatResponse,
-    OpenAICompatUsage, PredictInput, PredictRequest, PredictResponse, Prediction, Rank,
-    RerankRequest, RerankResponse, Sequence, SimilarityInput, SimilarityParameters,
-    SimilarityRequest, SimilarityResponse, SimpleToken, SparseValue, TokenizeInput,
-    TokenizeRequest, TokenizeResponse, TruncationDirection, VertexPrediction, VertexRequest,
-    VertexResponse,
+    OpenAICompatUsage, PredictInput, PredictRequest, PredictResponse, Prediction, Priority, Rank,
+    RerankCompatBilledUnits, RerankCompatDocument, RerankCompatMeta, RerankCompatRequest,
+    RerankCompatResponse, RerankCompatResult, RerankRequest, RerankResponse, Sequence,
+    SimilarityInput, SimilarityParameters, SimilarityRequest, SimilarityResponse, SimilaritySource,
+    SimpleToken, SparseValue, TokenizeInput, TokenizeRequest, TokenizeResponse,
+    TruncationDirection, VertexPrediction, VertexRequest, VertexResponse,
 };
+use crate::quantization::{quantize, Precision, QuantizationRanges};
+use crate::shutdown::{InFlight, Lifecycle};
+use crate::similarity::{SimilarityInputs, SimilarityMetric};
+use crate::tls::{ClientIdentity, TlsAcceptor, TlsConnectInfo, TlsFiles};
+#[cfg(unix)]
+use crate::uds::SocketFile;
+use crate::uds::UnixSocket;
 use crate::{
-    logging, shutdown, ClassifierModel, EmbeddingModel, ErrorResponse, ErrorType, Info, ModelType,
-    ResponseMetadata,
+    logging, rerank_input, ClassifierModel, EmbeddingModel, ErrorResponse, ErrorType, Info,
+    ModelLoader, ModelType, Models, ResponseMetadata, ServedModel, ServerSettings,
 };
 use ::http::HeaderMap;
 use anyhow::Context;
-use axum::extract::{DefaultBodyLimit, Extension};
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Extension, Query, State};
 use axum::http::HeaderValue;
 use axum::http::{Method, StatusCode};
+use axum::response::IntoResponse;
 use axum::routing::{get, post};
 use axum::{http, Json, Router};
 use axum_tracing_opentelemetry::middleware::OtelAxumLayer;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use futures::future::join_all;
-use futures::FutureExt;
+use futures::{FutureExt, Stream, StreamExt};
 use http::header::AUTHORIZATION;
-use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
-use simsimd::SpatialSimilarity;
+use hyper::body::{Body as HttpBody, Frame, SizeHint};
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::ops::Range;
+use std::pin::Pin;
 use std::sync::{atomic::AtomicUsize, Arc};
+use std::task::Poll;
 use std::time::{Duration, Instant};
 use text_embeddings_backend::BackendError;
+use text_embeddings_core::chunking::{aggregate, separator_spans, Chunk};
 use text_embeddings_core::infer::{
     AllEmbeddingsInferResponse, Infer, InferMetadata, PooledEmbeddingsInferResponse,
 };
+use text_embeddings_core::multi_vector::{self, MultiVectorRole};
+use text_embeddings_core::queue::Priority as QueuePriority;
 use text_embeddings_core::tokenization::{into_tokens, SimpleToken as CoreSimpleToken};
 use text_embeddings_core::TextEmbeddingsError;
-use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::io::ReaderStream;
+use tower_http::compression::predicate::{NotForContentType, Predicate};
+use tower_http::compression::{CompressionLayer, DefaultPredicate};
 use tower_http::cors::{AllowOrigin, CorsLayer};
-use tracing::instrument;
+use tower_http::decompression::RequestDecompressionLayer;
+use tracing::{instrument, Instrument};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
@@ -49,11 +81,15 @@ use utoipa_swagger_ui::SwaggerUi;
 get,
 tag = "Text Embeddings Inference",
 path = "/info",
-responses((status = 200, description = "Served model info", body = Info))
+responses((status = 200, description = "Served models info", body = InfoResponse))
 )]
-#[instrument]
-async fn get_model_info(info: Extension<Info>) -> Json<Info> {
-    Json(info.0)
+#[instrument(skip_all)]
+async fn get_model_info(models: Extension<Models>) -> Json<InfoResponse> {
+    let models: Vec<Info> = models.iter().map(|m| m.info).collect();
+    Json(InfoResponse {
+        info: models[0].clone(),
+        models,
+    })
 }
 
 #[utoipa::path(
@@ -66,10 +102,19 @@ responses(
 example = json ! ({"error": "unhealthy", "error_type": "unhealthy"})),
 )
 )]
-#[instrument(skip(infer))]
+#[instrument(skip(models, lifecycle))]
 /// Health check method
-async fn health(infer: Extension<Infer>) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
-    match infer.health().await {
+async fn health(
+    models: Extension<Models>,
+    lifecycle: Extension<Lifecycle>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if lifecycle.is_draining() {
+        Err(ErrorResponse {
+            error: "draining".to_string(),
+            error_type: ErrorType::Unhealthy,
+        })?;
+    }
+    match models.health().await {
         true => Ok(()),
         false => Err(ErrorResponse {
             error: "unhealthy".to_string(),
@@ -78,6 +123,44 @@ async fn health(infer: Extension<Infer>) -> Result<(), (StatusCode, Json<ErrorRe
     }
 }
 
+/// Liveness probe: the process is up, even while the models are loading or the router is
+/// draining
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/live",
+responses((status = 200, description = "The router is alive"))
+)]
+async fn live() {}
+
+/// Readiness probe: every model is warmed up and healthy and the router is not draining
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/ready",
+responses(
+(status = 200, description = "The router accepts requests"),
+(status = 503, description = "The router is loading, draining or unhealthy", body = ErrorResponse,
+example = json ! ({"error": "draining", "error_type": "unhealthy"})),
+)
+)]
+#[instrument(skip_all)]
+async fn ready(
+    models: Extension<Models>,
+    lifecycle: Extension<Lifecycle>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    health(models, lifecycle).await
+}
+
+/// Readiness probe while the models are loading
+async fn loading() -> (StatusCode, Json<ErrorResponse>) {
+    ErrorResponse {
+        error: "loading".to_string(),
+        error_type: ErrorType::Unhealthy,
+    }
+    .into()
+}
+
 /// Get Predictions. Returns a 424 status code if the model is not a Sequence Classification model
 #[utoipa::path(
 post,
@@ -103,9 +186,9 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn predict(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
     Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
     Json(req): Json<PredictRequest>,
 ) -> Result<(HeaderMap, Json<PredictResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
@@ -113,6 +196,9 @@ async fn predict(
         span.set_parent(context);
     }
 
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
     let start_time = Instant::now();
 
     // Closure for predict
@@ -183,7 +269,7 @@ async fn predict(
             let compute_chars = inputs.count_chars();
             let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
             let (prompt_tokens, tokenization, queue, inference, predictions) =
-                predict_inner(inputs, truncate, infer.0, info.0, Some(permit), None).await?;
+                predict_inner(inputs, truncate, infer, info, Some(permit), None).await?;
 
             let counter = metrics::counter!("te_request_count", "method" => "single");
             counter.increment(1);
@@ -236,8 +322,8 @@ async fn predict(
                 futures.push(predict_inner(
                     input,
                     truncate,
-                    local_infer.0,
-                    local_info.0,
+                    local_infer,
+                    local_info,
                     None,
                     local_batch_counter,
                 ))
@@ -315,9 +401,9 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn rerank(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
     Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
     Json(req): Json<RerankRequest>,
 ) -> Result<(HeaderMap, Json<RerankResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
@@ -325,6 +411,23 @@ async fn rerank(
         span.set_parent(context);
     }
 
+    let (response, metadata) = rerank_texts(&models, queue_headers, req).await?;
+    let headers = HeaderMap::from(metadata);
+
+    Ok((headers, Json(response)))
+}
+
+/// Rank the texts of a rerank request and record the metrics of the request
+async fn rerank_texts(
+    models: &Models,
+    queue_headers: QueueHeaders,
+    req: RerankRequest,
+) -> Result<(RerankResponse, ResponseMetadata), ErrorResponse> {
+    let span = tracing::Span::current();
+
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
     let start_time = Instant::now();
 
     if req.texts.is_empty() {
@@ -355,12 +458,7 @@ async fn rerank(
         ErrorResponse::from(err)
     })?;
 
-    // Apply template if needed for Qwen3 rerankers
     let model_id = info.model_id.clone();
-    let use_template = req.use_template.unwrap_or_else(|| {
-        // Default to true for Qwen3 sequence classification models
-        text_embeddings_core::templates::requires_template(&model_id)
-    });
 
     // Closure for rerank
     let rerank_inner = move |query: String,
@@ -372,20 +470,14 @@ async fn rerank(
                              batch_counter: Option<Arc<AtomicUsize>>| async move {
         let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
 
-        // Apply template formatting if needed
-        let input: text_embeddings_core::tokenization::EncodingInput = if use_template {
-            if let Some(formatter) = text_embeddings_core::templates::get_template_formatter(&model_id) {
-                // Format as single string with template
-                let formatted = formatter.format_rerank(&query, &text, instruction.as_deref());
-                formatted.into()
-            } else {
-                // No template, use dual input
-                (query, text).into()
-            }
-        } else {
-            // Template disabled, use dual input
-            (query, text).into()
-        };
+        // Apply the prompt template of Qwen3 rerankers
+        let input = rerank_input(
+            &model_id,
+            req.use_template,
+            query,
+            text,
+            instruction.as_deref(),
+        );
 
         let response = infer
             .predict(
@@ -451,7 +543,7 @@ async fn rerank(
                 truncate,
                 req.instruction.clone(),
                 model_id.clone(),
-                local_infer.0,
+                local_infer,
                 local_batch_counter,
             ))
         }
@@ -514,9 +606,99 @@ async fn rerank(
     metadata.record_span(&span);
     metadata.record_metrics();
 
+    tracing::info!("Success");
+
+    Ok((response, metadata))
+}
+
+/// Cohere and Jina compatible route. Returns a 424 status code if the model is not a Sequence
+/// Classification model with a single class.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/v1/rerank",
+request_body = RerankCompatRequest,
+responses(
+(status = 200, description = "Ranks", body = RerankCompatResponse),
+(status = 424, description = "Rerank Error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Inference failed", "type": "backend"})),
+(status = 429, description = "Model is overloaded", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Model is overloaded", "type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Tokenization error", "type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch is empty", "type": "empty"})),
+(status = 413, description = "Batch size error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch size error", "type": "validation"})),
+)
+)]
+#[instrument(skip_all)]
+async fn rerank_compat(
+    models: Extension<Models>,
+    Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
+    Json(req): Json<RerankCompatRequest>,
+) -> Result<(HeaderMap, Json<RerankCompatResponse>), (StatusCode, Json<OpenAICompatErrorResponse>)>
+{
+    let span = tracing::Span::current();
+    if let Some(context) = context {
+        span.set_parent(context);
+    }
+
+    let texts = req
+        .documents
+        .iter()
+        .map(|document| document.text(req.rank_fields.as_deref()))
+        .collect::<Result<Vec<String>, ErrorResponse>>()
+        .map_err(|err| {
+            tracing::error!("{}", err.error);
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            err
+        })?;
+
+    let rerank_req = RerankRequest {
+        query: req.query,
+        texts,
+        truncate: None,
+        truncation_direction: TruncationDirection::default(),
+        raw_scores: false,
+        return_text: false,
+        instruction: None,
+        use_template: None,
+        model: req.model,
+        priority: req.priority,
+        timeout: req.timeout,
+    };
+    let (RerankResponse(ranks), metadata) =
+        rerank_texts(&models, queue_headers, rerank_req).await?;
+    let input_tokens = metadata.compute_tokens;
     let headers = HeaderMap::from(metadata);
 
-    tracing::info!("Success");
+    let mut documents: Vec<Option<RerankCompatDocument>> =
+        req.documents.into_iter().map(Some).collect();
+    let top_n = req.top_n.unwrap_or(ranks.len());
+    let results = ranks
+        .into_iter()
+        .take(top_n)
+        .map(|rank| RerankCompatResult {
+            index: rank.index,
+            relevance_score: rank.score,
+            document: if req.return_documents {
+                documents[rank.index]
+                    .take()
+                    .map(RerankCompatDocument::into_value)
+            } else {
+                None
+            },
+        })
+        .collect();
+
+    let response = RerankCompatResponse {
+        results,
+        meta: RerankCompatMeta {
+            billed_units: RerankCompatBilledUnits { input_tokens },
+        },
+    };
 
     Ok((headers, Json(response)))
 }
@@ -546,65 +728,71 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn similarity(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
     context: Extension<Option<opentelemetry::Context>>,
+    queue_headers: Extension<QueueHeaders>,
     Json(req): Json<SimilarityRequest>,
 ) -> Result<(HeaderMap, Json<SimilarityResponse>), (StatusCode, Json<ErrorResponse>)> {
-    if req.inputs.sentences.is_empty() {
-        let message = "`inputs.sentences` cannot be empty".to_string();
-        tracing::error!("{message}");
-        let err = ErrorResponse {
-            error: message,
-            error_type: ErrorType::Empty,
-        };
-        let counter = metrics::counter!("te_request_failure", "err" => "validation");
-        counter.increment(1);
-        Err(err)?;
-    }
-    // +1 because of the source sentence
-    let batch_size = req.inputs.sentences.len() + 1;
-    if batch_size > info.max_client_batch_size {
-        let message = format!(
-            "batch size {batch_size} > maximum allowed batch size {}",
-            info.max_client_batch_size
-        );
-        tracing::error!("{message}");
-        let err = ErrorResponse {
-            error: message,
-            error_type: ErrorType::Validation,
-        };
-        let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
-        counter.increment(1);
-        Err(err)?;
-    }
+    let info = models.get(req.model.as_deref())?.info;
+
+    let (sources, matrix) = match req.inputs.source_sentence {
+        SimilaritySource::Single(source) => (vec![source], false),
+        SimilaritySource::Batch(sources) => (sources, true),
+    };
+    // Sources are compared against each other if no sentences are given
+    let sentences = match req.inputs.sentences {
+        Some(sentences) => Some(sentences),
+        None if matrix => None,
+        None => Some(vec![]),
+    };
+
+    // Embed each unique text only once
+    let inputs = SimilarityInputs::new(sources, sentences, info.max_client_batch_size)?;
 
     // Convert request to embed request
-    let mut inputs = Vec::with_capacity(req.inputs.sentences.len() + 1);
-    inputs.push(InputType::String(req.inputs.source_sentence));
-    for s in req.inputs.sentences {
-        inputs.push(InputType::String(s));
-    }
     let parameters = req.parameters.unwrap_or_default();
     let embed_req = EmbedRequest {
-        inputs: Input::Batch(inputs),
+        inputs: Input::Batch(
+            inputs
+                .texts
+                .iter()
+                .map(|text| InputType::String(text.clone()))
+                .collect(),
+        ),
         truncate: parameters.truncate,
         truncation_direction: parameters.truncation_direction,
         prompt_name: parameters.prompt_name,
         normalize: false,
         dimensions: None,
+        precision: Precision::Float,
+        chunking: None,
+        model: req.model,
+        priority: req.priority,
+        timeout: req.timeout,
     };
 
     // Get embeddings
-    let (header_map, embed_response) = embed(infer, info, context, Json(embed_req)).await?;
-    let embeddings = embed_response.0 .0;
+    let (header_map, embed_response) = embed(
+        ResponseFormat::Json,
+        models,
+        context,
+        queue_headers,
+        Json(embed_req),
+    )
+    .await?;
+    let EmbedResponse::Float(embeddings) = embed_response.body else {
+        unreachable!("similarity always requests float embeddings")
+    };
 
-    // Compute cosine
-    let distances = (1..batch_size)
-        .map(|i| 1.0 - f32::cosine(&embeddings[0], &embeddings[i]).unwrap() as f32)
-        .collect();
+    let mut scores = inputs.scores(parameters.metric, &embeddings);
+
+    let response = if matrix {
+        SimilarityResponse::Matrix(scores)
+    } else {
+        SimilarityResponse::Scores(scores.swap_remove(0))
+    };
 
-    Ok((header_map, Json(SimilarityResponse(distances))))
+    Ok((header_map, Json(response)))
 }
 
 /// Get Embeddings. Returns a 424 status code if the model is not an embedding model.
@@ -614,7 +802,8 @@ tag = "Text Embeddings Inference",
 path = "/embed",
 request_body = EmbedRequest,
 responses(
-(status = 200, description = "Embeddings", body = EmbedResponse),
+(status = 200, description = "Embeddings", body = EmbedResponse,
+content_type = ["application/json", "application/msgpack", "application/octet-stream", "application/vnd.apache.arrow.stream"]),
 (status = 424, description = "Embedding Error", body = ErrorResponse,
 example = json ! ({"error": "Inference failed", "error_type": "backend"})),
 (status = 429, description = "Model is overloaded", body = ErrorResponse,
@@ -625,6 +814,10 @@ example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
 (status = 413, description = "Batch size error", body = ErrorResponse,
 example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 406, description = "Unsupported Accept header", body = ErrorResponse,
+example = json ! ({"error": "Unsupported Accept header", "error_type": "validation"})),
+(status = 500, description = "Encoding Error", body = ErrorResponse,
+example = json ! ({"error": "Failed to encode the response", "error_type": "backend"})),
 )
 )]
 #[instrument(
@@ -632,21 +825,64 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn embed(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    format: ResponseFormat,
+    models: Extension<Models>,
     Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
     Json(req): Json<EmbedRequest>,
-) -> Result<(HeaderMap, Json<EmbedResponse>), (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(HeaderMap, Negotiated<EmbedResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     if let Some(context) = context {
         span.set_parent(context);
     }
 
+    let ServedModel {
+        infer,
+        info,
+        quantization_ranges,
+    } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+    // Chunk embeddings without an aggregation only have a JSON representation
+    if req
+        .chunking
+        .as_ref()
+        .is_some_and(|chunking| chunking.aggregation.is_none())
+    {
+        format.check_chunked()?;
+    }
+
     let start_time = Instant::now();
 
+    if let Some(chunking) = req.chunking {
+        let (response, metadata) = embed_chunked(
+            &infer,
+            &info,
+            req,
+            chunking,
+            quantization_ranges.as_deref(),
+            start_time,
+        )
+        .await?;
+
+        metadata.record_span(&span);
+        metadata.record_metrics();
+
+        let headers = HeaderMap::from(metadata);
+
+        tracing::info!("Success");
+
+        return Ok((
+            headers,
+            Negotiated {
+                format,
+                body: response,
+            },
+        ));
+    }
+
     let truncate = req.truncate.unwrap_or(info.auto_truncate);
 
-    let (response, metadata) = match req.inputs {
+    let (embeddings, metadata) = match req.inputs {
         Input::Single(input) => {
             metrics::counter!("te_request_count", "method" => "single").increment(1);
 
@@ -670,7 +906,7 @@ async fn embed(
             metrics::counter!("te_request_success", "method" => "single").increment(1);
 
             (
-                EmbedResponse(vec![response.results]),
+                vec![response.results],
                 ResponseMetadata::new(
                     compute_chars,
                     response.metadata.prompt_tokens,
@@ -772,7 +1008,7 @@ async fn embed(
             counter.increment(1);
 
             (
-                EmbedResponse(embeddings),
+                embeddings,
                 ResponseMetadata::new(
                     compute_chars,
                     total_compute_tokens,
@@ -785,6 +1021,8 @@ async fn embed(
         }
     };
 
+    let response = EmbedResponse::new(embeddings, req.precision, quantization_ranges.as_deref())?;
+
     metadata.record_span(&span);
     metadata.record_metrics();
 
@@ -792,7 +1030,432 @@ async fn embed(
 
     tracing::info!("Success");
 
-    Ok((headers, Json(response)))
+    Ok((
+        headers,
+        Negotiated {
+            format,
+            body: response,
+        },
+    ))
+}
+
+/// Embed inputs split in token windows, each window being its own queue entry
+async fn embed_chunked(
+    infer: &Infer,
+    info: &Info,
+    req: EmbedRequest,
+    chunking: ChunkingParameters,
+    quantization_ranges: Option<&QuantizationRanges>,
+    start_time: Instant,
+) -> Result<(EmbedResponse, ResponseMetadata), ErrorResponse> {
+    metrics::counter!("te_request_count", "method" => "chunked").increment(1);
+
+    let inputs = match req.inputs {
+        Input::Single(input) => vec![input],
+        Input::Batch(inputs) => inputs,
+    };
+
+    if inputs.is_empty() {
+        let message = "`inputs` cannot be empty".to_string();
+        tracing::error!("{message}");
+        metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+        return Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Empty,
+        });
+    }
+
+    if inputs.len() > info.max_client_batch_size {
+        let message = format!(
+            "batch size {} > maximum allowed batch size {}",
+            inputs.len(),
+            info.max_client_batch_size
+        );
+        tracing::error!("{message}");
+        metrics::counter!("te_request_failure", "err" => "batch_size").increment(1);
+        return Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        });
+    }
+
+    let max_tokens = chunking.max_tokens.unwrap_or(info.max_input_length);
+    if max_tokens > info.max_input_length {
+        let message = format!(
+            "`max_tokens` must be smaller than the maximum input length {}. Given: {max_tokens}",
+            info.max_input_length
+        );
+        tracing::error!("{message}");
+        metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+        return Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        });
+    }
+
+    let mut chunks = Vec::with_capacity(inputs.len());
+    let mut compute_chars = 0;
+    for input in inputs {
+        let InputType::String(input) = input else {
+            let message = "`chunking` is only available for string inputs".to_string();
+            tracing::error!("{message}");
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            return Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            });
+        };
+        compute_chars += input.chars().count();
+
+        let input_chunks = infer
+            .chunk(input, max_tokens, chunking.stride, req.prompt_name.clone())
+            .await
+            .map_err(ErrorResponse::from)?;
+        chunks.push(input_chunks);
+    }
+
+    // Aggregated embeddings are normalized after the aggregation
+    let normalize = req.normalize && chunking.aggregation.is_none();
+    let dimensions = req.dimensions;
+
+    // Each chunk is embedded as one input of a batch request
+    let num_chunks = chunks.iter().map(Vec::len).sum();
+    if num_chunks > info.max_client_batch_size {
+        let message = format!(
+            "number of chunks {num_chunks} > maximum allowed batch size {}",
+            info.max_client_batch_size
+        );
+        tracing::error!("{message}");
+        metrics::counter!("te_request_failure", "err" => "batch_size").increment(1);
+        return Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        });
+    }
+
+    let batch_counter = if num_chunks == 1 {
+        None
+    } else {
+        Some(Arc::new(AtomicUsize::new(num_chunks)))
+    };
+    let mut futures = Vec::with_capacity(num_chunks);
+    for chunk in chunks.iter().flatten() {
+        let local_infer = infer.clone();
+        let text = chunk.text.clone();
+        let prompt_name = req.prompt_name.clone();
+        let local_batch_counter = batch_counter.clone();
+
+        let permit = local_infer
+            .try_acquire_permit()
+            .map_err(ErrorResponse::from)?;
+
+        futures.push(async move {
+            local_infer
+                .embed_pooled(
+                    text,
+                    true,
+                    tokenizers::TruncationDirection::Right,
+                    prompt_name,
+                    normalize,
+                    dimensions,
+                    permit,
+                    local_batch_counter,
+                )
+                .await
+        })
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<PooledEmbeddingsInferResponse>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    let mut embeddings = Vec::with_capacity(num_chunks);
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+
+    for r in results {
+        total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+        total_queue_time += r.metadata.queue.as_nanos() as u64;
+        total_inference_time += r.metadata.inference.as_nanos() as u64;
+        total_compute_tokens += r.metadata.prompt_tokens;
+        embeddings.push(r.results);
+    }
+
+    let mut embeddings = embeddings.into_iter();
+    let inputs: Vec<Vec<(Vec<f32>, Chunk)>> = chunks
+        .into_iter()
+        .map(|chunks| {
+            chunks
+                .into_iter()
+                .map(|chunk| (embeddings.next().expect("missing chunk embedding"), chunk))
+                .collect()
+        })
+        .collect();
+
+    let response = match chunking.aggregation {
+        None => EmbedResponse::chunked(inputs, req.precision, quantization_ranges)?,
+        Some(aggregation) => {
+            let embeddings = inputs
+                .into_iter()
+                .map(|chunks| {
+                    let weights: Vec<usize> = chunks.iter().map(|(_, c)| c.num_tokens).collect();
+                    let chunks: Vec<Vec<f32>> = chunks.into_iter().map(|(e, _)| e).collect();
+                    let weights = match aggregation {
+                        ChunkAggregation::Mean => None,
+                        ChunkAggregation::Weighted => Some(weights.as_slice()),
+                    };
+                    aggregate(&chunks, weights, req.normalize)
+                })
+                .collect();
+            EmbedResponse::new(embeddings, req.precision, quantization_ranges)?
+        }
+    };
+
+    metrics::counter!("te_request_success", "method" => "chunked").increment(1);
+
+    let num_chunks = num_chunks as u64;
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        total_compute_tokens,
+        start_time,
+        Duration::from_nanos(total_tokenization_time / num_chunks),
+        Duration::from_nanos(total_queue_time / num_chunks),
+        Duration::from_nanos(total_inference_time / num_chunks),
+    );
+    Ok((response, metadata))
+}
+
+/// Stream Embeddings. Embeds the newline-delimited JSON records of the request body and streams
+/// their results back as newline-delimited JSON, in completion order. Errors are returned inline
+/// for each record. Each stream embeds at most `max_client_batch_size` records of a model at once.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/embed/stream",
+request_body(content = EmbedStreamRecord, content_type = "application/x-ndjson"),
+responses(
+(status = 200, description = "Embeddings", body = EmbedStreamResult,
+content_type = "application/x-ndjson"),
+)
+)]
+#[instrument(skip_all)]
+async fn embed_stream(
+    models: Extension<Models>,
+    Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Extension(PayloadLimit(payload_limit)): Extension<PayloadLimit>,
+    body: Body,
+) -> axum::response::Response {
+    let span = tracing::Span::current();
+    if let Some(context) = context {
+        span.set_parent(context);
+    }
+
+    // A stream is bounded like a client batch: records wait for a slot of the model they select,
+    // and at most the largest `max_client_batch_size` records are read ahead
+    let max_in_flight = models
+        .iter()
+        .map(|model| model.info.max_client_batch_size)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let slots = StreamSlots::default();
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+
+    let results = ndjson_lines(body, payload_limit)
+        .filter(|line| {
+            let blank = matches!(line, Ok(line) if line.iter().all(u8::is_ascii_whitespace));
+            futures::future::ready(!blank)
+        })
+        .map(move |line| {
+            let models = models.clone();
+            let slots = slots.clone();
+            let tenant = tenant.clone();
+            async move {
+                match line {
+                    Ok(line) => {
+                        let tenant = tenant.as_deref();
+                        embed_stream_record(&models, &slots, queue_headers, tenant, &line).await
+                    }
+                    Err(err) => {
+                        let message = format!("Failed to read the request body: {err}");
+                        tracing::error!("{message}");
+                        EmbedStreamResult::Error {
+                            id: serde_json::Value::Null,
+                            error: message,
+                            error_type: ErrorType::Validation,
+                        }
+                    }
+                }
+            }
+        })
+        .buffer_unordered(max_in_flight)
+        .map(|result| {
+            let mut line = serde_json::to_vec(&result).expect("results are serializable");
+            line.push(b'\n');
+            Ok::<_, Infallible>(Bytes::from(line))
+        });
+
+    (
+        [(http::header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(results),
+    )
+        .into_response()
+}
+
+/// Records of an `/embed/stream` request in flight, bounded for each model by its
+/// `max_client_batch_size`
+#[derive(Clone, Default)]
+struct StreamSlots(Arc<std::sync::Mutex<HashMap<String, Arc<Semaphore>>>>);
+
+impl StreamSlots {
+    async fn acquire(&self, info: &Info) -> OwnedSemaphorePermit {
+        // Models swapped in while streaming get their own slots
+        let slots = self
+            .0
+            .lock()
+            .unwrap()
+            .entry(info.model_id.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(info.max_client_batch_size.max(1))))
+            .clone();
+        slots
+            .acquire_owned()
+            .await
+            .expect("stream slots are never closed")
+    }
+}
+
+/// Embed a single record of an `/embed/stream` request
+async fn embed_stream_record(
+    models: &Models,
+    slots: &StreamSlots,
+    queue_headers: QueueHeaders,
+    tenant: Option<&Tenant>,
+    line: &[u8],
+) -> EmbedStreamResult {
+    let start_time = Instant::now();
+    metrics::counter!("te_request_count", "method" => "stream").increment(1);
+
+    let record: EmbedStreamRecord = match serde_json::from_slice(line) {
+        Ok(record) => record,
+        Err(err) => {
+            let message = format!("Invalid record: {err}");
+            tracing::error!("{message}");
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            return EmbedStreamResult::Error {
+                id: serde_json::Value::Null,
+                error: message,
+                error_type: ErrorType::Validation,
+            };
+        }
+    };
+    let id = record.id.clone();
+
+    let result = async {
+        let ServedModel {
+            infer,
+            info,
+            quantization_ranges,
+        } = models.get(record.model.as_deref())?;
+        let infer = queue_headers.apply(infer, record.priority, record.timeout)?;
+
+        let compute_chars = record.inputs.chars().count();
+        let _slot = slots.acquire(&info).await;
+        let permit = infer.acquire_permit().await;
+        let response = infer
+            .embed_pooled(
+                record.inputs,
+                record.truncate.unwrap_or(info.auto_truncate),
+                record.truncation_direction.into(),
+                record.prompt_name,
+                record.normalize,
+                record.dimensions,
+                permit,
+                None,
+            )
+            .await
+            .map_err(ErrorResponse::from)?;
+
+        if let Some(tenant) = tenant {
+            tenant.consume_tokens(response.metadata.prompt_tokens);
+        }
+        let metadata = ResponseMetadata::new(
+            compute_chars,
+            response.metadata.prompt_tokens,
+            start_time,
+            response.metadata.tokenization,
+            response.metadata.queue,
+            response.metadata.inference,
+        );
+        metadata.record_metrics();
+
+        let embedding = quantize(
+            response.results,
+            record.precision,
+            quantization_ranges.as_deref(),
+        )?;
+        Ok::<Embedding, ErrorResponse>(embedding.into())
+    }
+    .await;
+
+    match result {
+        Ok(embedding) => {
+            metrics::counter!("te_request_success", "method" => "stream").increment(1);
+            EmbedStreamResult::Embedding { id, embedding }
+        }
+        Err(err) => EmbedStreamResult::Error {
+            id,
+            error: err.error,
+            error_type: err.error_type,
+        },
+    }
+}
+
+/// `--payload-limit` of the server
+#[derive(Debug, Clone, Copy)]
+struct PayloadLimit(usize);
+
+/// Split a request body in lines of at most `max_line` bytes
+fn ndjson_lines(body: Body, max_line: usize) -> impl Stream<Item = Result<Vec<u8>, axum::Error>> {
+    futures::stream::unfold(
+        (body.into_data_stream(), Vec::new(), false),
+        move |(mut data, mut buffer, mut done)| async move {
+            loop {
+                let end = buffer.iter().position(|b| *b == b'\n');
+                if end.unwrap_or(buffer.len()) > max_line {
+                    // Stop at the first line over the limit instead of buffering the body
+                    buffer.clear();
+                    let err = axum::Error::new(format!("line is longer than {max_line} bytes"));
+                    return Some((Err(err), (data, buffer, true)));
+                }
+                if let Some(i) = end {
+                    let line: Vec<u8> = buffer.drain(..=i).collect();
+                    return Some((Ok(line), (data, buffer, done)));
+                }
+                if done {
+                    if buffer.is_empty() {
+                        return None;
+                    }
+                    let line = std::mem::take(&mut buffer);
+                    return Some((Ok(line), (data, buffer, done)));
+                }
+                match data.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                    Some(Err(err)) => {
+                        // Stop at the first body error
+                        buffer.clear();
+                        return Some((Err(err), (data, buffer, true)));
+                    }
+                    None => done = true,
+                }
+            }
+        },
+    )
 }
 
 /// Get Sparse Embeddings. Returns a 424 status code if the model is not an embedding model with SPLADE pooling.
@@ -802,7 +1465,8 @@ tag = "Text Embeddings Inference",
 path = "/embed_sparse",
 request_body = EmbedSparseRequest,
 responses(
-(status = 200, description = "Embeddings", body = EmbedSparseResponse),
+(status = 200, description = "Embeddings", body = EmbedSparseResponse,
+content_type = ["application/json", "application/msgpack", "application/octet-stream", "application/vnd.apache.arrow.stream"]),
 (status = 424, description = "Embedding Error", body = ErrorResponse,
 example = json ! ({"error": "Inference failed", "error_type": "backend"})),
 (status = 429, description = "Model is overloaded", body = ErrorResponse,
@@ -813,6 +1477,10 @@ example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
 (status = 413, description = "Batch size error", body = ErrorResponse,
 example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 406, description = "Unsupported Accept header", body = ErrorResponse,
+example = json ! ({"error": "Unsupported Accept header", "error_type": "validation"})),
+(status = 500, description = "Encoding Error", body = ErrorResponse,
+example = json ! ({"error": "Failed to encode the response", "error_type": "backend"})),
 )
 )]
 #[instrument(
@@ -820,16 +1488,20 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn embed_sparse(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    format: ResponseFormat,
+    models: Extension<Models>,
     Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
     Json(req): Json<EmbedSparseRequest>,
-) -> Result<(HeaderMap, Json<EmbedSparseResponse>), (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(HeaderMap, Negotiated<EmbedSparseResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     if let Some(context) = context {
         span.set_parent(context);
     }
 
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
     let start_time = Instant::now();
 
     let sparsify = |values: Vec<f32>| {
@@ -962,22 +1634,479 @@ async fn embed_sparse(
             }
             let batch_size = batch_size as u64;
 
-            let counter = metrics::counter!("te_request_success", "method" => "batch");
-            counter.increment(1);
+            let counter = metrics::counter!("te_request_success", "method" => "batch");
+            counter.increment(1);
+
+            (
+                EmbedSparseResponse(embeddings),
+                ResponseMetadata::new(
+                    compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                ),
+            )
+        }
+    };
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((
+        headers,
+        Negotiated {
+            format,
+            body: response,
+        },
+    ))
+}
+
+/// Get contextual chunk embeddings of a document.
+/// The document is embedded in a single forward pass and the token embeddings of each chunk are
+/// mean pooled, so every chunk embedding carries the context of the whole document.
+/// Returns a 424 status code if the model is not an embedding model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/embed_late_chunking",
+request_body = EmbedLateChunkingRequest,
+responses(
+(status = 200, description = "Chunk embeddings", body = EmbedLateChunkingResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 400, description = "Spans are empty", body = ErrorResponse,
+example = json ! ({"error": "Spans are empty", "error_type": "empty"})),
+(status = 413, description = "Span error", body = ErrorResponse,
+example = json ! ({"error": "Span error", "error_type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn embed_late_chunking(
+    models: Extension<Models>,
+    Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
+    Json(req): Json<EmbedLateChunkingRequest>,
+) -> Result<(HeaderMap, Json<EmbedLateChunkingResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    if let Some(context) = context {
+        span.set_parent(context);
+    }
+
+    let ServedModel {
+        infer,
+        quantization_ranges,
+        ..
+    } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
+    let start_time = Instant::now();
+
+    metrics::counter!("te_request_count", "method" => "late_chunking").increment(1);
+
+    let spans: Vec<Range<usize>> = match (req.spans, &req.separator) {
+        (Some(spans), None) => spans.into_iter().map(|s| s.start..s.end).collect(),
+        (None, Some(separator)) if !separator.is_empty() => separator_spans(&req.inputs, separator),
+        _ => {
+            let message =
+                "exactly one of `spans` or a non-empty `separator` must be set".to_string();
+            tracing::error!("{message}");
+            metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+            return Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            }
+            .into());
+        }
+    };
+
+    let compute_chars = req.inputs.chars().count();
+
+    let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+    let response = infer
+        .embed_spans(
+            req.inputs,
+            spans.clone(),
+            req.prompt_name,
+            req.normalize,
+            permit,
+        )
+        .await
+        .map_err(ErrorResponse::from)?;
+
+    let chunks = response
+        .results
+        .into_iter()
+        .zip(spans)
+        .map(|(embedding, span)| {
+            Ok(ChunkEmbedding {
+                embedding: quantize(embedding, req.precision, quantization_ranges.as_deref())?
+                    .into(),
+                start: span.start,
+                end: span.end,
+            })
+        })
+        .collect::<Result<Vec<_>, ErrorResponse>>()?;
+
+    metrics::counter!("te_request_success", "method" => "late_chunking").increment(1);
+
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        response.metadata.prompt_tokens,
+        start_time,
+        response.metadata.tokenization,
+        response.metadata.queue,
+        response.metadata.inference,
+    );
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((headers, Json(EmbedLateChunkingResponse(chunks))))
+}
+
+/// Get multi-vector Embeddings: the projected and normalized token embeddings of a ColBERT model.
+/// Returns a 424 status code if the model is not a multi-vector model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/embed_multi_vector",
+request_body = EmbedMultiVectorRequest,
+responses(
+(status = 200, description = "Embeddings", body = EmbedAllResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = ErrorResponse,
+example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn embed_multi_vector(
+    models: Extension<Models>,
+    Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
+    Json(req): Json<EmbedMultiVectorRequest>,
+) -> Result<(HeaderMap, Json<EmbedAllResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    if let Some(context) = context {
+        span.set_parent(context);
+    }
+
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
+    let start_time = Instant::now();
+
+    let truncate = req.truncate.unwrap_or(info.auto_truncate);
+    let role = if req.is_query {
+        MultiVectorRole::Query
+    } else {
+        MultiVectorRole::Document
+    };
+
+    let inputs = match req.inputs {
+        Input::Single(input) => {
+            metrics::counter!("te_request_count", "method" => "single").increment(1);
+            vec![input]
+        }
+        Input::Batch(inputs) => {
+            metrics::counter!("te_request_count", "method" => "batch").increment(1);
+            inputs
+        }
+    };
+
+    if inputs.is_empty() {
+        let message = "`inputs` cannot be empty".to_string();
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Empty,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        Err(err)?;
+    }
+
+    let batch_size = inputs.len();
+    if batch_size > info.max_client_batch_size {
+        let message = format!(
+            "batch size {batch_size} > maximum allowed batch size {}",
+            info.max_client_batch_size
+        );
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+        counter.increment(1);
+        Err(err)?;
+    }
+
+    let batch_counter = if batch_size == 1 {
+        None
+    } else {
+        Some(Arc::new(AtomicUsize::new(batch_size)))
+    };
+    let mut futures = Vec::with_capacity(batch_size);
+    let mut compute_chars = 0;
+
+    for input in inputs {
+        compute_chars += input.count_chars();
+
+        let local_infer = infer.clone();
+        let prompt_name = req.prompt_name.clone();
+        let local_batch_counter = batch_counter.clone();
+
+        let permit = local_infer
+            .try_acquire_permit()
+            .map_err(ErrorResponse::from)?;
+
+        futures.push(async move {
+            local_infer
+                .embed_multi_vector(
+                    input,
+                    truncate,
+                    req.truncation_direction.into(),
+                    prompt_name,
+                    role,
+                    req.skip_punctuation,
+                    permit,
+                    local_batch_counter,
+                )
+                .await
+        })
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<AllEmbeddingsInferResponse>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    let mut embeddings = Vec::with_capacity(batch_size);
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+
+    for r in results {
+        total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+        total_queue_time += r.metadata.queue.as_nanos() as u64;
+        total_inference_time += r.metadata.inference.as_nanos() as u64;
+        total_compute_tokens += r.metadata.prompt_tokens;
+        embeddings.push(r.results);
+    }
+    let batch_size = batch_size as u64;
+
+    let method = if batch_size == 1 { "single" } else { "batch" };
+    metrics::counter!("te_request_success", "method" => method).increment(1);
+
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        total_compute_tokens,
+        start_time,
+        Duration::from_nanos(total_tokenization_time / batch_size),
+        Duration::from_nanos(total_queue_time / batch_size),
+        Duration::from_nanos(total_inference_time / batch_size),
+    );
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((headers, Json(EmbedAllResponse(embeddings))))
+}
+
+/// Score documents against a query with late interaction (MaxSim) on the token embeddings of a
+/// multi-vector model. Returns a 424 status code if the model is not a multi-vector model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/maxsim",
+request_body = MaxSimRequest,
+responses(
+(status = 200, description = "Ranks", body = RerankResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = ErrorResponse,
+example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn maxsim(
+    models: Extension<Models>,
+    Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
+    Json(req): Json<MaxSimRequest>,
+) -> Result<(HeaderMap, Json<RerankResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    if let Some(context) = context {
+        span.set_parent(context);
+    }
+
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
+    let start_time = Instant::now();
+
+    metrics::counter!("te_request_count", "method" => "batch").increment(1);
+
+    if req.documents.is_empty() {
+        let message = "`documents` cannot be empty".to_string();
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Empty,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        Err(err)?;
+    }
+
+    let batch_size = req.documents.len();
+    if batch_size > info.max_client_batch_size {
+        let message = format!(
+            "batch size {batch_size} > maximum allowed batch size {}",
+            info.max_client_batch_size
+        );
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+        counter.increment(1);
+        Err(err)?;
+    }
+
+    let truncate = req.truncate.unwrap_or(info.auto_truncate);
+
+    // The query and the documents are batched together
+    let batch_counter = Some(Arc::new(AtomicUsize::new(batch_size + 1)));
+    let mut futures = Vec::with_capacity(batch_size + 1);
+    let mut compute_chars = req.query.chars().count();
+
+    let query = (
+        req.query.clone(),
+        req.query_prompt_name.clone(),
+        MultiVectorRole::Query,
+    );
+    let documents = req.documents.iter().map(|d| {
+        (
+            d.clone(),
+            req.document_prompt_name.clone(),
+            MultiVectorRole::Document,
+        )
+    });
+    for (input, prompt_name, role) in std::iter::once(query).chain(documents) {
+        // ColBERT only skips the punctuation of the documents
+        let skip_punctuation = role == MultiVectorRole::Document;
+        let local_infer = infer.clone();
+        let local_batch_counter = batch_counter.clone();
+
+        let permit = local_infer
+            .try_acquire_permit()
+            .map_err(ErrorResponse::from)?;
+
+        futures.push(async move {
+            local_infer
+                .embed_multi_vector(
+                    input,
+                    truncate,
+                    req.truncation_direction.into(),
+                    prompt_name,
+                    role,
+                    skip_punctuation,
+                    permit,
+                    local_batch_counter,
+                )
+                .await
+        })
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<AllEmbeddingsInferResponse>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+    for r in &results {
+        total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+        total_queue_time += r.metadata.queue.as_nanos() as u64;
+        total_inference_time += r.metadata.inference.as_nanos() as u64;
+        total_compute_tokens += r.metadata.prompt_tokens;
+    }
+
+    let mut results = results.into_iter();
+    let query = results.next().expect("missing query embeddings").results;
+
+    let mut ranks = Vec::with_capacity(batch_size);
+    for (index, document) in results.enumerate() {
+        compute_chars += req.documents[index].chars().count();
+        let text = if req.return_text {
+            Some(req.documents[index].clone())
+        } else {
+            None
+        };
+        let score = multi_vector::maxsim(&query, &document.results);
+        ranks.push(Rank { index, text, score })
+    }
+
+    // Reverse sort
+    ranks.sort_by(|x, y| y.score.total_cmp(&x.score));
 
-            (
-                EmbedSparseResponse(embeddings),
-                ResponseMetadata::new(
-                    compute_chars,
-                    total_compute_tokens,
-                    start_time,
-                    Duration::from_nanos(total_tokenization_time / batch_size),
-                    Duration::from_nanos(total_queue_time / batch_size),
-                    Duration::from_nanos(total_inference_time / batch_size),
-                ),
-            )
-        }
-    };
+    let counter = metrics::counter!("te_request_success", "method" => "batch");
+    counter.increment(1);
+
+    let num_inputs = batch_size as u64 + 1;
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        total_compute_tokens,
+        start_time,
+        Duration::from_nanos(total_tokenization_time / num_inputs),
+        Duration::from_nanos(total_queue_time / num_inputs),
+        Duration::from_nanos(total_inference_time / num_inputs),
+    );
 
     metadata.record_span(&span);
     metadata.record_metrics();
@@ -986,7 +2115,7 @@ async fn embed_sparse(
 
     tracing::info!("Success");
 
-    Ok((headers, Json(response)))
+    Ok((headers, Json(RerankResponse(ranks))))
 }
 
 /// Get all Embeddings without Pooling.
@@ -997,7 +2126,8 @@ tag = "Text Embeddings Inference",
 path = "/embed_all",
 request_body = EmbedAllRequest,
 responses(
-(status = 200, description = "Embeddings", body = EmbedAllResponse),
+(status = 200, description = "Embeddings", body = EmbedAllResponse,
+content_type = ["application/json", "application/msgpack", "application/octet-stream", "application/vnd.apache.arrow.stream"]),
 (status = 424, description = "Embedding Error", body = ErrorResponse,
 example = json ! ({"error": "Inference failed", "error_type": "backend"})),
 (status = 429, description = "Model is overloaded", body = ErrorResponse,
@@ -1008,6 +2138,10 @@ example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
 (status = 413, description = "Batch size error", body = ErrorResponse,
 example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 406, description = "Unsupported Accept header", body = ErrorResponse,
+example = json ! ({"error": "Unsupported Accept header", "error_type": "validation"})),
+(status = 500, description = "Encoding Error", body = ErrorResponse,
+example = json ! ({"error": "Failed to encode the response", "error_type": "backend"})),
 )
 )]
 #[instrument(
@@ -1015,16 +2149,20 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn embed_all(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    format: ResponseFormat,
+    models: Extension<Models>,
     Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
     Json(req): Json<EmbedAllRequest>,
-) -> Result<(HeaderMap, Json<EmbedAllResponse>), (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(HeaderMap, Negotiated<EmbedAllResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     if let Some(context) = context {
         span.set_parent(context);
     }
 
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
     let start_time = Instant::now();
 
     let truncate = req.truncate.unwrap_or(info.auto_truncate);
@@ -1171,7 +2309,13 @@ async fn embed_all(
 
     tracing::info!("Success");
 
-    Ok((headers, Json(response)))
+    Ok((
+        headers,
+        Negotiated {
+            format,
+            body: response,
+        },
+    ))
 }
 
 /// OpenAI compatible route. Returns a 424 status code if the model is not an embedding model.
@@ -1199,32 +2343,35 @@ example = json ! ({"message": "Batch size error", "type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn openai_embed(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
     Extension(context): Extension<Option<opentelemetry::Context>>,
+    Extension(queue_headers): Extension<QueueHeaders>,
     Json(req): Json<OpenAICompatRequest>,
 ) -> Result<(HeaderMap, Json<OpenAICompatResponse>), (StatusCode, Json<OpenAICompatErrorResponse>)>
 {
-    let encode_embedding = |array: Vec<f32>| {
-        match req.encoding_format {
-            EncodingFormat::Float => Embedding::Float(array),
-            EncodingFormat::Base64 => {
-                // Unsafe is fine here since we do not violate memory ownership: bytes
-                // is only used in this scope and we return an owned string
-                let bytes = unsafe {
-                    std::slice::from_raw_parts(array.as_ptr() as *const u8, array.len() * 4)
-                };
-
-                Embedding::Base64(BASE64_STANDARD.encode(bytes))
-            }
-        }
-    };
-
     let span = tracing::Span::current();
     if let Some(context) = context {
         span.set_parent(context);
     }
 
+    let ServedModel {
+        infer,
+        info,
+        quantization_ranges,
+    } = models.get(req.model.as_deref())?;
+    let infer = queue_headers.apply(infer, req.priority, req.timeout)?;
+
+    let encode_embedding = |array: Vec<f32>| -> Result<Embedding, ErrorResponse> {
+        let embedding = quantize(array, req.precision, quantization_ranges.as_deref())?;
+        let embedding = match req.encoding_format {
+            EncodingFormat::Float => embedding.into(),
+            EncodingFormat::Base64 => {
+                Embedding::Base64(BASE64_STANDARD.encode(embedding.to_le_bytes()))
+            }
+        };
+        Ok(embedding)
+    };
+
     let start_time = Instant::now();
 
     let truncate = info.auto_truncate;
@@ -1252,7 +2399,7 @@ async fn openai_embed(
 
             metrics::counter!("te_request_success", "method" => "single").increment(1);
 
-            let embedding = encode_embedding(response.results);
+            let embedding = encode_embedding(response.results)?;
             (
                 vec![OpenAICompatEmbedding {
                     object: "embedding",
@@ -1351,7 +2498,7 @@ async fn openai_embed(
                 total_queue_time += r.metadata.queue.as_nanos() as u64;
                 total_inference_time += r.metadata.inference.as_nanos() as u64;
                 total_compute_tokens += r.metadata.prompt_tokens;
-                let embedding = encode_embedding(r.results);
+                let embedding = encode_embedding(r.results)?;
                 embeddings.push(OpenAICompatEmbedding {
                     object: "embedding",
                     embedding,
@@ -1415,10 +2562,11 @@ example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 )]
 #[instrument(skip_all)]
 async fn tokenize(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
     Json(req): Json<TokenizeRequest>,
 ) -> Result<Json<TokenizeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+
     let tokenize_inner = move |input: String,
                                add_special_tokens: bool,
                                prompt_name: Option<String>,
@@ -1453,7 +2601,7 @@ async fn tokenize(
 
     let tokens = match req.inputs {
         TokenizeInput::Single(input) => {
-            vec![tokenize_inner(input, req.add_special_tokens, req.prompt_name, infer.0).await?]
+            vec![tokenize_inner(input, req.add_special_tokens, req.prompt_name, infer).await?]
         }
         TokenizeInput::Batch(inputs) => {
             if inputs.is_empty() {
@@ -1490,7 +2638,7 @@ async fn tokenize(
                     input,
                     req.add_special_tokens,
                     req.prompt_name.clone(),
-                    infer.0.clone(),
+                    infer.clone(),
                 ));
             }
 
@@ -1521,10 +2669,11 @@ example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 )]
 #[instrument(skip_all)]
 async fn decode(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
     Json(req): Json<DecodeRequest>,
 ) -> Result<Json<DecodeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let ServedModel { infer, info, .. } = models.get(req.model.as_deref())?;
+
     let decode_inner = move |ids: Vec<u32>, skip_special_tokens: bool, infer: Infer| async move {
         let text = infer
             .decode(ids, skip_special_tokens)
@@ -1534,7 +2683,7 @@ async fn decode(
     };
 
     let texts = match req.ids {
-        InputIds::Single(ids) => vec![decode_inner(ids, req.skip_special_tokens, infer.0).await?],
+        InputIds::Single(ids) => vec![decode_inner(ids, req.skip_special_tokens, infer).await?],
         InputIds::Batch(ids) => {
             if ids.is_empty() {
                 let message = "`ids` cannot be empty".to_string();
@@ -1566,7 +2715,7 @@ async fn decode(
 
             let mut futures = Vec::with_capacity(batch_size);
             for ids in ids {
-                futures.push(decode_inner(ids, req.skip_special_tokens, infer.0.clone()));
+                futures.push(decode_inner(ids, req.skip_special_tokens, infer.clone()));
             }
 
             join_all(futures)
@@ -1600,72 +2749,99 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
 )]
 #[instrument(skip_all)]
 async fn vertex_compatibility(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
     context: Extension<Option<opentelemetry::Context>>,
+    queue_headers: Extension<QueueHeaders>,
     Json(req): Json<VertexRequest>,
 ) -> Result<Json<VertexResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let embed_future = move |infer: Extension<Infer>,
-                             info: Extension<Info>,
+    let embed_future = move |models: Extension<Models>,
                              context: Extension<Option<opentelemetry::Context>>,
+                             queue_headers: Extension<QueueHeaders>,
                              req: EmbedRequest| async move {
-        let result = embed(infer, info, context, Json(req)).await?;
-        Ok(VertexPrediction::Embed(result.1 .0))
+        let result = embed(
+            ResponseFormat::Json,
+            models,
+            context,
+            queue_headers,
+            Json(req),
+        )
+        .await?;
+        Ok(VertexPrediction::Embed(result.1.body))
     };
-    let embed_sparse_future = move |infer: Extension<Infer>,
-                                    info: Extension<Info>,
+    let embed_sparse_future = move |models: Extension<Models>,
                                     context: Extension<Option<opentelemetry::Context>>,
+                                    queue_headers: Extension<QueueHeaders>,
                                     req: EmbedSparseRequest| async move {
-        let result = embed_sparse(infer, info, context, Json(req)).await?;
-        Ok(VertexPrediction::EmbedSparse(result.1 .0))
+        let result = embed_sparse(
+            ResponseFormat::Json,
+            models,
+            context,
+            queue_headers,
+            Json(req),
+        )
+        .await?;
+        Ok(VertexPrediction::EmbedSparse(result.1.body))
     };
-    let predict_future = move |infer: Extension<Infer>,
-                               info: Extension<Info>,
+    let predict_future = move |models: Extension<Models>,
                                context: Extension<Option<opentelemetry::Context>>,
+                               queue_headers: Extension<QueueHeaders>,
                                req: PredictRequest| async move {
-        let result = predict(infer, info, context, Json(req)).await?;
+        let result = predict(models, context, queue_headers, Json(req)).await?;
         Ok(VertexPrediction::Predict(result.1 .0))
     };
-    let rerank_future = move |infer: Extension<Infer>,
-                              info: Extension<Info>,
+    let rerank_future = move |models: Extension<Models>,
                               context: Extension<Option<opentelemetry::Context>>,
+                              queue_headers: Extension<QueueHeaders>,
                               req: RerankRequest| async move {
-        let result = rerank(infer, info, context, Json(req)).await?;
+        let result = rerank(models, context, queue_headers, Json(req)).await?;
         Ok(VertexPrediction::Rerank(result.1 .0))
     };
 
     let mut futures = Vec::with_capacity(req.instances.len());
     for instance in req.instances {
-        let local_infer = infer.clone();
-        let local_info = info.clone();
+        let local_models = models.clone();
         let local_context = context.clone();
+        let local_queue_headers = queue_headers.clone();
 
         // Rerank is the only payload that can me matched safely
         if let Ok(instance) = serde_json::from_value::<RerankRequest>(instance.clone()) {
-            futures.push(rerank_future(local_infer, local_info, local_context, instance).boxed());
+            futures.push(
+                rerank_future(local_models, local_context, local_queue_headers, instance).boxed(),
+            );
             continue;
         }
 
-        match info.model_type {
+        // Each instance can target a different model
+        let served_model = models.get(instance.get("model").and_then(|m| m.as_str()))?;
+
+        match served_model.info.model_type {
             ModelType::Classifier(_) | ModelType::Reranker(_) => {
                 let instance = serde_json::from_value::<PredictRequest>(instance)
                     .map_err(ErrorResponse::from)?;
-                futures
-                    .push(predict_future(local_infer, local_info, local_context, instance).boxed());
+                futures.push(
+                    predict_future(local_models, local_context, local_queue_headers, instance)
+                        .boxed(),
+                );
             }
             ModelType::Embedding(_) => {
-                if infer.is_splade() {
+                if served_model.infer.is_splade() {
                     let instance = serde_json::from_value::<EmbedSparseRequest>(instance)
                         .map_err(ErrorResponse::from)?;
                     futures.push(
-                        embed_sparse_future(local_infer, local_info, local_context, instance)
-                            .boxed(),
+                        embed_sparse_future(
+                            local_models,
+                            local_context,
+                            local_queue_headers,
+                            instance,
+                        )
+                        .boxed(),
                     );
                 } else {
                     let instance = serde_json::from_value::<EmbedRequest>(instance)
                         .map_err(ErrorResponse::from)?;
                     futures.push(
-                        embed_future(local_infer, local_info, local_context, instance).boxed(),
+                        embed_future(local_models, local_context, local_queue_headers, instance)
+                            .boxed(),
                     );
                 }
             }
@@ -1680,6 +2856,218 @@ async fn vertex_compatibility(
     Ok(Json(VertexResponse { predictions }))
 }
 
+/// Submit a batch job. The input is a JSONL file of records, sent as the request body or read
+/// from `path` in `--jobs-input-dir`. Jobs run in the background, one at a time and with a low
+/// priority, and survive restarts.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/jobs",
+params(JobParameters),
+request_body(content = JobRecord, content_type = "application/x-ndjson"),
+responses(
+(status = 202, description = "Job queued", body = JobInfo),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "Model not found", "error_type": "not_found"})),
+(status = 413, description = "Invalid job", body = ErrorResponse,
+example = json ! ({"error": "`corpus.jsonl` is not a file in `/data`", "error_type": "validation"})),
+(status = 500, description = "Job could not be stored", body = ErrorResponse,
+example = json ! ({"error": "Failed to store job: No space left on device", "error_type": "internal"})),
+)
+)]
+#[instrument(skip_all)]
+async fn submit_job(
+    jobs: Extension<Jobs>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Query(parameters): Query<JobParameters>,
+    body: Body,
+) -> Result<(StatusCode, Json<JobInfo>), (StatusCode, Json<ErrorResponse>)> {
+    let tenant = tenant.as_ref().map(|tenant| tenant.name.as_str());
+    let info = jobs.submit(parameters, tenant, body).await?;
+    Ok((StatusCode::ACCEPTED, Json(info)))
+}
+
+/// List batch jobs, most recent first
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/jobs",
+responses(
+(status = 200, description = "Jobs", body = Vec<JobInfo>),
+)
+)]
+#[instrument(skip_all)]
+async fn list_jobs(
+    jobs: Extension<Jobs>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+) -> Result<Json<Vec<JobInfo>>, (StatusCode, Json<ErrorResponse>)> {
+    let tenant = tenant.as_ref().map(|tenant| tenant.name.as_str());
+    Ok(Json(jobs.list(tenant).await?))
+}
+
+/// Get the status and progress of a batch job
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/jobs/{id}",
+params(("id" = String, Path, description = "Job id")),
+responses(
+(status = 200, description = "Job", body = JobInfo),
+(status = 404, description = "Job not found", body = ErrorResponse,
+example = json ! ({"error": "Job `18f2a3c4d5e6f708-0` not found", "error_type": "not_found"})),
+)
+)]
+#[instrument(skip(jobs, tenant))]
+async fn get_job(
+    jobs: Extension<Jobs>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<JobInfo>, (StatusCode, Json<ErrorResponse>)> {
+    let tenant = tenant.as_ref().map(|tenant| tenant.name.as_str());
+    Ok(Json(jobs.get(&id, tenant).await?))
+}
+
+/// Download the results of a completed batch job as JSONL, `.npy` or Parquet
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/jobs/{id}/results",
+params(("id" = String, Path, description = "Job id"), JobResultsQuery),
+responses(
+(status = 200, description = "Results", body = JobResult, content_type = "application/x-ndjson"),
+(status = 404, description = "Job not found", body = ErrorResponse,
+example = json ! ({"error": "Job `18f2a3c4d5e6f708-0` not found", "error_type": "not_found"})),
+(status = 413, description = "Job not completed", body = ErrorResponse,
+example = json ! ({"error": "Job `18f2a3c4d5e6f708-0` is not completed", "error_type": "validation"})),
+)
+)]
+#[instrument(skip(jobs, tenant))]
+async fn get_job_results(
+    jobs: Extension<Jobs>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(query): Query<JobResultsQuery>,
+) -> Result<axum::response::Response, (StatusCode, Json<ErrorResponse>)> {
+    let tenant = tenant.as_ref().map(|tenant| tenant.name.as_str());
+    let path = jobs.results(&id, tenant, query.format).await?;
+    let file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|err| ErrorResponse {
+            error: format!("Failed to open results: {err}"),
+            error_type: ErrorType::Internal,
+        })?;
+
+    let (content_type, extension) = match query.format {
+        JobResultsFormat::Jsonl => ("application/x-ndjson", "jsonl"),
+        JobResultsFormat::Npy => ("application/octet-stream", "npy"),
+        JobResultsFormat::Parquet => ("application/vnd.apache.parquet", "parquet"),
+    };
+    let content_disposition = format!("attachment; filename=\"{id}.{extension}\"");
+
+    Ok((
+        [
+            (http::header::CONTENT_TYPE, content_type.to_string()),
+            (http::header::CONTENT_DISPOSITION, content_disposition),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/// Clear the embedding and score cache of all served models
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/admin/cache/clear",
+responses(
+(status = 200, description = "Cache cleared"),
+(status = 401, description = "Missing or invalid admin API key", body = ErrorResponse,
+example = json ! ({"error": "Missing or invalid API key", "error_type": "unauthorized"})),
+)
+)]
+#[instrument(skip(models))]
+async fn clear_cache(models: Extension<Models>) {
+    for model in models.iter() {
+        model.infer.clear_cache();
+    }
+    tracing::info!("Cache cleared");
+}
+
+/// Drain the router: readiness fails and new requests are rejected while the in flight
+/// requests complete, then the router shuts down
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/admin/drain",
+responses(
+(status = 202, description = "Draining"),
+(status = 401, description = "Missing or invalid admin API key", body = ErrorResponse,
+example = json ! ({"error": "Missing or invalid API key", "error_type": "unauthorized"})),
+(status = 429, description = "Already draining", body = ErrorResponse,
+example = json ! ({"error": "The router is draining", "error_type": "overloaded"})),
+)
+)]
+#[instrument(skip_all)]
+async fn drain(
+    lifecycle: Extension<Lifecycle>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    if !lifecycle.drain() {
+        Err(draining_error())?;
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+fn draining_error() -> ErrorResponse {
+    let counter = metrics::counter!("te_request_failure", "err" => "draining");
+    counter.increment(1);
+    ErrorResponse {
+        error: "The router is draining".to_string(),
+        error_type: ErrorType::Overloaded,
+    }
+}
+
+/// Load a model in the background and swap it with a served model of the same type once it is
+/// warmed up
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/admin/model",
+request_body = ModelSwapRequest,
+responses(
+(status = 202, description = "Model loading", body = ModelSwapStatus),
+(status = 401, description = "Missing or invalid admin API key", body = ErrorResponse,
+example = json ! ({"error": "Missing or invalid API key", "error_type": "unauthorized"})),
+(status = 404, description = "Replaced model not served", body = ErrorResponse,
+example = json ! ({"error": "Model `BAAI/bge-base-en-v1.5` is not served", "error_type": "not_found"})),
+(status = 429, description = "A swap is already running", body = ErrorResponse,
+example = json ! ({"error": "Model `BAAI/bge-large-en-v1.5` is already loading", "error_type": "overloaded"})),
+)
+)]
+#[instrument(skip_all)]
+async fn swap_model(
+    model_swap: Extension<ModelSwap>,
+    Json(req): Json<ModelSwapRequest>,
+) -> Result<(StatusCode, Json<ModelSwapStatus>), (StatusCode, Json<ErrorResponse>)> {
+    let status = model_swap.start(req)?;
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+/// Get the state of the last model swap
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/admin/model",
+responses(
+(status = 200, description = "Model swap state", body = ModelSwapStatus),
+(status = 401, description = "Missing or invalid admin API key", body = ErrorResponse,
+example = json ! ({"error": "Missing or invalid API key", "error_type": "unauthorized"})),
+)
+)]
+#[instrument(skip_all)]
+async fn get_model_swap(model_swap: Extension<ModelSwap>) -> Json<ModelSwapStatus> {
+    Json(model_swap.status())
+}
+
 /// Prometheus metrics scrape endpoint
 #[utoipa::path(
 get,
@@ -1691,31 +3079,291 @@ async fn metrics(prom_handle: Extension<PrometheusHandle>) -> String {
     prom_handle.render()
 }
 
+/// Authorize the request with the API keys file and charge its tokens to the tenant
+///
+/// Without API keys, the tenant is the client certificate of the connection, if any.
+async fn tenant_middleware(
+    State(api_keys): State<Option<ApiKeys>>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let tenant = match api_keys {
+        Some(api_keys) => {
+            let authorization = request
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|v| v.to_str().ok());
+
+            let path = request.uri().path();
+            api_keys
+                .authorize(authorization, path)
+                .map_err(|err| match err {
+                    AuthError::Unauthorized => {
+                        <(StatusCode, Json<ErrorResponse>)>::from(unauthorized_error())
+                            .into_response()
+                    }
+                    AuthError::Forbidden => {
+                        let err = ErrorResponse {
+                            error: format!("API key cannot call `{path}`"),
+                            error_type: ErrorType::Forbidden,
+                        };
+                        <(StatusCode, Json<ErrorResponse>)>::from(err).into_response()
+                    }
+                    AuthError::RateLimited(retry_after) => {
+                        let err = ErrorResponse {
+                            error: "Rate limit exceeded".to_string(),
+                            error_type: ErrorType::Overloaded,
+                        };
+                        let mut response =
+                            <(StatusCode, Json<ErrorResponse>)>::from(err).into_response();
+                        response.headers_mut().insert(
+                            http::header::RETRY_AFTER,
+                            HeaderValue::from(retry_after_secs(retry_after)),
+                        );
+                        response
+                    }
+                })?
+        }
+        None => {
+            let client_identity = request
+                .extensions()
+                .get::<TlsConnectInfo>()
+                .and_then(|info| info.client_identity.clone());
+            match client_identity {
+                Some(ClientIdentity(name)) => Tenant::identified(name),
+                None => return Ok(next.run(request).await),
+            }
+        }
+    };
+
+    // Streaming handlers charge the tokens of each record themselves
+    request.extensions_mut().insert(tenant.clone());
+    let span = tracing::info_span!("tenant", tenant = %tenant.name);
+    let response = next.run(request).instrument(span).await;
+
+    // Handlers report the number of tokens they computed in their headers
+    let compute_tokens = response
+        .headers()
+        .get("x-compute-tokens")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+    if let Some(compute_tokens) = compute_tokens {
+        tenant.consume_tokens(compute_tokens);
+    }
+
+    Ok(response)
+}
+
+/// Authorize the `/admin` routes with the `Authorization` header of `--admin-api-key`
+async fn admin_middleware(
+    State(admin_api_key): State<&'static str>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Result<axum::response::Response, (StatusCode, Json<ErrorResponse>)> {
+    match request.headers().get(AUTHORIZATION) {
+        Some(token) if token == admin_api_key => Ok(next.run(request).await),
+        _ => Err(unauthorized_error())?,
+    }
+}
+
+fn unauthorized_error() -> ErrorResponse {
+    ErrorResponse {
+        error: "Missing or invalid API key".to_string(),
+        error_type: ErrorType::Unauthorized,
+    }
+}
+
+/// Queue options of a request set by its headers
+#[derive(Debug, Clone, Copy, Default)]
+struct QueueHeaders {
+    /// `X-Priority` header
+    priority: Option<QueuePriority>,
+    /// `X-Request-Timeout` header, in milliseconds
+    timeout: Option<u64>,
+}
+
+impl QueueHeaders {
+    /// Infer handle queueing the request with its options. The body fields take precedence over
+    /// the headers.
+    fn apply(
+        self,
+        infer: Infer,
+        priority: Option<Priority>,
+        timeout: Option<u64>,
+    ) -> Result<Infer, ErrorResponse> {
+        let priority = priority
+            .map(QueuePriority::from)
+            .or(self.priority)
+            .unwrap_or_default();
+
+        let deadline = match timeout.or(self.timeout) {
+            Some(0) => {
+                let message = "`timeout` must be > 0".to_string();
+                tracing::error!("{message}");
+                let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                counter.increment(1);
+                return Err(ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                });
+            }
+            Some(timeout) => {
+                let deadline = Instant::now().checked_add(Duration::from_millis(timeout));
+                if deadline.is_none() {
+                    let message = format!("`timeout` {timeout} is too large");
+                    tracing::error!("{message}");
+                    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                    counter.increment(1);
+                    return Err(ErrorResponse {
+                        error: message,
+                        error_type: ErrorType::Validation,
+                    });
+                }
+                deadline
+            }
+            None => None,
+        };
+
+        Ok(infer.with_priority(priority).with_deadline(deadline))
+    }
+}
+
+/// Reject new requests while draining and track the in flight ones
+async fn drain_middleware(
+    State(lifecycle): State<Lifecycle>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Result<axum::response::Response, (StatusCode, Json<ErrorResponse>)> {
+    let Some(in_flight) = lifecycle.start_request() else {
+        Err(draining_error())?
+    };
+    // Streamed bodies are still in flight once the handler returned
+    let response = next.run(request).await;
+    Ok(response.map(|body| {
+        Body::new(InFlightBody {
+            body,
+            _in_flight: in_flight,
+        })
+    }))
+}
+
+/// Response body that keeps its request in flight until it is sent or dropped
+struct InFlightBody {
+    body: Body,
+    _in_flight: InFlight,
+}
+
+impl HttpBody for InFlightBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// Parse the queue options headers of the request
+async fn queue_headers_middleware(
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Result<axum::response::Response, (StatusCode, Json<ErrorResponse>)> {
+    fn parse<T: std::str::FromStr>(
+        request: &axum::extract::Request,
+        name: &str,
+    ) -> Result<Option<T>, ErrorResponse> {
+        let Some(value) = request.headers().get(name) else {
+            return Ok(None);
+        };
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                let message = format!("invalid `{name}` header: {value:?}");
+                tracing::error!("{message}");
+                let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                counter.increment(1);
+                ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                }
+            })
+    }
+
+    let queue_headers = QueueHeaders {
+        priority: parse(&request, "x-priority")?,
+        timeout: parse(&request, "x-request-timeout")?,
+    };
+
+    request.extensions_mut().insert(queue_headers);
+    Ok(next.run(request).await)
+}
+
 /// Serving method
 pub async fn run(
-    infer: Infer,
-    info: Info,
-    addr: SocketAddr,
-    prom_builder: PrometheusBuilder,
-    payload_limit: usize,
-    api_key: Option<String>,
-    cors_allow_origin: Option<Vec<String>>,
+    models: Models,
+    listeners: Vec<Listener>,
+    prom_handle: PrometheusHandle,
+    settings: ServerSettings,
+    api_keys: Option<ApiKeys>,
+    loader: ModelLoader,
+    lifecycle: Lifecycle,
 ) -> Result<(), anyhow::Error> {
+    let ServerSettings {
+        payload_limit,
+        api_key,
+        admin_api_key,
+        cors_allow_origin,
+        jobs_dir,
+        jobs_input_dir,
+        jobs_max_concurrency,
+        jobs_max_input_size,
+        tls_client_ca,
+        ..
+    } = settings;
+
     // OpenAPI documentation
     #[derive(OpenApi)]
     #[openapi(
     paths(
     get_model_info,
     health,
+    live,
+    ready,
     predict,
     rerank,
+    rerank_compat,
     embed,
+    embed_stream,
+    submit_job,
+    list_jobs,
+    get_job,
+    get_job_results,
     embed_all,
+    embed_late_chunking,
+    embed_multi_vector,
+    maxsim,
     embed_sparse,
     openai_embed,
     similarity,
     tokenize,
     decode,
+    clear_cache,
+    drain,
+    swap_model,
+    get_model_swap,
     metrics,
     ),
     components(
@@ -1723,6 +3371,7 @@ pub async fn run(
     PredictInput,
     Input,
     Info,
+    InfoResponse,
     ModelType,
     ClassifierModel,
     Embedding,
@@ -1743,15 +3392,44 @@ pub async fn run(
     RerankRequest,
     Rank,
     RerankResponse,
+    RerankCompatDocument,
+    RerankCompatRequest,
+    RerankCompatResult,
+    RerankCompatBilledUnits,
+    RerankCompatMeta,
+    RerankCompatResponse,
     EmbedRequest,
     EmbedResponse,
+    EmbedStreamRecord,
+    EmbedStreamResult,
+    JobParameters,
+    JobRecord,
+    JobResult,
+    JobStatus,
+    JobInfo,
+    JobResultsFormat,
+    ModelSwapRequest,
+    ModelSwapState,
+    ModelSwapStatus,
+    ChunkingParameters,
+    ChunkAggregation,
+    ChunkEmbedding,
+    EmbedLateChunkingRequest,
+    ChunkSpan,
+    EmbedLateChunkingResponse,
+    EmbedMultiVectorRequest,
+    MaxSimRequest,
+    Precision,
     ErrorResponse,
     OpenAICompatErrorResponse,
     TokenizeInput,
     TokenizeRequest,
     TokenizeResponse,
     TruncationDirection,
+    Priority,
     SimilarityInput,
+    SimilaritySource,
+    SimilarityMetric,
     SimilarityParameters,
     SimilarityRequest,
     SimilarityResponse,
@@ -1791,18 +3469,21 @@ pub async fn run(
         }
     });
 
-    // See: https://github.com/metrics-rs/metrics/issues/467#issuecomment-2022755151
-    let (recorder, _) = prom_builder
-        .build()
-        .context("failed to build prometheus recorder")?;
-    let prom_handle = recorder.handle();
-    metrics::set_global_recorder(recorder).context("Failed to set global recorder")?;
-
     // CORS layer
     let allow_origin = allow_origin.unwrap_or(AllowOrigin::any());
     let cors_layer = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST])
-        .allow_headers([http::header::CONTENT_TYPE])
+        .allow_headers([
+            http::header::CONTENT_TYPE,
+            http::HeaderName::from_static("x-priority"),
+            http::HeaderName::from_static("x-request-timeout"),
+            http::header::CONTENT_ENCODING,
+        ])
+        .expose_headers([
+            http::HeaderName::from_static(EMBEDDING_SHAPE),
+            http::HeaderName::from_static(EMBEDDING_DTYPE),
+            http::HeaderName::from_static(EMBEDDING_LENGTHS),
+        ])
         .allow_origin(allow_origin);
 
     // Define VertextApiDoc conditionally only if the "google" feature is enabled
@@ -1830,7 +3511,11 @@ pub async fn run(
         // Base routes
         .route("/info", get(get_model_info))
         .route("/embed", post(embed))
+        .route("/embed/stream", post(embed_stream))
         .route("/embed_all", post(embed_all))
+        .route("/embed_late_chunking", post(embed_late_chunking))
+        .route("/embed_multi_vector", post(embed_multi_vector))
+        .route("/maxsim", post(maxsim))
         .route("/embed_sparse", post(embed_sparse))
         .route("/predict", post(predict))
         .route("/rerank", post(rerank))
@@ -1840,13 +3525,35 @@ pub async fn run(
         // OpenAI compat route
         .route("/embeddings", post(openai_embed))
         .route("/v1/embeddings", post(openai_embed))
+        // Cohere and Jina compat routes
+        .route("/v1/rerank", post(rerank_compat))
+        .route("/v2/rerank", post(rerank_compat))
         // Vertex compat route
         .route("/vertex", post(vertex_compatibility));
 
+    if let Some(jobs_dir) = jobs_dir {
+        let jobs = Jobs::start(
+            jobs_dir.into(),
+            jobs_input_dir.map(Into::into),
+            jobs_max_concurrency,
+            jobs_max_input_size,
+            models.clone(),
+        )
+        .await?;
+        routes = routes
+            .route("/jobs", post(submit_job).get(list_jobs))
+            .route("/jobs/:id", get(get_job))
+            .route("/jobs/:id/results", get(get_job_results))
+            .layer(Extension(jobs));
+    }
+
     #[allow(unused_mut)]
     let mut public_routes = Router::new()
         // Base Health route
         .route("/health", get(health))
+        // Liveness and readiness probes
+        .route("/live", get(live))
+        .route("/ready", get(ready))
         // Inference API health route
         .route("/", get(health))
         // AWS Sagemaker health route
@@ -1870,8 +3577,8 @@ pub async fn run(
     }
     #[cfg(not(feature = "google"))]
     {
-        // Set default routes
-        routes = match &info.model_type {
+        // Set default routes for the default model
+        routes = match &models.default_model().info.model_type {
             ModelType::Classifier(_) => {
                 routes
                     .route("/", post(predict))
@@ -1905,6 +3612,16 @@ pub async fn run(
         };
     }
 
+    // Only the routes above read the queue options headers: a malformed header must not fail the
+    // probes
+    routes = routes.layer(axum::middleware::from_fn(queue_headers_middleware));
+
+    // Drain the routes above
+    routes = routes.layer(axum::middleware::from_fn_with_state(
+        lifecycle.clone(),
+        drain_middleware,
+    ));
+
     if let Some(api_key) = api_key {
         let prefix = format!("Bearer {}", api_key);
 
@@ -1919,43 +3636,313 @@ pub async fn run(
                     let response = next.run(request).await;
                     Ok(response)
                 }
-                _ => Err(StatusCode::UNAUTHORIZED),
+                _ => Err(<(StatusCode, Json<ErrorResponse>)>::from(
+                    unauthorized_error(),
+                )),
             }
         };
 
         routes = routes.layer(axum::middleware::from_fn(auth));
     }
 
+    // Client certificates identify the tenant when there are no API keys
+    let client_auth = tls_client_ca.is_some();
+    if api_keys.is_some() || client_auth {
+        routes = routes.layer(axum::middleware::from_fn_with_state(
+            api_keys,
+            tenant_middleware,
+        ));
+    }
+
+    // The admin routes are only served with their own api key. They are not drained so that
+    // `/admin/drain` can still answer while draining.
+    let admin_routes = match admin_api_key {
+        Some(admin_api_key) => {
+            // Leak to share with the middleware
+            let admin_api_key: &'static str = format!("Bearer {admin_api_key}").leak();
+            Router::new()
+                .route("/admin/cache/clear", post(clear_cache))
+                .route("/admin/drain", post(drain))
+                .route("/admin/model", post(swap_model).get(get_model_swap))
+                .layer(axum::middleware::from_fn_with_state(
+                    admin_api_key,
+                    admin_middleware,
+                ))
+        }
+        None => Router::new(),
+    };
+
     let app = Router::new()
         .merge(SwaggerUi::new("/docs").url("/api-doc/openapi.json", doc))
         .merge(routes)
+        .merge(admin_routes)
         .merge(public_routes)
-        .layer(Extension(infer))
-        .layer(Extension(info))
+        .layer(Extension(ModelSwap::new(models.clone(), loader)))
+        .layer(Extension(PayloadLimit(payload_limit)))
+        .layer(Extension(lifecycle.clone()))
+        .layer(Extension(models))
         .layer(Extension(prom_handle.clone()))
         .layer(OtelAxumLayer::default())
         .layer(axum::middleware::from_fn(
             logging::http::trace_context_middleware,
         ))
+        // `payload_limit` applies to the decompressed body
         .layer(DefaultBodyLimit::max(payload_limit))
+        .layer(RequestDecompressionLayer::new())
+        // Streamed responses are sent uncompressed: the encoder would hold their lines back
+        .layer(CompressionLayer::new().compress_when(
+            DefaultPredicate::new().and(NotForContentType::const_new("application/x-ndjson")),
+        ))
         .layer(cors_layer);
 
-    // Run server
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .context(format!("Could not bind TCP Listener on {addr}"))?;
-
-    tracing::info!("Starting HTTP server: {}", &addr);
+    // Run server on the listeners of the probes
+    for listener in &listeners {
+        match listener {
+            Listener::Tcp(listener, Some(_)) => {
+                tracing::info!("Starting HTTPS server: {}", listener.local_addr()?);
+            }
+            Listener::Tcp(listener, None) => {
+                tracing::info!("Starting HTTP server: {}", listener.local_addr()?);
+            }
+            #[cfg(unix)]
+            Listener::Unix(_, socket_file) => {
+                tracing::info!("Starting HTTP server: {}", socket_file.path().display());
+            }
+        }
+    }
     tracing::info!("Ready");
 
-    axum::serve(listener, app)
+    tokio::select! {
         // Wait until all requests are finished to shut down
-        .with_graceful_shutdown(shutdown::shutdown_signal())
-        .await?;
+        result = serve(listeners, app, lifecycle.clone().drained()) => result?,
+        _ = lifecycle.deadline() => {},
+    }
+
+    Ok(())
+}
+
+/// Listener of the HTTP server
+pub(crate) enum Listener {
+    /// TCP listener, with the TLS acceptor of `--tls-cert`
+    Tcp(tokio::net::TcpListener, Option<TlsAcceptor>),
+    /// Unix socket of `--listen-uds`, removed once the listener is dropped
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, SocketFile),
+}
+
+/// Connection accepted by a `Listener`, before its TLS handshake
+enum Accepted {
+    Tcp(tokio::net::TcpStream, SocketAddr),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+impl Listener {
+    async fn accept(&self) -> std::io::Result<Accepted> {
+        match self {
+            Listener::Tcp(listener, _) => listener
+                .accept()
+                .await
+                .map(|(stream, remote_addr)| Accepted::Tcp(stream, remote_addr)),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener
+                .accept()
+                .await
+                .map(|(stream, _)| Accepted::Unix(stream)),
+        }
+    }
+}
+
+/// Bind the TCP listener on `addr` and the Unix socket `uds`
+pub(crate) async fn bind(
+    addr: Option<SocketAddr>,
+    uds: Option<&UnixSocket>,
+    tls: Option<TlsFiles>,
+) -> Result<Vec<Listener>, anyhow::Error> {
+    let mut listeners = Vec::with_capacity(2);
+    if let Some(addr) = addr {
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .context(format!("Could not bind TCP Listener on {addr}"))?;
+        let acceptor = tls
+            .map(|tls| TlsAcceptor::new(tls, &[b"h2", b"http/1.1"]))
+            .transpose()?;
+        listeners.push(Listener::Tcp(listener, acceptor));
+    }
+    #[cfg(unix)]
+    if let Some(uds) = uds {
+        let (listener, socket_file) = uds.bind()?;
+        listeners.push(Listener::Unix(listener, socket_file));
+    }
+    // `--listen-uds` is rejected on other platforms
+    #[cfg(not(unix))]
+    let _ = uds;
+    Ok(listeners)
+}
 
+/// Serve `app` on all `listeners` until `shutdown` resolves and the open connections are closed
+async fn serve(
+    listeners: Vec<Listener>,
+    app: Router,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<(), anyhow::Error> {
+    let shutdown = shutdown.shared();
+    let servers = listeners.into_iter().map(|listener| {
+        let app = app.clone();
+        let shutdown = shutdown.clone();
+        async move {
+            match listener {
+                Listener::Tcp(listener, None) => {
+                    axum::serve(listener, app)
+                        .with_graceful_shutdown(shutdown)
+                        .await?
+                }
+                listener => {
+                    let closed = serve_connections(&listener, app, shutdown).await;
+                    // Stop accepting connections and wait for the open ones to close
+                    drop(listener);
+                    closed.await;
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        }
+    });
+    futures::future::try_join_all(servers).await?;
     Ok(())
 }
 
+/// Serve `app` on the connections of `listener` until `shutdown` resolves. Returns a future
+/// that resolves once the open connections are closed.
+///
+/// `axum::serve` only accepts plain TCP listeners: this is the same loop, with a TLS handshake on
+/// TLS listeners, that adds the connection information to the extensions of each request.
+async fn serve_connections(
+    listener: &Listener,
+    app: Router,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> impl std::future::Future<Output = ()> {
+    let (signal_sender, signal_receiver) = tokio::sync::watch::channel(());
+    let (close_sender, close_receiver) = tokio::sync::watch::channel(());
+    tokio::pin!(shutdown);
+
+    let acceptor = match listener {
+        Listener::Tcp(_, acceptor) => acceptor.clone(),
+        #[cfg(unix)]
+        Listener::Unix(..) => None,
+    };
+
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    tracing::error!("Failed to accept connection: {err}");
+                    // Errors such as running out of file descriptors would spin the loop, axum also
+                    // waits before accepting again
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let signal_receiver = signal_receiver.clone();
+        let close_receiver = close_receiver.clone();
+
+        tokio::spawn(async move {
+            let result = match (accepted, acceptor) {
+                (Accepted::Tcp(stream, remote_addr), Some(acceptor)) => {
+                    let Some(connection) = acceptor.accept(stream, remote_addr).await else {
+                        return;
+                    };
+                    let connect_info = connection.connect_info();
+                    serve_connection(connection, Some(connect_info), app, signal_receiver).await
+                }
+                (Accepted::Tcp(stream, _), None) => {
+                    serve_connection(stream, None, app, signal_receiver).await
+                }
+                #[cfg(unix)]
+                (Accepted::Unix(stream), _) => {
+                    serve_connection(stream, None, app, signal_receiver).await
+                }
+            };
+            if let Err(err) = result {
+                tracing::debug!("Failed to serve connection: {err}");
+            }
+            drop(close_receiver);
+        });
+    }
+
+    // Finish the open connections
+    drop(signal_receiver);
+    let _ = signal_sender.send(());
+    drop(close_receiver);
+    async move {
+        close_sender.closed().await;
+    }
+}
+
+/// Serve the requests of a single connection, and finish its in flight requests once `signal`
+/// changes
+async fn serve_connection<I>(
+    io: I,
+    connect_info: Option<TlsConnectInfo>,
+    app: Router,
+    mut signal: tokio::sync::watch::Receiver<()>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let service =
+        hyper::service::service_fn(move |mut request: http::Request<hyper::body::Incoming>| {
+            if let Some(connect_info) = &connect_info {
+                request.extensions_mut().insert(connect_info.clone());
+            }
+            tower::Service::call(&mut app.clone(), request)
+        });
+    let builder =
+        hyper_util::server::conn::auto::Builder::new(hyper_util::rt::TokioExecutor::new());
+    let connection =
+        builder.serve_connection_with_upgrades(hyper_util::rt::TokioIo::new(io), service);
+    tokio::pin!(connection);
+
+    tokio::select! {
+        result = connection.as_mut() => result,
+        _ = signal.changed() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    }
+}
+
+/// Answer the liveness and readiness probes on `listeners` until `stop` resolves
+///
+/// Returns the listeners for the server: they stay bound, so the connections that arrive in
+/// between wait in their backlog instead of being refused.
+pub(crate) async fn serve_probes(
+    listeners: Vec<Listener>,
+    stop: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Vec<Listener> {
+    let app = Router::new()
+        .route("/live", get(live))
+        .route("/ready", get(loading))
+        .route("/health", get(loading))
+        .route("/", get(loading))
+        .route("/ping", get(loading));
+
+    let stop = stop.shared();
+    let closed = join_all(
+        listeners
+            .iter()
+            .map(|listener| serve_connections(listener, app.clone(), stop.clone())),
+    )
+    .await;
+    join_all(closed).await;
+    listeners
+}
+
 impl From<&ErrorType> for StatusCode {
     fn from(value: &ErrorType) -> Self {
         match value {
@@ -1965,6 +3952,11 @@ impl From<&ErrorType> for StatusCode {
             ErrorType::Tokenizer => StatusCode::UNPROCESSABLE_ENTITY,
             ErrorType::Validation => StatusCode::PAYLOAD_TOO_LARGE,
             ErrorType::Empty => StatusCode::BAD_REQUEST,
+            ErrorType::NotFound => StatusCode::NOT_FOUND,
+            ErrorType::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorType::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorType::Forbidden => StatusCode::FORBIDDEN,
+            ErrorType::Internal => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -1,11 +1,14 @@
-use crate::ErrorType;
+use crate::quantization::{quantize, Precision, QuantizationRanges, QuantizedEmbedding};
+use crate::similarity::SimilarityMetric;
+use crate::{ErrorResponse, ErrorType, Info};
 use serde::de::{SeqAccess, Visitor};
 use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::json;
 use std::fmt::Formatter;
+use text_embeddings_core::chunking::Chunk;
 use text_embeddings_core::tokenization::EncodingInput;
 use utoipa::openapi::{RefOr, Schema};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Debug)]
 pub(crate) enum Sequence {
@@ -193,7 +196,7 @@ impl<'__s> ToSchema<'__s> for PredictInput {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema, Eq, Default)]
 pub(crate) enum TruncationDirection {
     #[serde(alias = "left", alias = "Left")]
     Left,
@@ -202,6 +205,25 @@ pub(crate) enum TruncationDirection {
     Right,
 }
 
+/// Queue priority class of a request
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Priority {
+    High,
+    Normal,
+    Low,
+}
+
+impl From<Priority> for text_embeddings_core::queue::Priority {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::High => Self::High,
+            Priority::Normal => Self::Normal,
+            Priority::Low => Self::Low,
+        }
+    }
+}
+
 impl From<TruncationDirection> for tokenizers::TruncationDirection {
     fn from(value: TruncationDirection) -> Self {
         match value {
@@ -222,6 +244,18 @@ pub(crate) struct PredictRequest {
     #[serde(default)]
     #[schema(default = "false", example = "false")]
     pub raw_scores: bool,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -260,13 +294,29 @@ pub(crate) struct RerankRequest {
     /// Custom instruction for reranking (e.g., "Select only semantically similar documents")
     /// Used with models that support templated prompts like Qwen3 rerankers
     #[serde(default)]
-    #[schema(default = "null", example = "Select only the Documents that are semantically similar to the Query.", nullable = true)]
+    #[schema(
+        default = "null",
+        example = "Select only the Documents that are semantically similar to the Query.",
+        nullable = true
+    )]
     pub instruction: Option<String>,
     /// Whether to use the model's chat template for formatting.  
     /// Defaults to true for models that support it (e.g., Qwen3 rerankers)
     #[serde(default)]
     #[schema(default = "null", example = "true", nullable = true)]
     pub use_template: Option<bool>,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -283,6 +333,114 @@ pub(crate) struct Rank {
 #[derive(Serialize, ToSchema)]
 pub(crate) struct RerankResponse(pub Vec<Rank>);
 
+#[derive(Deserialize, Serialize, ToSchema, Clone)]
+#[serde(untagged)]
+pub(crate) enum RerankCompatDocument {
+    #[schema(example = "Deep Learning is ...")]
+    Text(String),
+    #[schema(example = json!({"text": "Deep Learning is ..."}))]
+    Object(serde_json::Value),
+}
+
+impl RerankCompatDocument {
+    /// Text of the document that is ranked
+    ///
+    /// Object documents are ranked on their `text` field, or on the `rank_fields` if they are set.
+    pub(crate) fn text(&self, rank_fields: Option<&[String]>) -> Result<String, ErrorResponse> {
+        let field = |object: &serde_json::Map<String, serde_json::Value>, name: &str| match object
+            .get(name)
+        {
+            Some(serde_json::Value::String(value)) => Ok(value.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(ErrorResponse {
+                error: format!("document is missing the `{name}` field"),
+                error_type: ErrorType::Validation,
+            }),
+        };
+
+        match self {
+            RerankCompatDocument::Text(text) => Ok(text.clone()),
+            RerankCompatDocument::Object(serde_json::Value::Object(object)) => match rank_fields {
+                Some(rank_fields) => Ok(rank_fields
+                    .iter()
+                    .map(|name| Ok(format!("{name}: {}", field(object, name)?)))
+                    .collect::<Result<Vec<_>, ErrorResponse>>()?
+                    .join("\n")),
+                None => field(object, "text"),
+            },
+            RerankCompatDocument::Object(_) => Err(ErrorResponse {
+                error: "documents must be strings or objects".to_string(),
+                error_type: ErrorType::Validation,
+            }),
+        }
+    }
+
+    /// Document returned to the client: strings are wrapped in a `text` field
+    pub(crate) fn into_value(self) -> serde_json::Value {
+        match self {
+            RerankCompatDocument::Text(text) => json!({ "text": text }),
+            RerankCompatDocument::Object(object) => object,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct RerankCompatRequest {
+    #[schema(example = "What is Deep Learning?")]
+    pub query: String,
+    pub documents: Vec<RerankCompatDocument>,
+    /// The number of most relevant documents to return. Defaults to all the documents.
+    #[serde(default)]
+    #[schema(default = "null", example = "3", nullable = true)]
+    pub top_n: Option<usize>,
+    /// Whether the documents should be returned with the results.
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub return_documents: bool,
+    /// The fields of object documents that are ranked. Defaults to the `text` field.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub rank_fields: Option<Vec<String>>,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RerankCompatResult {
+    #[schema(example = "0")]
+    pub index: usize,
+    #[schema(example = "0.99")]
+    pub relevance_score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true, example = json!({"text": "Deep Learning is ..."}), default = "null")]
+    pub document: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RerankCompatBilledUnits {
+    #[schema(example = "512")]
+    pub input_tokens: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RerankCompatMeta {
+    pub billed_units: RerankCompatBilledUnits,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RerankCompatResponse {
+    pub results: Vec<RerankCompatResult>,
+    pub meta: RerankCompatMeta,
+}
+
 #[derive(Deserialize, ToSchema, Debug)]
 #[serde(untagged)]
 pub(crate) enum InputType {
@@ -326,7 +484,6 @@ pub(crate) enum EncodingFormat {
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct OpenAICompatRequest {
     pub input: Input,
-    #[allow(dead_code)]
     #[schema(nullable = true, example = "null")]
     pub model: Option<String>,
     #[allow(dead_code)]
@@ -337,15 +494,46 @@ pub(crate) struct OpenAICompatRequest {
     pub encoding_format: EncodingFormat,
     #[schema(default = "null", example = "null", nullable = true)]
     pub dimensions: Option<usize>,
+
+    /// The precision of the output embeddings. Scalar quantization (`int8`, `uint8`) uses the
+    /// calibration ranges of the model if it ships a `quantization_ranges.json` file, and the
+    /// `[-1, 1]` range of normalized embeddings otherwise. Binary quantization (`binary`,
+    /// `ubinary`) packs the sign bits of 8 dimensions in each value.
+    #[serde(default, alias = "embedding_type")]
+    #[schema(default = "float", example = "float")]
+    pub precision: Precision,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
 #[serde(untagged)]
 pub(crate) enum Embedding {
     Float(Vec<f32>),
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
     Base64(String),
 }
 
+impl From<QuantizedEmbedding> for Embedding {
+    fn from(value: QuantizedEmbedding) -> Self {
+        match value {
+            QuantizedEmbedding::Float(e) => Embedding::Float(e),
+            QuantizedEmbedding::Float16(e) => {
+                Embedding::Float(e.into_iter().map(|v| v.to_f32()).collect())
+            }
+            QuantizedEmbedding::Int8(e) => Embedding::Int8(e),
+            QuantizedEmbedding::Uint8(e) => Embedding::Uint8(e),
+        }
+    }
+}
+
 #[derive(Serialize, ToSchema)]
 pub(crate) struct OpenAICompatEmbedding {
     #[schema(example = "embedding")]
@@ -374,15 +562,29 @@ pub(crate) struct OpenAICompatResponse {
     pub usage: OpenAICompatUsage,
 }
 
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum SimilaritySource {
+    #[schema(example = "What is Deep Learning?")]
+    Single(String),
+    #[schema(example = json!(["What is Deep Learning?", "What is Machine Learning?"]))]
+    Batch(Vec<String>),
+}
+
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct SimilarityInput {
     /// The string that you wish to compare the other strings with. This can be a phrase, sentence,
     /// or longer passage, depending on the model being used.
+    ///
+    /// If a list of strings is given, the full similarity matrix between the sources and the
+    /// sentences is returned.
     #[schema(example = "What is Deep Learning?")]
-    pub source_sentence: String,
-    /// A list of strings which will be compared against the source_sentence.
-    #[schema(example = json!(["What is Machine Learning?"]))]
-    pub sentences: Vec<String>,
+    pub source_sentence: SimilaritySource,
+    /// A list of strings which will be compared against the source_sentence. If not set with a
+    /// list of sources, the sources are compared against each other.
+    #[serde(default)]
+    #[schema(example = json!(["What is Machine Learning?"]), nullable = true)]
+    pub sentences: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, ToSchema, Default)]
@@ -403,6 +605,11 @@ pub(crate) struct SimilarityParameters {
     /// any text to encode.
     #[schema(default = "null", example = "null", nullable = true)]
     pub prompt_name: Option<String>,
+    /// The similarity metric. `euclidean` and `manhattan` are distances: lower values mean more
+    /// similar sentences.
+    #[serde(default)]
+    #[schema(default = "cosine", example = "cosine")]
+    pub metric: SimilarityMetric,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -411,11 +618,27 @@ pub(crate) struct SimilarityRequest {
     /// Additional inference parameters for Sentence Similarity
     #[schema(default = "null", example = "null", nullable = true)]
     pub parameters: Option<SimilarityParameters>,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
+#[serde(untagged)]
 #[schema(example = json!([0.0, 1.0, 0.5]))]
-pub(crate) struct SimilarityResponse(pub Vec<f32>);
+pub(crate) enum SimilarityResponse {
+    Scores(Vec<f32>),
+    Matrix(Vec<Vec<f32>>),
+}
 
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct EmbedRequest {
@@ -449,15 +672,337 @@ pub(crate) struct EmbedRequest {
     /// shape of the representation will be returned instead.
     #[schema(default = "null", example = "null", nullable = true)]
     pub dimensions: Option<usize>,
+
+    /// The precision of the output embeddings. Scalar quantization (`int8`, `uint8`) uses the
+    /// calibration ranges of the model if it ships a `quantization_ranges.json` file, and the
+    /// `[-1, 1]` range of normalized embeddings otherwise. Binary quantization (`binary`,
+    /// `ubinary`) packs the sign bits of 8 dimensions in each value.
+    #[serde(default, alias = "embedding_type")]
+    #[schema(default = "float", example = "float")]
+    pub precision: Precision,
+
+    /// Split inputs in token windows that are embedded separately. Inputs longer than the
+    /// maximum input length of the model are not truncated when set. The chunks of all the
+    /// inputs count against the maximum client batch size.
+    #[serde(default)]
+    #[schema(default = "null", nullable = true)]
+    pub chunking: Option<ChunkingParameters>,
+
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
 }
 
 fn default_normalize() -> bool {
     true
 }
 
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+pub(crate) struct ChunkingParameters {
+    /// The maximum number of tokens of each chunk, special and prompt tokens included.
+    /// Defaults to the maximum input length of the model.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub max_tokens: Option<usize>,
+
+    /// The number of tokens shared by two consecutive chunks.
+    #[serde(default)]
+    #[schema(default = "0", example = "32")]
+    pub stride: usize,
+
+    /// How the chunk embeddings of an input are combined in a single embedding. If not set, the
+    /// embedding of every chunk is returned with its character offsets in the input.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub aggregation: Option<ChunkAggregation>,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChunkAggregation {
+    /// Average of the chunk embeddings
+    Mean,
+    /// Average of the chunk embeddings weighted by their number of tokens
+    Weighted,
+}
+
 #[derive(Serialize, ToSchema)]
+pub(crate) struct ChunkEmbedding {
+    #[schema(example = json!([0.0, 1.0, 2.0]))]
+    pub embedding: Embedding,
+    /// Character offset of the first character of the chunk in the input
+    #[schema(example = "0")]
+    pub start: usize,
+    /// Character offset after the last character of the chunk in the input
+    #[schema(example = "2048")]
+    pub end: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
 #[schema(example = json!([[0.0, 1.0, 2.0]]))]
-pub(crate) struct EmbedResponse(pub Vec<Vec<f32>>);
+pub(crate) enum EmbedResponse {
+    Float(Vec<Vec<f32>>),
+    Int8(Vec<Vec<i8>>),
+    Uint8(Vec<Vec<u8>>),
+    Chunked(Vec<Vec<ChunkEmbedding>>),
+}
+
+impl EmbedResponse {
+    /// Convert the float embeddings to the requested precision
+    pub(crate) fn new(
+        embeddings: Vec<Vec<f32>>,
+        precision: Precision,
+        ranges: Option<&QuantizationRanges>,
+    ) -> Result<Self, ErrorResponse> {
+        let mut response = match precision {
+            Precision::Float | Precision::Float16 => {
+                EmbedResponse::Float(Vec::with_capacity(embeddings.len()))
+            }
+            Precision::Int8 | Precision::Binary => {
+                EmbedResponse::Int8(Vec::with_capacity(embeddings.len()))
+            }
+            Precision::Uint8 | Precision::Ubinary => {
+                EmbedResponse::Uint8(Vec::with_capacity(embeddings.len()))
+            }
+        };
+
+        for embedding in embeddings {
+            match (&mut response, quantize(embedding, precision, ranges)?) {
+                (EmbedResponse::Float(r), QuantizedEmbedding::Float(e)) => r.push(e),
+                (EmbedResponse::Float(r), QuantizedEmbedding::Float16(e)) => {
+                    r.push(e.into_iter().map(|v| v.to_f32()).collect())
+                }
+                (EmbedResponse::Int8(r), QuantizedEmbedding::Int8(e)) => r.push(e),
+                (EmbedResponse::Uint8(r), QuantizedEmbedding::Uint8(e)) => r.push(e),
+                _ => unreachable!("quantized embedding does not match the requested precision"),
+            }
+        }
+        Ok(response)
+    }
+
+    /// Convert the chunk embeddings of each input to the requested precision
+    pub(crate) fn chunked(
+        inputs: Vec<Vec<(Vec<f32>, Chunk)>>,
+        precision: Precision,
+        ranges: Option<&QuantizationRanges>,
+    ) -> Result<Self, ErrorResponse> {
+        let inputs = inputs
+            .into_iter()
+            .map(|chunks| {
+                chunks
+                    .into_iter()
+                    .map(|(embedding, chunk)| {
+                        Ok(ChunkEmbedding {
+                            embedding: quantize(embedding, precision, ranges)?.into(),
+                            start: chunk.start,
+                            end: chunk.end,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, ErrorResponse>>()
+            })
+            .collect::<Result<Vec<_>, ErrorResponse>>()?;
+        Ok(EmbedResponse::Chunked(inputs))
+    }
+}
+
+/// Record of an `/embed/stream` request body, one JSON object per line
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedStreamRecord {
+    /// Identifier of the record, returned with its result
+    #[schema(value_type = String, example = "doc-1")]
+    pub id: serde_json::Value,
+
+    #[schema(example = "What is Deep Learning?")]
+    pub inputs: String,
+
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+
+    #[serde(default = "default_normalize")]
+    #[schema(default = "true", example = "true")]
+    pub normalize: bool,
+
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub dimensions: Option<usize>,
+
+    #[serde(default, alias = "embedding_type")]
+    #[schema(default = "float", example = "float")]
+    pub precision: Precision,
+
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the record. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the record in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
+}
+
+/// Result of a record of an `/embed/stream` request, one JSON object per line
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum EmbedStreamResult {
+    Embedding {
+        #[schema(value_type = String, example = "doc-1")]
+        id: serde_json::Value,
+        #[schema(example = json!([0.0, 1.0, 2.0]))]
+        embedding: Embedding,
+    },
+    Error {
+        /// `null` if the record could not be parsed
+        #[schema(value_type = String, example = "doc-1", nullable = true)]
+        id: serde_json::Value,
+        #[schema(example = "Input validation error")]
+        error: String,
+        error_type: ErrorType,
+    },
+}
+
+/// Parameters of a `/jobs` submission, passed in the query string
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct JobParameters {
+    /// Path of a JSONL file in the `--jobs-input-dir` directory, relative to it or absolute. If
+    /// not set, the request body is the JSONL file.
+    #[serde(default)]
+    #[schema(default = "null", example = "corpus.jsonl", nullable = true)]
+    pub path: Option<String>,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+    #[serde(default = "default_normalize")]
+    #[schema(default = "true", example = "true")]
+    pub normalize: bool,
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub dimensions: Option<usize>,
+    /// Loopback URL notified with a POST of the job once it completes or fails
+    #[serde(default)]
+    #[schema(
+        default = "null",
+        example = "http://localhost:8000/done",
+        nullable = true
+    )]
+    pub webhook: Option<String>,
+}
+
+/// Record of a job input file, one JSON object per line
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct JobRecord {
+    /// Identifier of the record, returned with its result
+    #[schema(value_type = String, example = "doc-1")]
+    pub id: serde_json::Value,
+    #[schema(example = "What is Deep Learning?")]
+    pub inputs: String,
+}
+
+/// Result of a job record, one JSON object per line of the results file
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct JobResult {
+    #[schema(value_type = String, example = "doc-1")]
+    pub id: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!([0.0, 1.0, 2.0]), nullable = true)]
+    pub embedding: Option<Vec<f32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Input validation error", nullable = true)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// State of a job
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct JobInfo {
+    #[schema(example = "18f2a3c4d5e6f708-0")]
+    pub id: String,
+    pub status: JobStatus,
+    pub parameters: JobParameters,
+    /// Tenant that submitted the job, the only one that can see it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "null", nullable = true)]
+    pub tenant: Option<String>,
+    /// Number of records of the input file, known once the job started
+    #[schema(example = "1000", nullable = true)]
+    pub total: Option<usize>,
+    /// Number of records with a result
+    #[schema(example = "250")]
+    pub processed: usize,
+    /// Number of records whose result is an error
+    #[schema(example = "0")]
+    pub failed: usize,
+    /// Reason of the failure of the job
+    #[schema(example = "null", nullable = true)]
+    pub error: Option<String>,
+    /// Unix timestamp of the submission
+    #[schema(example = "1735689600")]
+    pub created_at: u64,
+    /// Unix timestamp of the completion or failure
+    #[schema(example = "null", nullable = true)]
+    pub finished_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum JobResultsFormat {
+    /// One `JobResult` per line
+    #[default]
+    Jsonl,
+    /// `float32` matrix with one row per record. Rows of failed records are `NaN`.
+    Npy,
+    /// Table with `id`, `embedding` and `error` columns
+    Parquet,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct JobResultsQuery {
+    #[serde(default)]
+    #[param(inline)]
+    pub format: JobResultsFormat,
+}
 
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct EmbedSparseRequest {
@@ -479,6 +1024,18 @@ pub(crate) struct EmbedSparseRequest {
     /// any text to encode.
     #[schema(default = "null", example = "null", nullable = true)]
     pub prompt_name: Option<String>,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -510,12 +1067,164 @@ pub(crate) struct EmbedAllRequest {
     /// any text to encode.
     #[schema(default = "null", example = "null", nullable = true)]
     pub prompt_name: Option<String>,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
 #[schema(example = json!([[[0.0, 1.0, 2.0]]]))]
 pub(crate) struct EmbedAllResponse(pub Vec<Vec<Vec<f32>>>);
 
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedLateChunkingRequest {
+    /// The document to embed. It must fit in the maximum input length of the model.
+    #[schema(example = "Berlin is the capital of Germany. It has 3.8 million inhabitants.")]
+    pub inputs: String,
+
+    /// The character spans of the chunks in `inputs`. Cannot be set with `separator`.
+    #[serde(default)]
+    #[schema(default = "null", example = json!([{"start": 0, "end": 33}, {"start": 34, "end": 65}]), nullable = true)]
+    pub spans: Option<Vec<ChunkSpan>>,
+
+    /// The separator between the chunks of `inputs`. Blank chunks are skipped. Cannot be set
+    /// with `spans`.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub separator: Option<String>,
+
+    /// The name of the prompt that should be used by for encoding. If not set, no prompt
+    /// will be applied.
+    ///
+    /// Must be a key in the `sentence-transformers` configuration `prompts` dictionary.
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+
+    #[serde(default = "default_normalize")]
+    #[schema(default = "true", example = "true")]
+    pub normalize: bool,
+
+    /// The precision of the output embeddings.
+    #[serde(default, alias = "embedding_type")]
+    #[schema(default = "float", example = "float")]
+    pub precision: Precision,
+
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+pub(crate) struct ChunkSpan {
+    /// Character offset of the first character of the chunk
+    #[schema(example = "0")]
+    pub start: usize,
+    /// Character offset after the last character of the chunk
+    #[schema(example = "33")]
+    pub end: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct EmbedLateChunkingResponse(pub Vec<ChunkEmbedding>);
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedMultiVectorRequest {
+    pub inputs: Input,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    /// The name of the prompt that should be used by for encoding. If not set, no prompt
+    /// will be applied.
+    ///
+    /// Must be a key in the `sentence-transformers` configuration `prompts` dictionary.
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+    /// Whether the inputs are queries. Queries get the query marker of the model and are padded
+    /// with `[MASK]` tokens up to its query length, documents get the document marker.
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub is_query: bool,
+    /// Whether the punctuation tokens should be skipped, as ColBERT does for documents but not
+    /// for queries.
+    #[serde(default = "default_skip_punctuation")]
+    #[schema(default = "true", example = "true")]
+    pub skip_punctuation: bool,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
+}
+
+fn default_skip_punctuation() -> bool {
+    true
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct MaxSimRequest {
+    #[schema(example = "What is Deep Learning?")]
+    pub query: String,
+    #[schema(example = json!(["Deep Learning is ..."]))]
+    pub documents: Vec<String>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    /// The name of the prompt used to encode the query.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub query_prompt_name: Option<String>,
+    /// The name of the prompt used to encode the documents.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub document_prompt_name: Option<String>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub return_text: bool,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
+    /// Queue priority class of the request. Overrides the `X-Priority` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub priority: Option<Priority>,
+    /// Queue timeout of the request in milliseconds. Overrides the `X-Request-Timeout` header.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub timeout: Option<u64>,
+}
+
 #[derive(Serialize, ToSchema)]
 pub(crate) struct OpenAICompatErrorResponse {
     pub message: String,
@@ -548,6 +1257,10 @@ pub(crate) struct TokenizeRequest {
     /// any text to encode.
     #[schema(default = "null", example = "null", nullable = true)]
     pub prompt_name: Option<String>,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
 }
 
 fn default_add_special_tokens() -> bool {
@@ -585,6 +1298,10 @@ pub(crate) struct DecodeRequest {
     #[serde(default = "default_skip_special_tokens")]
     #[schema(default = "true", example = "true")]
     pub skip_special_tokens: bool,
+    /// The name of the model to use. Defaults to the first model served by the router.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub model: Option<String>,
 }
 
 fn default_skip_special_tokens() -> bool {
@@ -595,6 +1312,15 @@ fn default_skip_special_tokens() -> bool {
 #[schema(example = json!(["test"]))]
 pub(crate) struct DecodeResponse(pub Vec<String>);
 
+/// Info of the default model, with the info of every served model
+#[derive(Serialize, ToSchema)]
+pub(crate) struct InfoResponse {
+    #[serde(flatten)]
+    pub info: Info,
+    /// Served models, the default model first
+    pub models: Vec<Info>,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct VertexRequest {
     pub instances: Vec<serde_json::Value>,
@@ -613,3 +1339,44 @@ pub(crate) enum VertexPrediction {
 pub(crate) struct VertexResponse {
     pub predictions: Vec<VertexPrediction>,
 }
+
+/// Model to load and serve in place of a served model
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct ModelSwapRequest {
+    #[schema(example = "BAAI/bge-large-en-v1.5")]
+    pub model_id: String,
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub revision: Option<String>,
+    /// Served model to replace. Defaults to the default model.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub replace: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ModelSwapState {
+    /// No swap was requested
+    #[default]
+    Idle,
+    Loading,
+    Completed,
+    Failed,
+}
+
+/// State of the last model swap
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub(crate) struct ModelSwapStatus {
+    pub state: ModelSwapState,
+    #[schema(example = "BAAI/bge-large-en-v1.5", nullable = true)]
+    pub model_id: Option<String>,
+    #[schema(example = "null", nullable = true)]
+    pub revision: Option<String>,
+    /// Served model replaced by the swap
+    #[schema(example = "BAAI/bge-base-en-v1.5", nullable = true)]
+    pub replace: Option<String>,
+    /// Reason of the failure of the swap. The replaced model keeps serving.
+    #[schema(example = "null", nullable = true)]
+    pub error: Option<String>,
+}
@@ -1,21 +1,32 @@
 /// Text Embedding Inference Webserver
+mod auth;
+mod config;
 mod logging;
+mod npy;
+mod offline;
 mod prometheus;
+mod quantization;
+mod settings;
+mod similarity;
 
 #[cfg(feature = "http")]
 mod http;
 
-#[cfg(feature = "http")]
-use ::http::HeaderMap;
-
 #[cfg(feature = "grpc")]
 mod grpc;
 
-#[cfg(feature = "grpc")]
-use tonic::codegen::http::HeaderMap;
-
 mod shutdown;
-
+mod tls;
+mod uds;
+
+use crate::auth::ApiKeys;
+pub use crate::config::Config;
+pub use crate::offline::OfflineTask;
+use crate::quantization::QuantizationRanges;
+pub use crate::settings::{ModelSettings, ServerSettings};
+use crate::shutdown::Lifecycle;
+use crate::tls::TlsFiles;
+use crate::uds::UnixSocket;
 use anyhow::{anyhow, Context, Result};
 use hf_hub::api::tokio::ApiBuilder;
 use hf_hub::{Repo, RepoType};
@@ -24,13 +35,17 @@ use serde::Serialize;
 use std::collections::HashMap;
 use std::fs;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
-use text_embeddings_backend::{DType, Pool};
+use text_embeddings_backend::{BackendError, DType, Pool};
+use text_embeddings_core::cache::EmbeddingCache;
 use text_embeddings_core::download::{download_artifacts, ST_CONFIG_NAMES};
 use text_embeddings_core::infer::Infer;
+use text_embeddings_core::multi_vector::ColBERTConfig;
 use text_embeddings_core::queue::Queue;
-use text_embeddings_core::tokenization::Tokenization;
+use text_embeddings_core::tokenization::{EncodingInput, Tokenization};
 use text_embeddings_core::TextEmbeddingsError;
 use tokenizers::processors::sequence::Sequence;
 use tokenizers::processors::template::TemplateProcessing;
@@ -40,33 +55,319 @@ use tracing::Span;
 pub use logging::init_logging;
 
 /// Create entrypoint
-#[allow(clippy::too_many_arguments)]
 pub async fn run(
+    model_ids: Vec<String>,
+    settings: ModelSettings,
+    server: ServerSettings,
+) -> Result<()> {
+    let ServerSettings {
+        hostname,
+        port,
+        api_keys_file,
+        prometheus_port,
+        drain_timeout,
+        grpc_port,
+        tls_cert,
+        tls_key,
+        tls_client_ca,
+        listen_uds,
+        listen_uds_mode,
+        listen_uds_only,
+        ..
+    } = server.clone();
+
+    if model_ids.is_empty() {
+        anyhow::bail!("At least one `--model-id` must be provided");
+    }
+
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert), Some(key)) => Some(TlsFiles {
+            cert: PathBuf::from(cert),
+            key: PathBuf::from(key),
+            client_ca: tls_client_ca.map(PathBuf::from),
+        }),
+        (None, None) => {
+            if tls_client_ca.is_some() {
+                anyhow::bail!("`--tls-client-ca` requires `--tls-cert`");
+            }
+            None
+        }
+        _ => anyhow::bail!("`--tls-cert` and `--tls-key` must be set together"),
+    };
+
+    let uds = match listen_uds {
+        Some(_) if !cfg!(unix) => anyhow::bail!("`--listen-uds` is only supported on Unix"),
+        Some(path) => Some(UnixSocket {
+            path: PathBuf::from(path),
+            mode: UnixSocket::parse_mode(&listen_uds_mode)?,
+        }),
+        None => {
+            if listen_uds_only {
+                anyhow::bail!("`--listen-uds-only` requires `--listen-uds`");
+            }
+            None
+        }
+    };
+
+    // The gRPC server of the HTTP build only listens on TCP
+    #[cfg(all(feature = "http", feature = "grpc"))]
+    if listen_uds_only && grpc_port.is_some() {
+        anyhow::bail!("`--listen-uds-only` cannot be used with `--grpc-port`");
+    }
+
+    let api_keys = match api_keys_file {
+        Some(api_keys_file) => {
+            let api_keys = ApiKeys::load(Path::new(&api_keys_file))?;
+            api_keys.reload_on_sighup();
+            Some(api_keys)
+        }
+        None => None,
+    };
+
+    // use AIP_HTTP_PORT if google feature is enabled
+    let port = if cfg!(feature = "google") {
+        std::env::var("AIP_HTTP_PORT")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .inspect(|&p| {
+                tracing::info!("`AIP_HTTP_PORT` is set: overriding port {port} by port {p}");
+            })
+            .unwrap_or(port)
+    } else {
+        port
+    };
+
+    let addr = match hostname.unwrap_or("0.0.0.0".to_string()).parse() {
+        Ok(ip) => SocketAddr::new(ip, port),
+        Err(_) => {
+            tracing::warn!("Invalid hostname, defaulting to 0.0.0.0");
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port)
+        }
+    };
+
+    // The API is only served on the Unix socket with `--listen-uds-only`
+    let tcp_addr = (!listen_uds_only).then_some(addr);
+
+    let lifecycle = Lifecycle::new(Duration::from_secs(drain_timeout));
+
+    // Answer the probes while the models are loading, on the listeners of the server
+    #[cfg(feature = "http")]
+    let (stop_probes, probes) = {
+        let (stop_probes, stopped) = tokio::sync::oneshot::channel::<()>();
+        let listeners = http::server::bind(tcp_addr, uds.as_ref(), tls.clone()).await?;
+        let probes = http::server::serve_probes(listeners, async {
+            let _ = stopped.await;
+        });
+        (stop_probes, tokio::spawn(probes))
+    };
+
+    let revision = settings.revision.clone();
+    let loader = ModelLoader {
+        settings,
+        loaded: Arc::new(AtomicUsize::new(0)),
+    };
+
+    let mut served_models = Vec::with_capacity(model_ids.len());
+    for model_id in model_ids {
+        let served_model = loader
+            .load(model_id.clone(), revision.clone())
+            .await
+            .context(format!("Could not load model `{model_id}`"))?;
+        served_models.push(served_model);
+    }
+    let models = Models::new(served_models)?;
+
+    #[cfg(feature = "http")]
+    let listeners = {
+        let _ = stop_probes.send(());
+        probes.await?
+    };
+    lifecycle.set_ready();
+
+    let max_input_length = models
+        .iter()
+        .map(|m| m.info.max_input_length)
+        .max()
+        .unwrap_or_default();
+    let prom_builder = prometheus::prometheus_builer(addr, prometheus_port, max_input_length)?;
+
+    #[cfg(all(feature = "grpc", feature = "google"))]
+    compile_error!("Features `http` and `google` cannot be enabled at the same time.");
+
+    #[cfg(not(any(feature = "http", feature = "grpc")))]
+    compile_error!("Either feature `http` or `grpc` must be enabled.");
+
+    // Both servers record to the same global Prometheus recorder
+    // See: https://github.com/metrics-rs/metrics/issues/467#issuecomment-2022755151
+    let (recorder, exporter) = prom_builder
+        .build()
+        .context("failed to build prometheus recorder")?;
+    let prom_handle = recorder.handle();
+    metrics::set_global_recorder(recorder).context("Failed to set global recorder")?;
+
+    lifecycle.drain_on_signal();
+
+    #[cfg(all(feature = "http", feature = "grpc"))]
+    if let Some(grpc_port) = grpc_port {
+        // Both servers share the models, the metrics, the API keys and the lifecycle
+        tokio::spawn(exporter);
+        tracing::info!("Serving Prometheus metrics: 0.0.0.0:{prometheus_port}");
+
+        // `--listen-uds` only applies to the HTTP server
+        let grpc = grpc::server::run(
+            models.clone(),
+            Some(SocketAddr::new(addr.ip(), grpc_port)),
+            None,
+            server.api_key.clone(),
+            api_keys.clone(),
+            lifecycle.clone(),
+            tls.clone(),
+        );
+        let http = http::server::run(
+            models,
+            listeners,
+            prom_handle,
+            server,
+            api_keys,
+            loader,
+            lifecycle,
+        );
+        tokio::try_join!(http, grpc)?;
+        return Ok(());
+    }
+
+    #[cfg(feature = "http")]
+    {
+        // The HTTP server exposes the metrics on `/metrics`
+        drop(exporter);
+        #[cfg(not(feature = "grpc"))]
+        let _ = grpc_port;
+        http::server::run(
+            models,
+            listeners,
+            prom_handle,
+            server,
+            api_keys,
+            loader,
+            lifecycle,
+        )
+        .await
+    }
+
+    #[cfg(all(feature = "grpc", not(feature = "http")))]
+    {
+        // cors_allow_origin, payload_limit, the jobs directories, model swaps and the admin routes
+        // are not used for gRPC servers
+        let _ = loader;
+        let _ = grpc_port;
+        let _ = prom_handle;
+        tokio::spawn(exporter);
+        tracing::info!("Serving Prometheus metrics: 0.0.0.0:{prometheus_port}");
+        grpc::server::run(
+            models,
+            tcp_addr,
+            uds,
+            server.api_key,
+            api_keys,
+            lifecycle,
+            tls,
+        )
+        .await
+    }
+}
+
+/// Run `task` on the records of a JSONL file with a single model, without a network listener
+pub async fn run_offline(
+    task: OfflineTask,
+    input: PathBuf,
+    output: PathBuf,
+    raw_scores: bool,
+    model_ids: Vec<String>,
+    settings: ModelSettings,
+) -> Result<()> {
+    let [model_id] = <[String; 1]>::try_from(model_ids)
+        .map_err(|_| anyhow!("Exactly one `--model-id` must be provided"))?;
+
+    // Records are not queued behind network clients: the queue time, the cache, the multi-vector
+    // routes and the OTLP exporter do not apply
+    let settings = ModelSettings {
+        max_queue_time: None,
+        max_client_batch_size: settings.max_concurrent_requests,
+        embedding_cache_size: None,
+        multi_vector: false,
+        otlp_endpoint: None,
+        otlp_service_name: "text-embeddings-inference.offline".to_string(),
+        ..settings
+    };
+
+    tracing::info!("Loading model `{model_id}`");
+    let uds_path = settings.uds_path.clone();
+    let served_model = load_model(
+        model_id.clone(),
+        settings.revision.clone(),
+        &settings,
+        uds_path,
+    )
+    .await
+    .context(format!("Could not load model `{model_id}`"))?;
+
+    offline::run(task, &served_model, &input, &output, raw_scores).await
+}
+
+/// Settings of the router used to load every served model
+#[derive(Clone, Debug)]
+pub(crate) struct ModelLoader {
+    settings: ModelSettings,
+    /// Number of models loaded so far
+    loaded: Arc<AtomicUsize>,
+}
+
+impl ModelLoader {
+    pub async fn load(&self, model_id: String, revision: Option<String>) -> Result<ServedModel> {
+        // Each Python backend needs its own socket
+        let i = self.loaded.fetch_add(1, Ordering::SeqCst);
+        let uds_path = if i == 0 {
+            self.settings.uds_path.clone()
+        } else {
+            format!("{}-{i}", self.settings.uds_path)
+        };
+
+        tracing::info!("Loading model `{model_id}`");
+        load_model(model_id, revision, &self.settings, uds_path).await
+    }
+}
+
+/// Download, load and warm up a single model
+async fn load_model(
     model_id: String,
     revision: Option<String>,
-    tokenization_workers: Option<usize>,
-    dtype: Option<DType>,
-    pooling: Option<text_embeddings_backend::Pool>,
-    max_concurrent_requests: usize,
-    max_batch_tokens: usize,
-    max_batch_requests: Option<usize>,
-    max_client_batch_size: usize,
-    auto_truncate: bool,
-    default_prompt: Option<String>,
-    default_prompt_name: Option<String>,
-    dense_path: Option<String>,
-    hf_token: Option<String>,
-    hostname: Option<String>,
-    port: u16,
-    uds_path: Option<String>,
-    huggingface_hub_cache: Option<String>,
-    payload_limit: usize,
-    api_key: Option<String>,
-    otlp_endpoint: Option<String>,
-    otlp_service_name: String,
-    prometheus_port: u16,
-    cors_allow_origin: Option<Vec<String>>,
-) -> Result<()> {
+    settings: &ModelSettings,
+    uds_path: String,
+) -> Result<ServedModel> {
+    let ModelSettings {
+        tokenization_workers,
+        dtype,
+        pooling,
+        max_concurrent_requests,
+        max_batch_tokens,
+        max_batch_requests,
+        max_queue_time,
+        priority_starvation_timeout,
+        max_client_batch_size,
+        auto_truncate,
+        embedding_cache_size,
+        bisect_failed_batches,
+        default_prompt,
+        default_prompt_name,
+        dense_path,
+        multi_vector,
+        hf_token,
+        huggingface_hub_cache,
+        otlp_endpoint,
+        otlp_service_name,
+        ..
+    } = settings.clone();
+
     let model_id_path = Path::new(&model_id);
     let (model_root, api_repo) = if model_id_path.exists() && model_id_path.is_dir() {
         // Using a local model
@@ -235,7 +536,25 @@ pub async fn run(
                 .context("Failed to parse `config_sentence_transformers.json`")?,
         );
     }
-    let prompts = new_st_config.and_then(|c| c.prompts);
+    let (prompts, colbert_config) = match new_st_config {
+        Some(config) => (
+            config.prompts,
+            ColBERTConfig {
+                query_prefix: config.query_prefix,
+                document_prefix: config.document_prefix,
+                query_length: config.query_length,
+                mask_token_id: None,
+            },
+        ),
+        None => (None, ColBERTConfig::default()),
+    };
+    // ColBERT pads the queries with the mask token
+    let colbert_config = ColBERTConfig {
+        mask_token_id: ["[MASK]", "<mask>"]
+            .into_iter()
+            .find_map(|token| tokenizer.token_to_id(token)),
+        ..colbert_config
+    };
     let default_prompt = if let Some(default_prompt_name) = default_prompt_name.as_ref() {
         match &prompts {
             None => {
@@ -269,17 +588,35 @@ pub async fn run(
         dtype.unwrap_or_default()
     };
 
+    // Int8 calibration ranges
+    let quantization_ranges = QuantizationRanges::load(&model_root)?;
+    if quantization_ranges.is_some() {
+        tracing::info!("Loaded int8 calibration ranges");
+    }
+
+    // Results cache, scoped to the revision and pooling of this model
+    let cache = embedding_cache_size.map(|size| {
+        let revision = format!(
+            "{model_id}@{}/{backend_model_type:?}",
+            revision.as_deref().unwrap_or("main")
+        );
+        tracing::info!("Caching results of `{revision}` up to {size} bytes");
+        EmbeddingCache::new(revision, size)
+    });
+
     // Create backend
     tracing::info!("Starting model backend");
     let backend = text_embeddings_backend::Backend::new(
+        model_id.clone(),
         model_root,
         api_repo,
         dtype.clone(),
         backend_model_type,
         dense_path,
-        uds_path.unwrap_or("/tmp/text-embeddings-inference-server".to_string()),
-        otlp_endpoint.clone(),
-        otlp_service_name.clone(),
+        multi_vector,
+        uds_path,
+        otlp_endpoint,
+        otlp_service_name,
     )
     .await
     .context("Could not create backend")?;
@@ -313,10 +650,20 @@ pub async fn run(
         max_batch_tokens,
         max_batch_requests,
         max_concurrent_requests,
+        max_queue_time.map(Duration::from_millis),
+        Duration::from_millis(priority_starvation_timeout),
     );
 
     // Create infer task
-    let infer = Infer::new(tokenization, queue, max_concurrent_requests, backend);
+    let infer = Infer::new(
+        tokenization,
+        queue,
+        max_concurrent_requests,
+        backend,
+        cache,
+        bisect_failed_batches,
+    )
+    .with_colbert_config(colbert_config);
 
     // Endpoint info
     let info = Info {
@@ -336,59 +683,11 @@ pub async fn run(
         docker_label: option_env!("DOCKER_LABEL"),
     };
 
-    // use AIP_HTTP_PORT if google feature is enabled
-    let port = if cfg!(feature = "google") {
-        std::env::var("AIP_HTTP_PORT")
-            .ok()
-            .and_then(|p| p.parse().ok())
-            .inspect(|&p| {
-                tracing::info!("`AIP_HTTP_PORT` is set: overriding port {port} by port {p}");
-            })
-            .unwrap_or(port)
-    } else {
-        port
-    };
-
-    let addr = match hostname.unwrap_or("0.0.0.0".to_string()).parse() {
-        Ok(ip) => SocketAddr::new(ip, port),
-        Err(_) => {
-            tracing::warn!("Invalid hostname, defaulting to 0.0.0.0");
-            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port)
-        }
-    };
-
-    let prom_builder = prometheus::prometheus_builer(addr, prometheus_port, info.max_input_length)?;
-
-    #[cfg(all(feature = "grpc", feature = "http"))]
-    compile_error!("Features `http` and `grpc` cannot be enabled at the same time.");
-
-    #[cfg(all(feature = "grpc", feature = "google"))]
-    compile_error!("Features `http` and `google` cannot be enabled at the same time.");
-
-    #[cfg(not(any(feature = "http", feature = "grpc")))]
-    compile_error!("Either feature `http` or `grpc` must be enabled.");
-
-    #[cfg(feature = "http")]
-    {
-        http::server::run(
-            infer,
-            info,
-            addr,
-            prom_builder,
-            payload_limit,
-            api_key,
-            cors_allow_origin,
-        )
-        .await
-    }
-
-    #[cfg(feature = "grpc")]
-    {
-        // cors_allow_origin and payload_limit are not used for gRPC servers
-        let _ = cors_allow_origin;
-        let _ = payload_limit;
-        grpc::server::run(infer, info, addr, prom_builder, api_key).await
-    }
+    Ok(ServedModel {
+        infer,
+        info,
+        quantization_ranges: quantization_ranges.map(Arc::new),
+    })
 }
 
 fn get_backend_model_type(
@@ -509,6 +808,12 @@ pub struct STConfig {
 #[derive(Debug, Deserialize)]
 pub struct NewSTConfig {
     pub prompts: Option<HashMap<String, String>>,
+    /// Query marker of PyLate ColBERT models
+    pub query_prefix: Option<String>,
+    /// Document marker of PyLate ColBERT models
+    pub document_prefix: Option<String>,
+    /// Length the queries of PyLate ColBERT models are padded to
+    pub query_length: Option<usize>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -536,6 +841,17 @@ pub enum ModelType {
     Reranker(ClassifierModel),
 }
 
+/// Handler of the `/` route of a model type
+#[cfg(feature = "http")]
+fn route_kind(model_type: &ModelType) -> &'static str {
+    match model_type {
+        ModelType::Classifier(_) => "predict",
+        ModelType::Reranker(_) => "rerank",
+        ModelType::Embedding(model) if model.pooling == "splade" => "embed_sparse",
+        ModelType::Embedding(_) => "embed",
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 #[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
 pub struct Info {
@@ -576,7 +892,154 @@ pub struct Info {
     pub docker_label: Option<&'static str>,
 }
 
-#[derive(Serialize)]
+/// A model served by the router
+#[derive(Clone, Debug)]
+pub struct ServedModel {
+    pub infer: Infer,
+    pub info: Info,
+    /// Int8 calibration ranges loaded from the model repository
+    pub quantization_ranges: Option<Arc<QuantizationRanges>>,
+}
+
+/// Models served by the router, selected by their `model_id`
+///
+/// The first model is the default model, used when a request does not specify a model. Models
+/// can be swapped while serving: requests hold a clone of the model they started with until they
+/// complete.
+#[derive(Clone, Debug)]
+pub struct Models {
+    models: Arc<RwLock<Vec<ServedModel>>>,
+    /// Notified after each swap
+    swapped: Arc<tokio::sync::watch::Sender<()>>,
+}
+
+impl Models {
+    pub fn new(models: Vec<ServedModel>) -> Result<Self> {
+        if models.is_empty() {
+            anyhow::bail!("At least one model must be served");
+        }
+
+        for (i, model) in models.iter().enumerate() {
+            if models[..i]
+                .iter()
+                .any(|m| m.info.model_id == model.info.model_id)
+            {
+                anyhow::bail!("Model `{}` is served more than once", model.info.model_id);
+            }
+        }
+
+        Ok(Self {
+            models: Arc::new(RwLock::new(models)),
+            swapped: Arc::new(tokio::sync::watch::channel(()).0),
+        })
+    }
+
+    /// Model used when a request does not specify one
+    pub fn default_model(&self) -> ServedModel {
+        self.models.read().unwrap()[0].clone()
+    }
+
+    /// Select a model by name
+    pub fn get(&self, model: Option<&str>) -> Result<ServedModel, ErrorResponse> {
+        let models = self.models.read().unwrap();
+        let Some(name) = model else {
+            return Ok(models[0].clone());
+        };
+
+        match models.iter().find(|m| m.info.model_id == name) {
+            Some(model) => Ok(model.clone()),
+            // OpenAI clients always send a `model` field: when a single model is served we keep
+            // ignoring it to stay backward compatible
+            None if models.len() == 1 => Ok(models[0].clone()),
+            None => {
+                let counter = metrics::counter!("te_request_failure", "err" => "model_not_found");
+                counter.increment(1);
+                let message = format!(
+                    "model `{name}` is not served. Available models: {:?}",
+                    models.iter().map(|m| &m.info.model_id).collect::<Vec<_>>()
+                );
+                tracing::error!("{message}");
+                Err(ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::NotFound,
+                })
+            }
+        }
+    }
+
+    /// Replace the model named `replace`, or the default model if not set, by `model`
+    ///
+    /// Returns the replaced model. It keeps serving the requests that already selected it.
+    #[cfg(feature = "http")]
+    pub fn swap(&self, replace: Option<&str>, model: ServedModel) -> Result<ServedModel> {
+        let mut models = self.models.write().unwrap();
+        let index = match replace {
+            None => 0,
+            Some(name) => models
+                .iter()
+                .position(|m| m.info.model_id == name)
+                .ok_or_else(|| anyhow!("Model `{name}` is not served"))?,
+        };
+        if models
+            .iter()
+            .enumerate()
+            .any(|(i, m)| i != index && m.info.model_id == model.info.model_id)
+        {
+            anyhow::bail!("Model `{}` is already served", model.info.model_id);
+        }
+        // `/` and `/invocations` are bound to the type of the model at startup
+        if route_kind(&models[index].info.model_type) != route_kind(&model.info.model_type) {
+            anyhow::bail!(
+                "Model `{}` does not have the type of `{}`",
+                model.info.model_id,
+                models[index].info.model_id
+            );
+        }
+        let replaced = std::mem::replace(&mut models[index], model);
+        drop(models);
+        self.swapped.send_replace(());
+        Ok(replaced)
+    }
+
+    /// Receiver notified after each swap, to follow the state of the served models
+    pub fn swapped(&self) -> tokio::sync::watch::Receiver<()> {
+        self.swapped.subscribe()
+    }
+
+    /// Snapshot of the served models
+    pub fn iter(&self) -> impl Iterator<Item = ServedModel> {
+        self.models.read().unwrap().clone().into_iter()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.models
+            .read()
+            .unwrap()
+            .iter()
+            .map(|m| m.info.model_id.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.models.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.read().unwrap().is_empty()
+    }
+
+    /// All served models are healthy
+    pub async fn health(&self) -> bool {
+        for model in self.iter() {
+            if !model.infer.health().await {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Serialize, Debug)]
 #[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
 pub enum ErrorType {
     Unhealthy,
@@ -585,9 +1048,14 @@ pub enum ErrorType {
     Validation,
     Tokenizer,
     Empty,
+    NotFound,
+    Timeout,
+    Unauthorized,
+    Forbidden,
+    Internal,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug)]
 #[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
 pub struct ErrorResponse {
     pub error: String,
@@ -601,7 +1069,10 @@ impl From<TextEmbeddingsError> for ErrorResponse {
             TextEmbeddingsError::Validation(_) => ErrorType::Validation,
             TextEmbeddingsError::Empty(_) => ErrorType::Empty,
             TextEmbeddingsError::Overloaded(_) => ErrorType::Overloaded,
+            // The circuit breaker of the backend is open
+            TextEmbeddingsError::Backend(BackendError::Unhealthy) => ErrorType::Unhealthy,
             TextEmbeddingsError::Backend(_) => ErrorType::Backend,
+            TextEmbeddingsError::Timeout(_) => ErrorType::Timeout,
         };
         Self {
             error: err.to_string(),
@@ -610,6 +1081,28 @@ impl From<TextEmbeddingsError> for ErrorResponse {
     }
 }
 
+/// Input of a re-ranker for `query` and `text`
+///
+/// `use_template` defaults to true for the Qwen3 re-rankers, which format the query, the text and
+/// the optional instruction in a single prompt.
+fn rerank_input(
+    model_id: &str,
+    use_template: Option<bool>,
+    query: String,
+    text: String,
+    instruction: Option<&str>,
+) -> EncodingInput {
+    let use_template = use_template
+        .unwrap_or_else(|| text_embeddings_core::templates::requires_template(model_id));
+    match use_template
+        .then(|| text_embeddings_core::templates::get_template_formatter(model_id))
+        .flatten()
+    {
+        Some(formatter) => formatter.format_rerank(&query, &text, instruction).into(),
+        None => (query, text).into(),
+    }
+}
+
 struct ResponseMetadata {
     compute_chars: usize,
     compute_tokens: usize,
@@ -659,63 +1152,63 @@ impl ResponseMetadata {
         let histogram = metrics::histogram!("te_request_inference_duration");
         histogram.record(self.inference_time.as_secs_f64());
     }
+
+    /// Response headers as name and value pairs
+    fn headers(&self) -> [(&'static str, String); 8] {
+        [
+            ("x-compute-type", "gpu+optimized".to_string()),
+            (
+                "x-compute-time",
+                self.start_time.elapsed().as_millis().to_string(),
+            ),
+            ("x-compute-characters", self.compute_chars.to_string()),
+            ("x-compute-tokens", self.compute_tokens.to_string()),
+            (
+                "x-total-time",
+                self.start_time.elapsed().as_millis().to_string(),
+            ),
+            (
+                "x-tokenization-time",
+                self.tokenization_time.as_millis().to_string(),
+            ),
+            ("x-queue-time", self.queue_time.as_millis().to_string()),
+            (
+                "x-inference-time",
+                self.inference_time.as_millis().to_string(),
+            ),
+        ]
+    }
 }
 
-impl From<ResponseMetadata> for HeaderMap {
+#[cfg(feature = "http")]
+impl From<ResponseMetadata> for ::http::HeaderMap {
     fn from(value: ResponseMetadata) -> Self {
-        // Headers
-        let mut headers = HeaderMap::new();
-        headers.insert("x-compute-type", "gpu+optimized".parse().unwrap());
-        headers.insert(
-            "x-compute-time",
-            value
-                .start_time
-                .elapsed()
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            "x-compute-characters",
-            value.compute_chars.to_string().parse().unwrap(),
-        );
-        headers.insert(
-            "x-compute-tokens",
-            value.compute_tokens.to_string().parse().unwrap(),
-        );
-        headers.insert(
-            "x-total-time",
-            value
-                .start_time
-                .elapsed()
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            "x-tokenization-time",
-            value
-                .tokenization_time
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            "x-queue-time",
-            value.queue_time.as_millis().to_string().parse().unwrap(),
-        );
-        headers.insert(
-            "x-inference-time",
-            value
-                .inference_time
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers
+        value
+            .headers()
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    ::http::HeaderName::from_static(name),
+                    value.parse().unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+// tonic depends on another version of `http`
+#[cfg(feature = "grpc")]
+impl From<ResponseMetadata> for tonic::codegen::http::HeaderMap {
+    fn from(value: ResponseMetadata) -> Self {
+        value
+            .headers()
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    tonic::codegen::http::HeaderName::from_static(name),
+                    value.parse().unwrap(),
+                )
+            })
+            .collect()
     }
 }
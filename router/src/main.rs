@@ -1,7 +1,9 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Subcommand};
 use opentelemetry::global;
+use std::path::PathBuf;
 use text_embeddings_backend::DType;
+use text_embeddings_router::{Config, ModelSettings, OfflineTask, ServerSettings};
 use veil::Redact;
 
 #[cfg(not(target_os = "linux"))]
@@ -9,7 +11,7 @@ use veil::Redact;
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 /// App Configuration
-#[derive(Parser, Redact)]
+#[derive(clap::Parser, Redact)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
     /// The Hugging Face model ID, can be any model listed on <https://huggingface.co/models> with
@@ -19,23 +21,27 @@ struct Args {
     /// Alternatively, the specified ID can also be a path to a local directory containing the
     /// necessary model files saved by the `save_pretrained(...)` methods of either Transformers or
     /// Sentence Transformers.
-    #[clap(long, env)]
+    ///
+    /// Multiple models can be served by passing a comma separated list of IDs. Requests select
+    /// a model with their `model` field and default to the first model of the list. All the
+    /// other arguments apply to every model.
+    #[clap(long, env, value_delimiter = ',', global = true)]
     #[redact(partial)]
-    model_id: String,
+    model_id: Vec<String>,
 
     /// The actual revision of the model if you're referring to a model
     /// on the hub. You can use a specific commit id or a branch like `refs/pr/2`.
-    #[clap(long, env)]
+    #[clap(long, env, global = true)]
     revision: Option<String>,
 
     /// Optionally control the number of tokenizer workers used for payload tokenization, validation
     /// and truncation.
     /// Default to the number of CPU cores on the machine.
-    #[clap(long, env)]
+    #[clap(long, env, global = true)]
     tokenization_workers: Option<usize>,
 
     /// The dtype to be forced upon the model.
-    #[clap(long, env, value_enum)]
+    #[clap(long, env, value_enum, global = true)]
     dtype: Option<DType>,
 
     /// Optionally control the pooling method for embedding models.
@@ -44,13 +50,13 @@ struct Args {
     /// model `1_Pooling/config.json` configuration.
     ///
     /// If `pooling` is set, it will override the model pooling configuration
-    #[clap(long, env, value_enum)]
+    #[clap(long, env, value_enum, global = true)]
     pooling: Option<text_embeddings_backend::Pool>,
 
     /// The maximum amount of concurrent requests for this particular deployment.
     /// Having a low limit will refuse clients requests instead of having them
     /// wait for too long and is usually good to handle backpressure correctly.
-    #[clap(default_value = "512", long, env)]
+    #[clap(default_value = "512", long, env, global = true)]
     max_concurrent_requests: usize,
 
     /// **IMPORTANT** This is one critical control to allow maximum usage
@@ -64,13 +70,30 @@ struct Args {
     /// Overall this number should be the largest possible until the model is compute bound.
     /// Since the actual memory overhead depends on the model implementation,
     /// text-embeddings-inference cannot infer this number automatically.
-    #[clap(default_value = "16384", long, env)]
+    #[clap(default_value = "16384", long, env, global = true)]
     max_batch_tokens: usize,
 
     /// Optionally control the maximum number of individual requests in a batch
-    #[clap(long, env)]
+    #[clap(long, env, global = true)]
     max_batch_requests: Option<usize>,
 
+    /// Maximum time in milliseconds a request can wait in the queue before being evicted with a
+    /// timeout error.
+    ///
+    /// Requests can set a shorter deadline with their `timeout` field or their
+    /// `X-Request-Timeout` header.
+    #[clap(long, env)]
+    max_queue_time: Option<u64>,
+
+    /// Time in milliseconds after which a queued request is batched before the requests of higher
+    /// priority classes.
+    ///
+    /// Requests are batched by priority class, set with their `priority` field or their
+    /// `X-Priority` header. This guard keeps a steady stream of `high` requests from starving the
+    /// `normal` and `low` ones.
+    #[clap(default_value = "5000", long, env)]
+    priority_starvation_timeout: u64,
+
     /// Control the maximum number of inputs that a client can send in a single request
     #[clap(default_value = "32", long, env)]
     max_client_batch_size: usize,
@@ -78,9 +101,21 @@ struct Args {
     /// Automatically truncate inputs that are longer than the maximum supported size
     ///
     /// Unused for gRPC servers
-    #[clap(long, env)]
+    #[clap(long, env, global = true)]
     auto_truncate: bool,
 
+    /// Maximum memory in bytes used to cache pooled embeddings and classification scores.
+    ///
+    /// Identical inputs are then served from the cache without going through the queue and
+    /// the model. The cache is disabled by default.
+    #[clap(long, env)]
+    embedding_cache_size: Option<usize>,
+
+    /// Retry the halves of a failed batch until the failing inputs are isolated, so that they
+    /// do not fail the other requests of the batch
+    #[clap(long, env, global = true)]
+    bisect_failed_batches: bool,
+
     /// The name of the prompt that should be used by default for encoding. If not set, no prompt
     /// will be applied.
     ///
@@ -93,7 +128,7 @@ struct Args {
     ///
     /// The argument '--default-prompt-name <DEFAULT_PROMPT_NAME>' cannot be used with
     /// '--default-prompt <DEFAULT_PROMPT>`
-    #[clap(long, env, conflicts_with = "default_prompt")]
+    #[clap(long, env, conflicts_with = "default_prompt", global = true)]
     default_prompt_name: Option<String>,
 
     /// The prompt that should be used by default for encoding. If not set, no prompt
@@ -105,7 +140,7 @@ struct Args {
     ///
     /// The argument '--default-prompt <DEFAULT_PROMPT>' cannot be used with
     /// '--default-prompt-name <DEFAULT_PROMPT_NAME>`
-    #[clap(long, env, conflicts_with = "default_prompt_name")]
+    #[clap(long, env, conflicts_with = "default_prompt_name", global = true)]
     default_prompt: Option<String>,
 
     /// Optionally, define the path to the Dense module required for some embedding models.
@@ -119,16 +154,23 @@ struct Args {
     /// Note that this argument is optional, only required to be set if there is no `modules.json`
     /// file or when you want to override a single Dense module path, only when running with the
     /// `candle` backend.
-    #[clap(long, env)]
+    #[clap(long, env, global = true)]
     dense_path: Option<String>,
 
+    /// Load the ColBERT projection of late interaction models to serve `/embed_multi_vector` and
+    /// `/maxsim`, only when running with the `candle` backend.
+    ///
+    /// The token embeddings returned by `/embed_all` are then projected and normalized as well.
+    #[clap(long, env)]
+    multi_vector: bool,
+
     /// [DEPRECATED IN FAVOR OF `--hf-token`] Your Hugging Face Hub token
-    #[clap(long, env, hide = true)]
+    #[clap(long, env, hide = true, global = true)]
     #[redact(partial)]
     hf_api_token: Option<String>,
 
     /// Your Hugging Face Hub token
-    #[clap(long, env, conflicts_with = "hf_api_token")]
+    #[clap(long, env, conflicts_with = "hf_api_token", global = true)]
     #[redact(partial)]
     hf_token: Option<String>,
 
@@ -140,14 +182,43 @@ struct Args {
     #[clap(default_value = "3000", long, short, env)]
     port: u16,
 
+    /// The port of the gRPC server, served next to the HTTP server on `--port`.
+    ///
+    /// Only used when the router is built with both the `http` and `grpc` features
+    #[clap(long, env)]
+    grpc_port: Option<u16>,
+
+    /// Path of a unix socket to serve the API on, next to the TCP listener on `--port`.
+    ///
+    /// When the router is built with both the `http` and `grpc` features, only the HTTP server
+    /// listens on this socket
+    #[clap(long, env)]
+    listen_uds: Option<String>,
+
+    /// The octal permissions of the `--listen-uds` socket. Clients need write permission to
+    /// connect
+    #[clap(default_value = "660", long, env)]
+    listen_uds_mode: String,
+
+    /// Only serve the API on the `--listen-uds` socket, without a TCP listener on `--port`.
+    ///
+    /// Cannot be used with `--grpc-port`, the gRPC server only listens on TCP
+    #[clap(long, env, requires = "listen_uds")]
+    listen_uds_only: bool,
+
     /// The name of the unix socket some text-embeddings-inference backends will use as they
     /// communicate internally with gRPC.
-    #[clap(default_value = "/tmp/text-embeddings-inference-server", long, env)]
+    #[clap(
+        default_value = "/tmp/text-embeddings-inference-server",
+        long,
+        env,
+        global = true
+    )]
     uds_path: String,
 
     /// The location of the huggingface hub cache.
     /// Used to override the location if you want to provide a mounted disk for instance
-    #[clap(long, env)]
+    #[clap(long, env, global = true)]
     huggingface_hub_cache: Option<String>,
 
     /// Payload size limit in bytes
@@ -160,14 +231,31 @@ struct Args {
     ///
     /// By default the server responds to every request. With an api key set, the requests must have the Authorization header set with the api key as Bearer token.
     #[clap(long, env)]
+    #[redact]
     api_key: Option<String>,
 
-    /// Outputs the logs in JSON format (useful for telemetry)
+    /// Path to a JSON file listing the api keys of each tenant, reloaded on SIGHUP.
+    ///
+    /// Each entry has a `key`, a `tenant` name and optional `requests_per_second`,
+    /// `tokens_per_minute` and `routes` limits. Requests over the limits get a 429 response with a
+    /// `Retry-After` header. Cannot be used with `--api-key`.
+    #[clap(long, env, conflicts_with = "api_key")]
+    api_keys_file: Option<String>,
+
+    /// Set an api key for the `/admin` routes, sent as Bearer token in the Authorization header.
+    ///
+    /// The `/admin` routes are disabled if not set. The keys of `--api-key` and `--api-keys-file`
+    /// cannot call them.
     #[clap(long, env)]
+    #[redact]
+    admin_api_key: Option<String>,
+
+    /// Outputs the logs in JSON format (useful for telemetry)
+    #[clap(long, env, global = true)]
     json_output: bool,
 
     // Whether or not to include the log trace through spans
-    #[clap(long, env)]
+    #[clap(long, env, global = true)]
     disable_spans: bool,
 
     /// The grpc endpoint for opentelemetry. Telemetry is sent to this endpoint as OTLP over gRPC.
@@ -187,12 +275,130 @@ struct Args {
     /// Unused for gRPC servers
     #[clap(long, env)]
     cors_allow_origin: Option<Vec<String>>,
+
+    /// Directory where the `/jobs` batch jobs and their results are stored. Jobs that did not
+    /// complete are resumed on startup. The `/jobs` routes are disabled if not set.
+    ///
+    /// Unused for gRPC servers
+    #[clap(long, env)]
+    jobs_dir: Option<String>,
+
+    /// Directory of the input files that `/jobs` submissions can read with their `path`
+    /// parameter. Submissions must send their input as the request body if not set.
+    ///
+    /// Unused for gRPC servers
+    #[clap(long, env)]
+    jobs_input_dir: Option<String>,
+
+    /// Maximum number of records of a `/jobs` batch job that are processed at once. Defaults to
+    /// half of `--max-concurrent-requests`, so that jobs never hold every permit of the model.
+    ///
+    /// Unused for gRPC servers
+    #[clap(long, env)]
+    jobs_max_concurrency: Option<usize>,
+
+    /// Maximum size in bytes of the input of a `/jobs` submission sent as the request body
+    ///
+    /// Unused for gRPC servers
+    #[clap(default_value = "1073741824", long, env)]
+    jobs_max_input_size: usize,
+
+    /// Maximum time in seconds to wait for the in flight requests when draining, on SIGTERM or
+    /// on `POST /admin/drain`, before shutting down
+    #[clap(default_value = "30", long, env)]
+    drain_timeout: u64,
+
+    /// Path to a PEM certificate chain. Serves HTTPS, and gRPC over TLS, with `--tls-key`.
+    ///
+    /// The certificate files are reloaded when they change.
+    #[clap(long, env, requires = "tls_key")]
+    tls_cert: Option<String>,
+
+    /// Path to the PEM private key of `--tls-cert`
+    #[clap(long, env, requires = "tls_cert")]
+    tls_key: Option<String>,
+
+    /// Path to a PEM CA certificate. Clients must present a certificate signed by this CA, and
+    /// the common name of their certificate is their tenant in the logs and the `te_tenant_*`
+    /// metrics.
+    #[clap(long, env, requires = "tls_cert")]
+    tls_client_ca: Option<String>,
+
+    /// Path to a TOML or YAML file setting any of the arguments above, with the argument names as
+    /// keys. Environment variables and flags take precedence over the file.
+    ///
+    /// The file can also list the api keys of each tenant in an `api_keys` section, with the
+    /// entries of `--api-keys-file`.
+    #[clap(long, env, global = true)]
+    config: Option<PathBuf>,
+
+    /// Print the effective configuration, with secrets redacted, and exit
+    #[clap(long)]
+    print_config: bool,
+
+    /// Run a task on a file instead of starting the server
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Embed the records of a JSONL file. Each record has an `inputs` string and an optional `id`.
+    Embed(OfflineArgs),
+    /// Classify the records of a JSONL file. Each record has an `inputs` string or pair of
+    /// strings and an optional `id`.
+    Predict(OfflineArgs),
+    /// Rerank the records of a JSONL file. Each record has a `query`, a list of `texts` and an
+    /// optional `id`.
+    Rerank(OfflineArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct OfflineArgs {
+    /// Path of the JSONL input file
+    #[clap(long)]
+    input: PathBuf,
+
+    /// Path of the output file. Results are written as a `float32` matrix with one row per record
+    /// if the extension is `.npy` and as JSONL otherwise. `rerank` only writes JSONL.
+    #[clap(long)]
+    output: PathBuf,
+
+    /// Return the scores before the sigmoid or softmax. Unused for `embed`.
+    #[clap(long)]
+    raw_scores: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Pattern match configuration
-    let args: Args = Args::parse();
+    let argv: Vec<String> = std::env::args().collect();
+    let config = Config::find_path(&argv)
+        .map(|path| Config::load(&path))
+        .transpose()?;
+    let mut command = Args::command();
+    if let Some(config) = &config {
+        command = config.apply(command)?;
+    }
+    let mut args =
+        Args::from_arg_matches(&command.get_matches_from(argv)).unwrap_or_else(|err| err.exit());
+
+    if let Some(api_keys_file) = config.as_ref().and_then(Config::api_keys_file) {
+        if args.api_key.is_some() || args.api_keys_file.is_some() {
+            anyhow::bail!(
+                "The `api_keys` of the config cannot be used with `--api-key` or `--api-keys-file`"
+            );
+        }
+        args.api_keys_file = Some(api_keys_file);
+    }
+
+    if args.print_config {
+        println!("{args:#?}");
+        if let Some(api_keys) = config.as_ref().and_then(Config::api_keys) {
+            println!("api_keys: {api_keys:#?}");
+        }
+        return Ok(());
+    }
 
     // Initialize logging and telemetry
     let global_tracer = text_embeddings_router::init_logging(
@@ -225,33 +431,71 @@ async fn main() -> Result<()> {
     }
     let token = args.hf_token.or(args.hf_api_token);
 
-    text_embeddings_router::run(
-        args.model_id,
-        args.revision,
-        args.tokenization_workers,
-        args.dtype,
-        args.pooling,
-        args.max_concurrent_requests,
-        args.max_batch_tokens,
-        args.max_batch_requests,
-        args.max_client_batch_size,
-        args.auto_truncate,
-        args.default_prompt,
-        args.default_prompt_name,
-        args.dense_path,
-        token,
-        Some(args.hostname),
-        args.port,
-        Some(args.uds_path),
-        args.huggingface_hub_cache,
-        args.payload_limit,
-        args.api_key,
-        args.otlp_endpoint,
-        args.otlp_service_name,
-        args.prometheus_port,
-        args.cors_allow_origin,
-    )
-    .await?;
+    let settings = ModelSettings {
+        revision: args.revision,
+        tokenization_workers: args.tokenization_workers,
+        dtype: args.dtype,
+        pooling: args.pooling,
+        max_concurrent_requests: args.max_concurrent_requests,
+        max_batch_tokens: args.max_batch_tokens,
+        max_batch_requests: args.max_batch_requests,
+        max_queue_time: args.max_queue_time,
+        priority_starvation_timeout: args.priority_starvation_timeout,
+        max_client_batch_size: args.max_client_batch_size,
+        auto_truncate: args.auto_truncate,
+        embedding_cache_size: args.embedding_cache_size,
+        bisect_failed_batches: args.bisect_failed_batches,
+        default_prompt: args.default_prompt,
+        default_prompt_name: args.default_prompt_name,
+        dense_path: args.dense_path,
+        multi_vector: args.multi_vector,
+        hf_token: token,
+        uds_path: args.uds_path,
+        huggingface_hub_cache: args.huggingface_hub_cache,
+        otlp_endpoint: args.otlp_endpoint,
+        otlp_service_name: args.otlp_service_name,
+    };
+
+    if let Some(command) = args.command {
+        let (task, offline_args) = match command {
+            Command::Embed(offline_args) => (OfflineTask::Embed, offline_args),
+            Command::Predict(offline_args) => (OfflineTask::Predict, offline_args),
+            Command::Rerank(offline_args) => (OfflineTask::Rerank, offline_args),
+        };
+        text_embeddings_router::run_offline(
+            task,
+            offline_args.input,
+            offline_args.output,
+            offline_args.raw_scores,
+            args.model_id,
+            settings,
+        )
+        .await?;
+    } else {
+        let server = ServerSettings {
+            hostname: Some(args.hostname),
+            port: args.port,
+            payload_limit: args.payload_limit,
+            api_key: args.api_key,
+            api_keys_file: args.api_keys_file,
+            admin_api_key: args.admin_api_key,
+            prometheus_port: args.prometheus_port,
+            cors_allow_origin: args.cors_allow_origin,
+            jobs_dir: args.jobs_dir,
+            jobs_input_dir: args.jobs_input_dir,
+            jobs_max_concurrency: args.jobs_max_concurrency,
+            jobs_max_input_size: args.jobs_max_input_size,
+            drain_timeout: args.drain_timeout,
+            grpc_port: args.grpc_port,
+            tls_cert: args.tls_cert,
+            tls_key: args.tls_key,
+            tls_client_ca: args.tls_client_ca,
+            listen_uds: args.listen_uds,
+            listen_uds_mode: args.listen_uds_mode,
+            listen_uds_only: args.listen_uds_only,
+        };
+        text_embeddings_router::run(args.model_id, settings, server).await?;
+    }
 
     if global_tracer {
         // Shutdown tracer
@@ -0,0 +1,109 @@
+//! Writer of `.npy` files of `float32` matrices
+//!
+//! See: https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+use std::io::{Seek, SeekFrom, Write};
+
+/// Length of the header. The shape is only known once every row is written, so the header has a
+/// fixed length that fits any shape and is rewritten at the end.
+const HEADER_LEN: usize = 128;
+
+fn header(rows: usize, cols: usize) -> Vec<u8> {
+    let mut dict =
+        format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({rows}, {cols}), }}");
+    // The header is padded with spaces and ends with a newline
+    let prefix_len = 10;
+    dict.extend(std::iter::repeat(' ').take(HEADER_LEN - prefix_len - dict.len() - 1));
+    dict.push('\n');
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(b"\x93NUMPY\x01\x00");
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+/// Streaming writer of a row-major `float32` matrix
+pub struct NpyWriter<W: Write + Seek> {
+    writer: W,
+    rows: usize,
+    cols: Option<usize>,
+}
+
+impl<W: Write + Seek> NpyWriter<W> {
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(&[b' '; HEADER_LEN])?;
+        Ok(Self {
+            writer,
+            rows: 0,
+            cols: None,
+        })
+    }
+
+    pub fn write_row(&mut self, row: &[f32]) -> anyhow::Result<()> {
+        let cols = *self.cols.get_or_insert(row.len());
+        if row.len() != cols {
+            anyhow::bail!(
+                "row {} has {} columns instead of {cols}",
+                self.rows,
+                row.len()
+            );
+        }
+        for value in row {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Write the header with the final shape
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer
+            .write_all(&header(self.rows, self.cols.unwrap_or(0)))?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_header() {
+        let header = header(3, 1024);
+        assert_eq!(header.len(), HEADER_LEN);
+        assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!(
+            u16::from_le_bytes([header[8], header[9]]) as usize,
+            HEADER_LEN - 10
+        );
+        let dict = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(dict.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (3, 1024), }"));
+        assert!(dict.ends_with(" \n"));
+
+        // Any shape fits
+        assert_eq!(super::header(usize::MAX, usize::MAX).len(), HEADER_LEN);
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = NpyWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.write_row(&[0.5, 1.0]).unwrap();
+        writer.write_row(&[f32::NAN, 2.0]).unwrap();
+        assert!(writer.write_row(&[1.0]).is_err());
+        let data = writer.finish().unwrap().into_inner();
+
+        assert_eq!(&data[..HEADER_LEN], &header(2, 2));
+        let values: Vec<f32> = data[HEADER_LEN..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values.len(), 4);
+        assert_eq!(&values[..2], &[0.5, 1.0]);
+        assert!(values[2].is_nan());
+        assert_eq!(values[3], 2.0);
+    }
+}
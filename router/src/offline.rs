@@ -0,0 +1,315 @@
+//! Offline inference on JSONL files, without a network listener
+use crate::npy::NpyWriter;
+use crate::ServedModel;
+use anyhow::{Context, Result};
+use futures::future::try_join_all;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use text_embeddings_core::infer::InferMetadata;
+use text_embeddings_core::tokenization::EncodingInput;
+use tokenizers::TruncationDirection;
+use tokio::io::AsyncBufReadExt;
+
+#[derive(Debug, Clone, Copy)]
+pub enum OfflineTask {
+    /// One embedding per record
+    Embed,
+    /// The scores of every label for each record
+    Predict,
+    /// The score of every text against the query for each record
+    Rerank,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Inputs {
+    Single(String),
+    Pair(String, String),
+}
+
+/// Record of an input file, one JSON object per line
+#[derive(Deserialize)]
+struct Record {
+    /// Identifier of the record, copied to the JSONL output
+    #[serde(default)]
+    id: serde_json::Value,
+    /// `embed` and `predict` input. `predict` also accepts a pair of texts.
+    #[serde(default)]
+    inputs: Option<Inputs>,
+    /// `rerank` query
+    #[serde(default)]
+    query: Option<String>,
+    /// `rerank` texts
+    #[serde(default)]
+    texts: Vec<String>,
+}
+
+enum Output {
+    Jsonl(BufWriter<File>),
+    Npy(NpyWriter<BufWriter<File>>),
+}
+
+impl Output {
+    /// `.npy` outputs are `float32` matrices with one row per record, other outputs are JSONL
+    fn create(task: OfflineTask, path: &Path) -> Result<Self> {
+        let npy = path.extension().is_some_and(|ext| ext == "npy");
+        // The number of `texts` of rerank records varies, their scores are not a matrix
+        if npy && matches!(task, OfflineTask::Rerank) {
+            anyhow::bail!("`rerank` cannot write a `.npy` output, use a JSONL output");
+        }
+        let file = BufWriter::new(
+            File::create(path).context(format!("Failed to create `{}`", path.display()))?,
+        );
+        if npy {
+            Ok(Output::Npy(NpyWriter::new(file)?))
+        } else {
+            Ok(Output::Jsonl(file))
+        }
+    }
+
+    fn write(&mut self, task: OfflineTask, id: serde_json::Value, row: Vec<f32>) -> Result<()> {
+        match self {
+            Output::Jsonl(file) => {
+                let field = match task {
+                    OfflineTask::Embed => "embedding",
+                    OfflineTask::Predict | OfflineTask::Rerank => "scores",
+                };
+                serde_json::to_writer(&mut *file, &serde_json::json!({"id": id, field: row}))?;
+                file.write_all(b"\n")?;
+            }
+            Output::Npy(writer) => writer.write_row(&row)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Output::Jsonl(mut file) => file.flush()?,
+            Output::Npy(writer) => {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Totals of the processed records
+#[derive(Debug, Default)]
+struct Stats {
+    records: usize,
+    inputs: usize,
+    tokens: usize,
+    tokenization: Duration,
+    queue: Duration,
+    inference: Duration,
+}
+
+impl Stats {
+    fn add(&mut self, metadata: &InferMetadata) {
+        self.inputs += 1;
+        self.tokens += metadata.prompt_tokens;
+        self.tokenization += metadata.tokenization;
+        self.queue += metadata.queue;
+        self.inference += metadata.inference;
+    }
+
+    fn print(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let inputs = self.inputs.max(1) as u32;
+        println!(
+            "Processed {} records ({} inputs, {} tokens) in {elapsed:.2?}",
+            self.records, self.inputs, self.tokens
+        );
+        println!(
+            "Throughput: {:.1} records/s, {:.1} inputs/s, {:.1} tokens/s",
+            self.records as f64 / secs,
+            self.inputs as f64 / secs,
+            self.tokens as f64 / secs
+        );
+        println!(
+            "Mean time per input: tokenization {:.2?}, queue {:.2?}, inference {:.2?}",
+            self.tokenization / inputs,
+            self.queue / inputs,
+            self.inference / inputs
+        );
+    }
+}
+
+/// Run `task` on every record of `input` and write the results to `output`, in input order
+///
+/// Records go through the same tokenization, queue and backend as server requests, so they are
+/// batched together. The first failed record stops the run.
+pub async fn run(
+    task: OfflineTask,
+    served_model: &ServedModel,
+    input: &Path,
+    output: &Path,
+    raw_scores: bool,
+) -> Result<()> {
+    let file = tokio::fs::File::open(input)
+        .await
+        .context(format!("Failed to open `{}`", input.display()))?;
+    let lines = futures::stream::unfold(
+        tokio::io::BufReader::new(file).lines(),
+        |mut lines| async move {
+            lines
+                .next_line()
+                .await
+                .transpose()
+                .map(|line| (line, lines))
+        },
+    );
+    let mut output_file = Output::create(task, output)?;
+
+    let start_time = Instant::now();
+    let truncate = served_model.info.auto_truncate;
+    let rerank_permits = tokio::sync::Mutex::new(());
+    let rerank_permits = &rerank_permits;
+    let mut results = lines
+        .filter(|line| futures::future::ready(!matches!(line, Ok(line) if line.trim().is_empty())))
+        .enumerate()
+        .map(|(i, line)| async move {
+            let record: Record =
+                serde_json::from_str(&line?).context(format!("Record {} is invalid", i + 1))?;
+            let result = run_record(
+                task,
+                served_model,
+                truncate,
+                raw_scores,
+                rerank_permits,
+                record,
+            )
+            .await
+            .context(format!("Record {} failed", i + 1))?;
+            Ok::<_, anyhow::Error>(result)
+        })
+        .buffered(served_model.info.max_concurrent_requests);
+
+    let mut stats = Stats::default();
+    while let Some(result) = results.next().await {
+        let (id, row, metadata) = result?;
+        for metadata in &metadata {
+            stats.add(metadata);
+        }
+        stats.records += 1;
+        output_file.write(task, id, row)?;
+    }
+    output_file.finish()?;
+
+    stats.print(start_time.elapsed());
+    Ok(())
+}
+
+/// `rerank_permits` is held while a rerank record acquires the permits of all its texts
+async fn run_record(
+    task: OfflineTask,
+    served_model: &ServedModel,
+    truncate: bool,
+    raw_scores: bool,
+    rerank_permits: &tokio::sync::Mutex<()>,
+    record: Record,
+) -> Result<(serde_json::Value, Vec<f32>, Vec<InferMetadata>)> {
+    let infer = &served_model.infer;
+    match task {
+        OfflineTask::Embed => {
+            let Some(Inputs::Single(inputs)) = record.inputs else {
+                anyhow::bail!("`inputs` must be a string");
+            };
+            let permit = infer.acquire_permit().await;
+            let response = infer
+                .embed_pooled(
+                    inputs,
+                    truncate,
+                    TruncationDirection::Right,
+                    None,
+                    true,
+                    None,
+                    permit,
+                    None,
+                )
+                .await?;
+            Ok((record.id, response.results, vec![response.metadata]))
+        }
+        OfflineTask::Predict => {
+            let inputs: EncodingInput = match record.inputs {
+                Some(Inputs::Single(text)) => text.into(),
+                Some(Inputs::Pair(first, second)) => (first, second).into(),
+                None => anyhow::bail!("`inputs` is missing"),
+            };
+            let permit = infer.acquire_permit().await;
+            let response = infer
+                .predict(
+                    inputs,
+                    truncate,
+                    TruncationDirection::Right,
+                    raw_scores,
+                    permit,
+                    None,
+                )
+                .await?;
+            Ok((record.id, response.results, vec![response.metadata]))
+        }
+        OfflineTask::Rerank => {
+            let Some(query) = record.query else {
+                anyhow::bail!("`query` is missing");
+            };
+            if record.texts.is_empty() {
+                anyhow::bail!("`texts` cannot be empty");
+            }
+            let batch_size = record.texts.len();
+            let max_batch_size = served_model.info.max_client_batch_size;
+            if batch_size > max_batch_size {
+                anyhow::bail!(
+                    "batch size {batch_size} > maximum allowed batch size {max_batch_size}"
+                );
+            }
+
+            // The texts of a record are only batched once they are all queued: records that
+            // each hold some of the permits would wait for each other forever
+            let permits = {
+                let _guard = rerank_permits.lock().await;
+                let mut permits = Vec::with_capacity(batch_size);
+                for _ in 0..batch_size {
+                    permits.push(infer.acquire_permit().await);
+                }
+                permits
+            };
+
+            let batch_counter = if record.texts.len() == 1 {
+                None
+            } else {
+                Some(Arc::new(AtomicUsize::new(record.texts.len())))
+            };
+            let texts = record.texts.into_iter().zip(permits);
+            let responses = try_join_all(texts.map(|(text, permit)| {
+                let query = query.clone();
+                let batch_counter = batch_counter.clone();
+                async move {
+                    infer
+                        .predict(
+                            (query, text),
+                            truncate,
+                            TruncationDirection::Right,
+                            raw_scores,
+                            permit,
+                            batch_counter,
+                        )
+                        .await
+                }
+            }))
+            .await?;
+
+            let (scores, metadata) = responses
+                .into_iter()
+                .map(|response| (response.results[0], response.metadata))
+                .unzip();
+            Ok((record.id, scores, metadata))
+        }
+    }
+}
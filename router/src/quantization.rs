@@ -0,0 +1,225 @@
+//! Quantization of the embeddings returned to the clients
+use crate::{ErrorResponse, ErrorType};
+use anyhow::Context;
+use half::f16;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use text_embeddings_core::download::QUANTIZATION_RANGES_NAME;
+
+/// Output precision of the embeddings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Precision {
+    #[default]
+    Float,
+    Float16,
+    Int8,
+    Uint8,
+    Binary,
+    Ubinary,
+}
+
+/// Per dimension calibration ranges used for scalar (int8 and uint8) quantization
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuantizationRanges {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+impl QuantizationRanges {
+    /// Load the calibration ranges stored next to the model, if any
+    pub fn load(model_root: &Path) -> anyhow::Result<Option<Self>> {
+        let path = model_root.join(QUANTIZATION_RANGES_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let ranges = std::fs::read_to_string(&path)
+            .context(format!("Failed to read `{}`", path.display()))?;
+        let ranges: Self = serde_json::from_str(&ranges)
+            .context(format!("Failed to parse `{}`", path.display()))?;
+
+        if ranges.min.len() != ranges.max.len() {
+            anyhow::bail!(
+                "`{}` is invalid: `min` has {} values but `max` has {}",
+                path.display(),
+                ranges.min.len(),
+                ranges.max.len()
+            );
+        }
+        Ok(Some(ranges))
+    }
+}
+
+/// Embedding converted to the requested precision
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuantizedEmbedding {
+    Float(Vec<f32>),
+    Float16(Vec<f16>),
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+}
+
+impl QuantizedEmbedding {
+    /// Raw little-endian bytes of the embedding
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        match self {
+            QuantizedEmbedding::Float(e) => e.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            QuantizedEmbedding::Float16(e) => e.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            QuantizedEmbedding::Int8(e) => e.iter().map(|v| *v as u8).collect(),
+            QuantizedEmbedding::Uint8(e) => e.clone(),
+        }
+    }
+}
+
+/// Convert a float embedding to the requested precision
+///
+/// Scalar quantization uses the calibration ranges if they are given, and the fixed
+/// `[-1.0, 1.0]` range of the values of normalized embeddings otherwise. Binary quantization
+/// packs the sign bits, most significant bit first.
+pub fn quantize(
+    embedding: Vec<f32>,
+    precision: Precision,
+    ranges: Option<&QuantizationRanges>,
+) -> Result<QuantizedEmbedding, ErrorResponse> {
+    let quantized = match precision {
+        Precision::Float => QuantizedEmbedding::Float(embedding),
+        Precision::Float16 => {
+            QuantizedEmbedding::Float16(embedding.into_iter().map(f16::from_f32).collect())
+        }
+        Precision::Int8 => QuantizedEmbedding::Int8(
+            scalar_quantize(&embedding, ranges)?
+                .into_iter()
+                .map(|v| (v as i16 - 128) as i8)
+                .collect(),
+        ),
+        Precision::Uint8 => QuantizedEmbedding::Uint8(scalar_quantize(&embedding, ranges)?),
+        Precision::Binary => QuantizedEmbedding::Int8(
+            pack_bits(&embedding)
+                .into_iter()
+                .map(|v| (v as i16 - 128) as i8)
+                .collect(),
+        ),
+        Precision::Ubinary => QuantizedEmbedding::Uint8(pack_bits(&embedding)),
+    };
+    Ok(quantized)
+}
+
+/// Range of every dimension of unit-normalized embeddings, used without calibration ranges
+const NORMALIZED_RANGE: (f32, f32) = (-1.0, 1.0);
+
+/// Map each value to one of 256 buckets between the min and max of its dimension
+///
+/// Without calibration ranges, the buckets are the same for every embedding so that quantized
+/// embeddings can be compared with each other. Values outside of the range, e.g. of embeddings
+/// that are not normalized, are clamped.
+fn scalar_quantize(
+    embedding: &[f32],
+    ranges: Option<&QuantizationRanges>,
+) -> Result<Vec<u8>, ErrorResponse> {
+    let bucket = |v: f32, min: f32, max: f32| {
+        let step = (max - min) / 255.0;
+        if step <= 0.0 {
+            return 0;
+        }
+        ((v - min) / step).round().clamp(0.0, 255.0) as u8
+    };
+
+    match ranges {
+        Some(ranges) => {
+            // Embeddings truncated with `dimensions` use the leading ranges
+            if ranges.min.len() < embedding.len() {
+                let message = format!(
+                    "quantization ranges have {} dimensions but the embedding has {}",
+                    ranges.min.len(),
+                    embedding.len()
+                );
+                tracing::error!("{message}");
+                metrics::counter!("te_request_failure", "err" => "validation").increment(1);
+                return Err(ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                });
+            }
+            Ok(embedding
+                .iter()
+                .zip(ranges.min.iter().zip(&ranges.max))
+                .map(|(v, (min, max))| bucket(*v, *min, *max))
+                .collect())
+        }
+        None => {
+            let (min, max) = NORMALIZED_RANGE;
+            Ok(embedding.iter().map(|v| bucket(*v, min, max)).collect())
+        }
+    }
+}
+
+/// Pack the sign of each value in bits, padding the last byte with zeros
+fn pack_bits(embedding: &[f32]) -> Vec<u8> {
+    embedding
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, v)| byte | (((*v > 0.0) as u8) << (7 - i)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary() {
+        let embedding = vec![0.1, -0.2, 0.3, 0.0, 0.5, 0.6, -0.7, 0.8, 0.9];
+
+        let ubinary = quantize(embedding.clone(), Precision::Ubinary, None).unwrap();
+        assert_eq!(
+            ubinary,
+            QuantizedEmbedding::Uint8(vec![0b1010_1101, 0b1000_0000])
+        );
+
+        let binary = quantize(embedding, Precision::Binary, None).unwrap();
+        assert_eq!(binary, QuantizedEmbedding::Int8(vec![45, 0]));
+    }
+
+    #[test]
+    fn test_scalar() {
+        let embedding = vec![-1.0, 0.0, 1.0];
+
+        let uint8 = quantize(embedding.clone(), Precision::Uint8, None).unwrap();
+        assert_eq!(uint8, QuantizedEmbedding::Uint8(vec![0, 128, 255]));
+
+        let int8 = quantize(embedding.clone(), Precision::Int8, None).unwrap();
+        assert_eq!(int8, QuantizedEmbedding::Int8(vec![-128, 0, 127]));
+
+        // The buckets do not depend on the range of the embedding
+        let uint8 = quantize(vec![-0.5, 0.5, 2.0], Precision::Uint8, None).unwrap();
+        assert_eq!(uint8, QuantizedEmbedding::Uint8(vec![64, 191, 255]));
+
+        let ranges = QuantizationRanges {
+            min: vec![0.0, 0.0, 0.0, 0.0],
+            max: vec![1.0, 1.0, 1.0, 1.0],
+        };
+        let uint8 = quantize(embedding.clone(), Precision::Uint8, Some(&ranges)).unwrap();
+        assert_eq!(uint8, QuantizedEmbedding::Uint8(vec![0, 0, 255]));
+
+        let ranges = QuantizationRanges {
+            min: vec![0.0],
+            max: vec![1.0],
+        };
+        assert!(quantize(embedding, Precision::Uint8, Some(&ranges)).is_err());
+    }
+
+    #[test]
+    fn test_to_le_bytes() {
+        let float16 = quantize(vec![1.0, -2.0], Precision::Float16, None).unwrap();
+        assert_eq!(float16.to_le_bytes(), vec![0x00, 0x3c, 0x00, 0xc0]);
+
+        let int8 = QuantizedEmbedding::Int8(vec![-1, 1]);
+        assert_eq!(int8.to_le_bytes(), vec![255, 1]);
+    }
+}
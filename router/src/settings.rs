@@ -0,0 +1,55 @@
+//! Settings of the router, built from the command line arguments and the configuration file
+use text_embeddings_backend::{DType, Pool};
+
+/// Settings used to load every served model
+#[derive(Clone, Debug)]
+pub struct ModelSettings {
+    pub revision: Option<String>,
+    pub tokenization_workers: Option<usize>,
+    pub dtype: Option<DType>,
+    pub pooling: Option<Pool>,
+    pub max_concurrent_requests: usize,
+    pub max_batch_tokens: usize,
+    pub max_batch_requests: Option<usize>,
+    pub max_queue_time: Option<u64>,
+    pub priority_starvation_timeout: u64,
+    pub max_client_batch_size: usize,
+    pub auto_truncate: bool,
+    pub embedding_cache_size: Option<usize>,
+    pub bisect_failed_batches: bool,
+    pub default_prompt: Option<String>,
+    pub default_prompt_name: Option<String>,
+    pub dense_path: Option<String>,
+    pub multi_vector: bool,
+    pub hf_token: Option<String>,
+    /// Socket of the Python backend. Each additional model appends `-{i}` to it
+    pub uds_path: String,
+    pub huggingface_hub_cache: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub otlp_service_name: String,
+}
+
+/// Settings of the HTTP and gRPC servers
+#[derive(Clone, Debug)]
+pub struct ServerSettings {
+    pub hostname: Option<String>,
+    pub port: u16,
+    pub payload_limit: usize,
+    pub api_key: Option<String>,
+    pub api_keys_file: Option<String>,
+    pub admin_api_key: Option<String>,
+    pub prometheus_port: u16,
+    pub cors_allow_origin: Option<Vec<String>>,
+    pub jobs_dir: Option<String>,
+    pub jobs_input_dir: Option<String>,
+    pub jobs_max_concurrency: Option<usize>,
+    pub jobs_max_input_size: usize,
+    pub drain_timeout: u64,
+    pub grpc_port: Option<u16>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_client_ca: Option<String>,
+    pub listen_uds: Option<String>,
+    pub listen_uds_mode: String,
+    pub listen_uds_only: bool,
+}
@@ -1,4 +1,8 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::signal;
+use tokio::sync::{watch, Notify};
 
 /// Shutdown signal handler
 pub(crate) async fn shutdown_signal() {
@@ -26,3 +30,157 @@ pub(crate) async fn shutdown_signal() {
 
     tracing::info!("signal received, starting graceful shutdown");
 }
+
+/// Readiness and drain state of the router
+///
+/// The router is ready once every model is loaded and warmed up. When draining, it is not ready
+/// anymore and rejects new requests, while the in flight requests complete within the drain
+/// timeout. The server stops once they are done or when the timeout expires.
+#[derive(Debug, Clone)]
+pub(crate) struct Lifecycle {
+    inner: Arc<LifecycleInner>,
+}
+
+#[derive(Debug)]
+struct LifecycleInner {
+    ready: AtomicBool,
+    draining: watch::Sender<bool>,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    drain_timeout: Duration,
+}
+
+impl Lifecycle {
+    pub fn new(drain_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(LifecycleInner {
+                ready: AtomicBool::new(false),
+                draining: watch::channel(false).0,
+                in_flight: AtomicUsize::new(0),
+                idle: Notify::new(),
+                drain_timeout,
+            }),
+        }
+    }
+
+    /// Every model is loaded and warmed up
+    pub fn set_ready(&self) {
+        self.inner.ready.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.inner.ready.load(Ordering::SeqCst) && !self.is_draining()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.inner.draining.borrow()
+    }
+
+    /// Start draining. Returns `false` if the router was already draining.
+    pub fn drain(&self) -> bool {
+        let started = self
+            .inner
+            .draining
+            .send_if_modified(|draining| !std::mem::replace(draining, true));
+        if started {
+            tracing::info!(
+                "Draining: rejecting new requests, {} in flight",
+                self.inner.in_flight.load(Ordering::SeqCst)
+            );
+        }
+        started
+    }
+
+    /// Start draining on SIGTERM or Ctrl+C
+    pub fn drain_on_signal(&self) {
+        let lifecycle = self.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            lifecycle.drain();
+        });
+    }
+
+    /// Track an in flight request. Returns `None` when draining: the request must be rejected.
+    pub fn start_request(&self) -> Option<InFlight> {
+        self.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight {
+            inner: self.inner.clone(),
+        };
+        // Checked after the increment so that `drained` cannot miss this request
+        if self.is_draining() {
+            return None;
+        }
+        Some(in_flight)
+    }
+
+    /// Resolves once draining started
+    pub async fn draining(&self) {
+        let mut receiver = self.inner.draining.subscribe();
+        while !*receiver.borrow_and_update() {
+            // The sender lives as long as `self`
+            let _ = receiver.changed().await;
+        }
+    }
+
+    /// Resolves once draining started and every in flight request completed
+    pub async fn drained(self) {
+        self.draining().await;
+        loop {
+            let idle = self.inner.idle.notified();
+            if self.inner.in_flight.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            idle.await;
+        }
+        tracing::info!("Drained, shutting down");
+    }
+
+    /// Resolves once the drain timeout expired
+    pub async fn deadline(self) {
+        self.draining().await;
+        tokio::time::sleep(self.inner.drain_timeout).await;
+        tracing::warn!(
+            "Drain timeout expired with {} requests in flight",
+            self.inner.in_flight.load(Ordering::SeqCst)
+        );
+    }
+}
+
+/// In flight request, tracked until dropped
+#[derive(Debug)]
+pub(crate) struct InFlight {
+    inner: Arc<LifecycleInner>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.inner.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain() {
+        let lifecycle = Lifecycle::new(Duration::from_secs(60));
+        assert!(!lifecycle.is_ready());
+        lifecycle.set_ready();
+        assert!(lifecycle.is_ready());
+
+        let request = lifecycle.start_request().unwrap();
+        assert!(lifecycle.drain());
+        assert!(!lifecycle.drain());
+        assert!(!lifecycle.is_ready());
+        assert!(lifecycle.start_request().is_none());
+
+        let drained = tokio::spawn(lifecycle.clone().drained());
+        tokio::task::yield_now().await;
+        assert!(!drained.is_finished());
+        drop(request);
+        drained.await.unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+//! Sentence similarity shared by the HTTP and gRPC APIs
+use crate::{ErrorResponse, ErrorType};
+use serde::Deserialize;
+use simsimd::SpatialSimilarity;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SimilarityMetric {
+    /// Cosine similarity
+    #[default]
+    Cosine,
+    /// Dot product
+    Dot,
+    /// Euclidean distance
+    Euclidean,
+    /// Manhattan distance
+    Manhattan,
+}
+
+impl SimilarityMetric {
+    /// Compare two embeddings
+    pub fn compute(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            SimilarityMetric::Cosine => 1.0 - f32::cosine(a, b).unwrap() as f32,
+            SimilarityMetric::Dot => f32::dot(a, b).unwrap() as f32,
+            SimilarityMetric::Euclidean => f32::sqeuclidean(a, b).unwrap().sqrt() as f32,
+            SimilarityMetric::Manhattan => a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum(),
+        }
+    }
+}
+
+/// Texts of a similarity request, each unique text being embedded only once
+#[derive(Debug)]
+pub(crate) struct SimilarityInputs {
+    /// Unique texts to embed
+    pub texts: Vec<String>,
+    /// Index in `texts` of each source sentence
+    sources: Vec<usize>,
+    /// Index in `texts` of each sentence the sources are compared with
+    sentences: Vec<usize>,
+}
+
+impl SimilarityInputs {
+    /// Validate the request. The sources are compared against each other if `sentences` is not
+    /// set.
+    pub fn new(
+        sources: Vec<String>,
+        sentences: Option<Vec<String>>,
+        max_client_batch_size: usize,
+    ) -> Result<Self, ErrorResponse> {
+        if sources.is_empty() || sentences.as_ref().is_some_and(|s| s.is_empty()) {
+            let message = "`inputs.sentences` cannot be empty".to_string();
+            tracing::error!("{message}");
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            return Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Empty,
+            });
+        }
+
+        let mut unique = HashMap::new();
+        let mut texts = Vec::new();
+        let mut index = |text: String| {
+            *unique.entry(text.clone()).or_insert_with(|| {
+                texts.push(text);
+                texts.len() - 1
+            })
+        };
+        let sources: Vec<usize> = sources.into_iter().map(&mut index).collect();
+        let sentences: Vec<usize> = match sentences {
+            Some(sentences) => sentences.into_iter().map(&mut index).collect(),
+            None => sources.clone(),
+        };
+
+        let batch_size = texts.len();
+        if batch_size > max_client_batch_size {
+            let message = format!(
+                "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+            );
+            tracing::error!("{message}");
+            let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+            counter.increment(1);
+            return Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            });
+        }
+
+        Ok(Self {
+            texts,
+            sources,
+            sentences,
+        })
+    }
+
+    /// Scores of each source against each sentence, given the embeddings of `texts`
+    pub fn scores(&self, metric: SimilarityMetric, embeddings: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        self.sources
+            .iter()
+            .map(|i| {
+                self.sentences
+                    .iter()
+                    .map(|j| metric.compute(&embeddings[*i], &embeddings[*j]))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_similarity_inputs() {
+        let inputs =
+            SimilarityInputs::new(strings(&["a", "b"]), Some(strings(&["b", "c", "a"])), 8)
+                .unwrap();
+        assert_eq!(inputs.texts, strings(&["a", "b", "c"]));
+
+        let embeddings = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]];
+        let scores = inputs.scores(SimilarityMetric::Manhattan, &embeddings);
+        assert_eq!(scores, vec![vec![2.0, 1.0, 0.0], vec![0.0, 1.0, 2.0]]);
+
+        // Without sentences, the sources are compared against each other
+        let inputs = SimilarityInputs::new(strings(&["a", "b"]), None, 8).unwrap();
+        let scores = inputs.scores(SimilarityMetric::Dot, &embeddings);
+        assert_eq!(scores, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+
+    #[test]
+    fn test_similarity_inputs_validation() {
+        let err = SimilarityInputs::new(strings(&["a"]), Some(vec![]), 8).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Empty));
+
+        let err = SimilarityInputs::new(vec![], None, 8).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Empty));
+
+        // Duplicated texts only count once in the batch size
+        assert!(SimilarityInputs::new(strings(&["a", "a"]), Some(strings(&["a"])), 1).is_ok());
+        let err = SimilarityInputs::new(strings(&["a", "b"]), None, 1).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Validation));
+    }
+}
@@ -0,0 +1,456 @@
+//! TLS termination of the HTTP and gRPC listeners, with optional client certificates
+use anyhow::Context;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock, Weak};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// How often the certificate files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+/// Maximum duration of a TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate files of `--tls-cert`, `--tls-key` and `--tls-client-ca`
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA of the client certificates. Clients must present a certificate signed by it if set.
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsFiles {
+    /// Last modification time of each file
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [Some(&self.cert), Some(&self.key), self.client_ca.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    fn server_config(&self, alpn_protocols: &[&[u8]]) -> anyhow::Result<ServerConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(client_ca)? {
+                    roots
+                        .add(cert)
+                        .context(format!("Invalid certificate in `{}`", client_ca.display()))?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()
+                        .context("Failed to build the client certificate verifier")?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(read_certs(&self.cert)?, read_key(&self.key)?)
+            .context("Invalid TLS certificate or key")?;
+        config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
+        Ok(config)
+    }
+}
+
+fn read_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).context(format!("Failed to open `{}`", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("Failed to parse `{}`", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("`{}` does not contain any certificate", path.display());
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).context(format!("Failed to open `{}`", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .context(format!("Failed to parse `{}`", path.display()))?
+        .ok_or_else(|| anyhow::anyhow!("`{}` does not contain any private key", path.display()))
+}
+
+/// Subject of a client certificate, used as the tenant identity
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIdentity(pub String);
+
+impl ClientIdentity {
+    /// Common name of the certificate, or its full subject if it has none
+    fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let subject = cert.subject();
+        let name = subject
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(String::from)
+            .unwrap_or_else(|| subject.to_string());
+        Some(Self(name))
+    }
+}
+
+/// Connection information of a TLS connection, added to the extensions of its requests
+#[derive(Debug, Clone)]
+pub struct TlsConnectInfo {
+    pub client_identity: Option<ClientIdentity>,
+}
+
+/// Accept TLS connections with the latest version of the certificate files
+#[derive(Debug, Clone)]
+pub(crate) struct TlsAcceptor {
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl TlsAcceptor {
+    /// Load the certificate files and reload them every time they change
+    pub fn new(files: TlsFiles, alpn_protocols: &'static [&'static [u8]]) -> anyhow::Result<Self> {
+        let config = Arc::new(RwLock::new(Arc::new(files.server_config(alpn_protocols)?)));
+
+        // Stop reloading once the acceptor is dropped
+        let weak_config = Arc::downgrade(&config);
+        tokio::spawn(reload_task(files, alpn_protocols, weak_config));
+
+        Ok(Self { config })
+    }
+
+    /// Run the TLS handshake of a TCP connection
+    pub async fn accept(
+        &self,
+        stream: TcpStream,
+        remote_addr: SocketAddr,
+    ) -> Option<TlsConnection> {
+        let acceptor = tokio_rustls::TlsAcceptor::from(self.config.read().unwrap().clone());
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+            Ok(Ok(stream)) => Some(TlsConnection::new(stream)),
+            Ok(Err(err)) => {
+                tracing::debug!("TLS handshake with {remote_addr} failed: {err}");
+                None
+            }
+            Err(_) => {
+                tracing::debug!("TLS handshake with {remote_addr} timed out");
+                None
+            }
+        }
+    }
+
+    /// Accept the TCP connections of `listener` and send the ones that completed their handshake
+    ///
+    /// Handshakes run concurrently so that a slow client does not block the others.
+    #[cfg(feature = "grpc")]
+    pub fn incoming(
+        self,
+        listener: tokio::net::TcpListener,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<TlsConnection> {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(err) => {
+                            tracing::error!("Failed to accept TCP connection: {err}");
+                            // Errors such as running out of file descriptors would spin the loop, axum also
+                            // waits before accepting again
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    },
+                    // The server stopped
+                    _ = sender.closed() => break,
+                };
+
+                let acceptor = self.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    if let Some(connection) = acceptor.accept(stream, remote_addr).await {
+                        let _ = sender.send(connection);
+                    }
+                });
+            }
+        });
+        receiver
+    }
+}
+
+async fn reload_task(
+    files: TlsFiles,
+    alpn_protocols: &'static [&'static [u8]],
+    config: Weak<RwLock<Arc<ServerConfig>>>,
+) {
+    let mut modified = files.modified();
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let Some(config) = config.upgrade() else {
+            break;
+        };
+
+        reload(&files, alpn_protocols, &config, &mut modified);
+    }
+}
+
+/// Replace `config` if the certificate files changed since `modified`
+fn reload(
+    files: &TlsFiles,
+    alpn_protocols: &[&[u8]],
+    config: &RwLock<Arc<ServerConfig>>,
+    modified: &mut Vec<Option<SystemTime>>,
+) {
+    let current = files.modified();
+    if current == *modified {
+        return;
+    }
+
+    // New connections use the new certificates, established ones keep the previous ones
+    match files.server_config(alpn_protocols) {
+        Ok(server_config) => {
+            *config.write().unwrap() = Arc::new(server_config);
+            *modified = current;
+            tracing::info!("Reloaded TLS certificate `{}`", files.cert.display());
+        }
+        // The files may be partially written: retry on the next tick
+        Err(err) => tracing::error!("Keeping the previous TLS certificate: {err:#}"),
+    }
+}
+
+/// Server side of a TLS connection
+#[derive(Debug)]
+pub struct TlsConnection {
+    stream: TlsStream<TcpStream>,
+    connect_info: TlsConnectInfo,
+}
+
+impl TlsConnection {
+    fn new(stream: TlsStream<TcpStream>) -> Self {
+        let client_identity = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| ClientIdentity::from_der(cert.as_ref()));
+        Self {
+            stream,
+            connect_info: TlsConnectInfo { client_identity },
+        }
+    }
+
+    #[cfg(feature = "http")]
+    pub fn connect_info(&self) -> TlsConnectInfo {
+        self.connect_info.clone()
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl tonic::transport::server::Connected for TlsConnection {
+    type ConnectInfo = TlsConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.connect_info.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use rustls::ClientConfig;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    fn touch(path: &Path, secs: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    fn client_config(client: Option<&str>) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(read_certs(&fixture("ca.pem")).unwrap().remove(0))
+            .unwrap();
+        let builder =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots);
+        match client {
+            Some(client) => builder
+                .with_client_auth_cert(
+                    read_certs(&fixture(&format!("{client}.pem"))).unwrap(),
+                    read_key(&fixture(&format!("{client}.key"))).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        }
+    }
+
+    /// Run a handshake between `acceptor` and a client presenting the `client` certificate
+    async fn handshake(acceptor: &TlsAcceptor, client: Option<&str>) -> Option<TlsConnection> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config(client)));
+        tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let server_name = ServerName::try_from("localhost").unwrap();
+            // With TLS 1.3 the server verifies the client certificate after the client finished
+            // its handshake: keep the connection open until the server closes it
+            if let Ok(mut stream) = connector.connect(server_name, stream).await {
+                let _ = stream.read_to_end(&mut Vec::new()).await;
+            }
+        });
+
+        let (stream, remote_addr) = listener.accept().await.unwrap();
+        acceptor.accept(stream, remote_addr).await
+    }
+
+    #[test]
+    fn test_client_identity() {
+        let identity = |name: &str| {
+            let certs = read_certs(&fixture(name)).unwrap();
+            ClientIdentity::from_der(certs[0].as_ref())
+        };
+
+        assert_eq!(
+            identity("client.pem"),
+            Some(ClientIdentity("tenant-a".to_string()))
+        );
+        // Certificates without a common name are identified by their full subject
+        assert_eq!(
+            identity("client-no-cn.pem"),
+            Some(ClientIdentity("O=Acme".to_string()))
+        );
+        assert_eq!(ClientIdentity::from_der(b"invalid"), None);
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("tei-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = TlsFiles {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            client_ca: None,
+        };
+        std::fs::copy(fixture("server.pem"), &files.cert).unwrap();
+        std::fs::copy(fixture("server.key"), &files.key).unwrap();
+
+        let config = RwLock::new(Arc::new(files.server_config(&[]).unwrap()));
+        let mut modified = files.modified();
+        let previous = config.read().unwrap().clone();
+
+        // Unchanged files are not reloaded
+        reload(&files, &[], &config, &mut modified);
+        assert!(Arc::ptr_eq(&previous, &config.read().unwrap()));
+
+        // A partially written certificate keeps the previous configuration
+        std::fs::write(&files.cert, "").unwrap();
+        touch(&files.cert, 1);
+        reload(&files, &[], &config, &mut modified);
+        assert!(Arc::ptr_eq(&previous, &config.read().unwrap()));
+
+        // The certificate is reloaded once it is complete
+        std::fs::copy(fixture("server.pem"), &files.cert).unwrap();
+        touch(&files.cert, 2);
+        reload(&files, &[], &config, &mut modified);
+        assert!(!Arc::ptr_eq(&previous, &config.read().unwrap()));
+        assert_eq!(modified, files.modified());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_certificates() {
+        let acceptor = TlsAcceptor::new(
+            TlsFiles {
+                cert: fixture("server.pem"),
+                key: fixture("server.key"),
+                client_ca: Some(fixture("ca.pem")),
+            },
+            &[],
+        )
+        .unwrap();
+
+        let connection = handshake(&acceptor, Some("client")).await.unwrap();
+        assert_eq!(
+            connection.connect_info.client_identity,
+            Some(ClientIdentity("tenant-a".to_string()))
+        );
+
+        // Certificates signed by another CA are rejected, even with the same subject
+        assert!(handshake(&acceptor, Some("other-client")).await.is_none());
+        // So are clients without a certificate
+        assert!(handshake(&acceptor, None).await.is_none());
+    }
+}
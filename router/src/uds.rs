@@ -0,0 +1,137 @@
+//! Unix domain socket of `--listen-uds`, served next to or instead of the TCP listener
+#[cfg(unix)]
+use anyhow::Context;
+#[cfg(unix)]
+use std::fs::Permissions;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// Path and permissions of the socket file
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    pub path: PathBuf,
+    /// Permission bits of the socket file. Clients need write access to connect.
+    pub mode: u32,
+}
+
+impl UnixSocket {
+    /// Parse the octal permissions of `--listen-uds-mode`
+    pub fn parse_mode(mode: &str) -> anyhow::Result<u32> {
+        u32::from_str_radix(mode, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o777)
+            .ok_or_else(|| anyhow::anyhow!("`{mode}` is not a valid octal file mode"))
+    }
+
+    /// Bind the socket, replacing the socket file left by a previous run
+    ///
+    /// The socket file is removed when the returned `SocketFile` is dropped.
+    #[cfg(unix)]
+    pub(crate) fn bind(&self) -> anyhow::Result<(UnixListener, SocketFile)> {
+        match std::fs::symlink_metadata(&self.path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                std::fs::remove_file(&self.path)
+                    .context(format!("Failed to remove `{}`", self.path.display()))?;
+            }
+            Ok(_) => anyhow::bail!("`{}` exists and is not a socket", self.path.display()),
+            Err(_) => {}
+        }
+
+        // Bind in a private directory and move the socket in place once its permissions are set,
+        // so that clients can never connect with the default permissions
+        let file_name = self
+            .path
+            .file_name()
+            .context("`--listen-uds` has no file name")?;
+        let private_dir = self.path.with_file_name(format!(
+            ".{}.{}",
+            file_name.to_string_lossy(),
+            std::process::id()
+        ));
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&private_dir)
+            .context(format!("Failed to create `{}`", private_dir.display()))?;
+        let result = bind_private(&private_dir.join(file_name), &self.path, self.mode);
+        let _ = std::fs::remove_dir_all(&private_dir);
+        let listener = result.context(format!(
+            "Could not bind Unix socket on `{}`",
+            self.path.display()
+        ))?;
+        Ok((listener, SocketFile(self.path.clone())))
+    }
+}
+
+/// Bind the socket on `private_path`, restrict its permissions to `mode` and move it to `path`
+#[cfg(unix)]
+fn bind_private(private_path: &Path, path: &Path, mode: u32) -> std::io::Result<UnixListener> {
+    let listener = UnixListener::bind(private_path)?;
+    std::fs::set_permissions(private_path, Permissions::from_mode(mode))?;
+    std::fs::rename(private_path, path)?;
+    Ok(listener)
+}
+
+/// Socket file of a bound `UnixSocket`, removed on drop
+#[cfg(unix)]
+#[derive(Debug)]
+pub(crate) struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl SocketFile {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(UnixSocket::parse_mode("660").unwrap(), 0o660);
+        assert_eq!(UnixSocket::parse_mode("0600").unwrap(), 0o600);
+        assert!(UnixSocket::parse_mode("rw").is_err());
+        assert!(UnixSocket::parse_mode("1777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind() {
+        let path = std::env::temp_dir().join(format!("tei-test-{}.sock", std::process::id()));
+        let socket = UnixSocket {
+            path: path.clone(),
+            mode: 0o600,
+        };
+
+        let (listener, socket_file) = socket.bind().unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        // A stale socket file is replaced
+        std::mem::forget(socket_file);
+        drop(listener);
+        let (_listener, socket_file) = socket.bind().unwrap();
+
+        // The socket file is removed once the server stops
+        drop(socket_file);
+        assert!(!path.exists());
+
+        // Other files are never replaced
+        std::fs::write(&path, "").unwrap();
+        assert!(socket.bind().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
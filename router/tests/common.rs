@@ -2,11 +2,11 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use text_embeddings_backend::DType;
-use text_embeddings_router::run;
+use text_embeddings_router::{run, ModelSettings, ServerSettings};
 use tokio::time::Instant;
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Score(f32);
+pub struct Score(pub f32);
 
 impl Score {
     fn is_close(&self, other: &Self, abs_tol: f32) -> bool {
@@ -32,7 +32,9 @@ async fn check_health(port: u16, timeout: Duration) -> Result<()> {
 
     let start = Instant::now();
     loop {
-        if client.get(&addr).send().await.is_ok() {
+        // The probes answer with a 503 while the model is loading
+        let response = client.get(&addr).send().await;
+        if response.is_ok_and(|response| response.status().is_success()) {
             return Ok(());
         }
         if start.elapsed() < timeout {
@@ -43,33 +45,68 @@ async fn check_health(port: u16, timeout: Duration) -> Result<()> {
     }
 }
 
+// Unused by the tests that also serve gRPC
+#[allow(dead_code)]
 pub async fn start_server(model_id: String, revision: Option<String>, dtype: DType) -> Result<()> {
+    start_server_with_grpc(vec![model_id], revision, dtype, None).await
+}
+
+/// Serve `model_ids` over HTTP, and over gRPC on `grpc_port` if set
+pub async fn start_server_with_grpc(
+    model_ids: Vec<String>,
+    revision: Option<String>,
+    dtype: DType,
+    grpc_port: Option<u16>,
+) -> Result<()> {
     let server_task = tokio::spawn({
         run(
-            model_id,
-            revision,
-            Some(1),
-            Some(dtype),
-            None,
-            4,
-            1024,
-            None,
-            32,
-            false,
-            None,
-            None,
-            None,
-            None,
-            None,
-            8090,
-            None,
-            None,
-            2_000_000,
-            None,
-            None,
-            "text-embeddings-inference.server".to_owned(),
-            9000,
-            None,
+            model_ids,
+            ModelSettings {
+                revision,
+                tokenization_workers: Some(1),
+                dtype: Some(dtype),
+                pooling: None,
+                max_concurrent_requests: 4,
+                max_batch_tokens: 1024,
+                max_batch_requests: None,
+                max_queue_time: None,
+                priority_starvation_timeout: 5000,
+                max_client_batch_size: 32,
+                auto_truncate: false,
+                embedding_cache_size: None,
+                bisect_failed_batches: false,
+                default_prompt: None,
+                default_prompt_name: None,
+                dense_path: None,
+                multi_vector: false,
+                hf_token: None,
+                uds_path: "/tmp/text-embeddings-inference-server".to_string(),
+                huggingface_hub_cache: None,
+                otlp_endpoint: None,
+                otlp_service_name: "text-embeddings-inference.server".to_owned(),
+            },
+            ServerSettings {
+                hostname: None,
+                port: 8090,
+                payload_limit: 2_000_000,
+                api_key: None,
+                api_keys_file: None,
+                admin_api_key: None,
+                prometheus_port: 9000,
+                cors_allow_origin: None,
+                jobs_dir: None,
+                jobs_input_dir: None,
+                jobs_max_concurrency: None,
+                jobs_max_input_size: 1 << 30,
+                drain_timeout: 30,
+                grpc_port,
+                tls_cert: None,
+                tls_key: None,
+                tls_client_ca: None,
+                listen_uds: None,
+                listen_uds_mode: "660".to_string(),
+                listen_uds_only: false,
+            },
         )
     });
 
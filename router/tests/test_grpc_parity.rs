@@ -0,0 +1,220 @@
+#![cfg(all(feature = "http", feature = "grpc"))]
+mod common;
+
+mod pb {
+    tonic::include_proto!("tei.v1");
+}
+
+use crate::common::{start_server_with_grpc, Score};
+use anyhow::Result;
+use pb::embed_client::EmbedClient;
+use pb::rerank_client::RerankClient;
+use pb::similarity_client::SimilarityClient;
+use pb::vertex_client::VertexClient;
+use pb::{
+    vertex_instance, vertex_prediction, EmbedRequest, RerankRequest, SimilarityRequest, Usage,
+    VertexInstance, VertexRequest,
+};
+use serde_json::{json, Value};
+use text_embeddings_backend::DType;
+use tonic::transport::Channel;
+
+const EMBEDDING_MODEL: &str = "sentence-transformers/all-MiniLM-L6-v2";
+const RERANKER_MODEL: &str = "tomaarsen/Qwen3-Reranker-0.6B-seq-cls";
+
+fn scores(values: &[f32]) -> Vec<Score> {
+    values.iter().map(|v| Score(*v)).collect()
+}
+
+// The routes that only the HTTP API serves are listed in the `gRPC` section of the README
+#[tokio::test]
+async fn test_grpc_http_parity() -> Result<()> {
+    start_server_with_grpc(
+        vec![EMBEDDING_MODEL.to_string(), RERANKER_MODEL.to_string()],
+        None,
+        DType::Float32,
+        Some(8091),
+    )
+    .await?;
+    let client = reqwest::Client::new();
+    let channel = Channel::from_static("http://0.0.0.0:8091")
+        .connect()
+        .await?;
+
+    // Embed with reduced dimensions
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&json!({"inputs": "test", "dimensions": 128}))
+        .send()
+        .await?;
+    let http_tokens: u32 = res.headers()["x-compute-tokens"].to_str()?.parse()?;
+    let http_embeddings = res.json::<Vec<Vec<Score>>>().await?;
+
+    let embed_request = EmbedRequest {
+        inputs: "test".to_string(),
+        normalize: true,
+        dimensions: Some(128),
+        ..Default::default()
+    };
+    let grpc_response = EmbedClient::new(channel.clone())
+        .embed(embed_request.clone())
+        .await?
+        .into_inner();
+    assert_eq!(grpc_response.embeddings.len(), 128);
+    assert_eq!(http_embeddings[0], scores(&grpc_response.embeddings));
+    assert_eq!(
+        grpc_response.usage,
+        Some(Usage {
+            prompt_tokens: http_tokens,
+            total_tokens: http_tokens,
+        })
+    );
+
+    // Both APIs reject the same invalid dimensions
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&json!({"inputs": "test", "dimensions": 1024}))
+        .send()
+        .await?;
+    assert_eq!(res.status(), 400);
+    let http_error = res.json::<Value>().await?;
+
+    let grpc_error = EmbedClient::new(channel.clone())
+        .embed(EmbedRequest {
+            dimensions: Some(1024),
+            ..embed_request.clone()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(grpc_error.code(), tonic::Code::InvalidArgument);
+    assert_eq!(http_error["error"], grpc_error.message());
+
+    // Similarity matrix
+    let res = client
+        .post("http://0.0.0.0:8090/similarity")
+        .json(&json!({
+            "inputs": {
+                "source_sentence": ["What is Deep Learning?", "What is a GPU?"],
+                "sentences": ["What is Machine Learning?", "What is Deep Learning?"],
+            }
+        }))
+        .send()
+        .await?;
+    let http_tokens: u32 = res.headers()["x-compute-tokens"].to_str()?.parse()?;
+    let http_scores = res.json::<Vec<Vec<Score>>>().await?;
+
+    let grpc_response = SimilarityClient::new(channel.clone())
+        .similarity(SimilarityRequest {
+            source_sentences: vec![
+                "What is Deep Learning?".to_string(),
+                "What is a GPU?".to_string(),
+            ],
+            sentences: vec![
+                "What is Machine Learning?".to_string(),
+                "What is Deep Learning?".to_string(),
+            ],
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    let grpc_scores: Vec<Vec<Score>> = grpc_response
+        .scores
+        .iter()
+        .map(|row| scores(&row.scores))
+        .collect();
+    assert_eq!(http_scores, grpc_scores);
+    assert_eq!(grpc_response.usage.unwrap().prompt_tokens, http_tokens);
+
+    // Both APIs reject the same invalid request
+    let res = client
+        .post("http://0.0.0.0:8090/similarity")
+        .json(&json!({"inputs": {"source_sentence": [], "sentences": ["test"]}}))
+        .send()
+        .await?;
+    assert_eq!(res.status(), 400);
+    let http_error = res.json::<Value>().await?;
+
+    let grpc_error = SimilarityClient::new(channel.clone())
+        .similarity(SimilarityRequest {
+            sentences: vec!["test".to_string()],
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(grpc_error.code(), tonic::Code::InvalidArgument);
+    assert_eq!(http_error["error"], grpc_error.message());
+
+    // Rerank with the prompt template and an instruction
+    let res = client
+        .post("http://0.0.0.0:8090/rerank")
+        .json(&json!({
+            "model": RERANKER_MODEL,
+            "query": "What is Deep Learning?",
+            "texts": ["Deep Learning is a subfield of Machine Learning.", "GPUs are fast."],
+            "instruction": "Find the definition of the query",
+            "use_template": true,
+        }))
+        .send()
+        .await?;
+    let http_tokens: u32 = res.headers()["x-compute-tokens"].to_str()?.parse()?;
+    let http_ranks = res.json::<Value>().await?;
+
+    let grpc_response = RerankClient::new(channel.clone())
+        .rerank(RerankRequest {
+            model: Some(RERANKER_MODEL.to_string()),
+            query: "What is Deep Learning?".to_string(),
+            texts: vec![
+                "Deep Learning is a subfield of Machine Learning.".to_string(),
+                "GPUs are fast.".to_string(),
+            ],
+            instruction: Some("Find the definition of the query".to_string()),
+            use_template: Some(true),
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    let http_ranks: Vec<(u32, Score)> = http_ranks
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rank| {
+            let index = rank["index"].as_u64().unwrap() as u32;
+            (index, Score(rank["score"].as_f64().unwrap() as f32))
+        })
+        .collect();
+    let grpc_ranks: Vec<(u32, Score)> = grpc_response
+        .ranks
+        .iter()
+        .map(|rank| (rank.index, Score(rank.score)))
+        .collect();
+    assert_eq!(http_ranks, grpc_ranks);
+    assert_eq!(grpc_response.usage.unwrap().prompt_tokens, http_tokens);
+
+    // Vertex
+    let res = client
+        .post("http://0.0.0.0:8090/vertex")
+        .json(&json!({"instances": [{"inputs": "test", "dimensions": 128}]}))
+        .send()
+        .await?;
+    let http_predictions = res.json::<Value>().await?;
+    let http_predictions: Vec<Vec<Vec<Score>>> =
+        serde_json::from_value(http_predictions["predictions"].clone())?;
+
+    let grpc_response = VertexClient::new(channel)
+        .predict(VertexRequest {
+            instances: vec![VertexInstance {
+                instance: Some(vertex_instance::Instance::Embed(embed_request)),
+            }],
+        })
+        .await?
+        .into_inner();
+    assert_eq!(grpc_response.predictions.len(), 1);
+    let Some(vertex_prediction::Prediction::Embed(grpc_embeddings)) =
+        &grpc_response.predictions[0].prediction
+    else {
+        panic!("expected an embedding prediction");
+    };
+    assert_eq!(http_predictions[0][0], scores(&grpc_embeddings.embeddings));
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use crate::common::{start_server, Score};
+use anyhow::Result;
+use serde_json::json;
+use text_embeddings_backend::DType;
+
+#[tokio::test]
+#[cfg(feature = "http")]
+async fn test_raw_embeddings() -> Result<()> {
+    start_server(
+        "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+        None,
+        DType::Float32,
+    )
+    .await?;
+
+    let request = json!({
+        "inputs": vec!["test", "other"],
+    });
+
+    let client = reqwest::Client::new();
+    let embeddings = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?
+        .json::<Vec<Vec<Score>>>()
+        .await?;
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .header("Accept", "application/octet-stream")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.headers()["x-embedding-shape"], "2,384");
+    assert_eq!(res.headers()["x-embedding-dtype"], "float32");
+
+    let values: Vec<f32> = res
+        .bytes()
+        .await?
+        .chunks_exact(4)
+        .map(|v| f32::from_le_bytes(v.try_into().unwrap()))
+        .collect();
+    let raw: Vec<Vec<Score>> = values
+        .chunks(384)
+        .map(|row| row.iter().map(|v| Score(*v)).collect())
+        .collect();
+    assert_eq!(raw, embeddings);
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .header("Accept", "text/html")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 406);
+
+    // Chunk embeddings are rejected before the inference
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .header("Accept", "application/octet-stream")
+        .json(&json!({"inputs": "test", "chunking": {"max_tokens": 8}}))
+        .send()
+        .await?;
+    assert_eq!(res.status(), 406);
+    assert!(!res.headers().contains_key("x-compute-tokens"));
+
+    Ok(())
+}
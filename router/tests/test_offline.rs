@@ -0,0 +1,178 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use text_embeddings_backend::DType;
+use text_embeddings_router::{run_offline, ModelSettings, OfflineTask};
+
+fn settings() -> ModelSettings {
+    ModelSettings {
+        revision: None,
+        tokenization_workers: Some(1),
+        dtype: Some(DType::Float32),
+        pooling: None,
+        max_concurrent_requests: 2,
+        max_batch_tokens: 1024,
+        max_batch_requests: None,
+        max_queue_time: None,
+        priority_starvation_timeout: 5000,
+        max_client_batch_size: 32,
+        auto_truncate: true,
+        embedding_cache_size: None,
+        bisect_failed_batches: false,
+        default_prompt: None,
+        default_prompt_name: None,
+        dense_path: None,
+        multi_vector: false,
+        hf_token: None,
+        uds_path: "/tmp/text-embeddings-inference-offline".to_string(),
+        huggingface_hub_cache: None,
+        otlp_endpoint: None,
+        otlp_service_name: "text-embeddings-inference.offline".to_string(),
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tei-offline-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn run(task: OfflineTask, model_id: &str, dir: &Path, records: &str) -> Result<Vec<Value>> {
+    let input = dir.join("input.jsonl");
+    let output = dir.join("output.jsonl");
+    std::fs::write(&input, records)?;
+    run_offline(
+        task,
+        input,
+        output.clone(),
+        false,
+        vec![model_id.to_string()],
+        settings(),
+    )
+    .await?;
+    std::fs::read_to_string(output)?
+        .lines()
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_offline_embed() -> Result<()> {
+    let dir = temp_dir("embed");
+    let model_id = "sentence-transformers/all-MiniLM-L6-v2";
+
+    // Blank lines are skipped and results are written in input order
+    let results = run(
+        OfflineTask::Embed,
+        model_id,
+        &dir,
+        "{\"id\": \"a\", \"inputs\": \"test\"}\n\n{\"id\": 2, \"inputs\": \"other\"}\n",
+    )
+    .await?;
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["id"], "a");
+    assert_eq!(results[1]["id"], 2);
+    assert_eq!(results[0]["embedding"].as_array().unwrap().len(), 384);
+
+    // The first malformed record stops the run
+    let err = run(
+        OfflineTask::Embed,
+        model_id,
+        &dir,
+        "{\"inputs\": \"test\"}\n{\"inputs\": \n",
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("Record 2 is invalid"));
+
+    let err = run(
+        OfflineTask::Embed,
+        model_id,
+        &dir,
+        "{\"inputs\": [\"a\", \"b\"]}\n",
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("`inputs` must be a string"));
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_offline_predict() -> Result<()> {
+    let dir = temp_dir("predict");
+    let model_id = "SamLowe/roberta-base-go_emotions";
+
+    let results = run(
+        OfflineTask::Predict,
+        model_id,
+        &dir,
+        "{\"id\": 1, \"inputs\": \"I am happy\"}\n{\"id\": 2, \"inputs\": [\"a\", \"b\"]}\n",
+    )
+    .await?;
+    assert_eq!(results.len(), 2);
+    // One score per label of the model
+    assert_eq!(results[0]["scores"].as_array().unwrap().len(), 28);
+
+    let err = run(OfflineTask::Predict, model_id, &dir, "{\"id\": 1}\n")
+        .await
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("`inputs` is missing"));
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_offline_rerank() -> Result<()> {
+    let dir = temp_dir("rerank");
+    let model_id = "BAAI/bge-reranker-base";
+
+    // Records are processed concurrently, the texts of a record are batched together
+    let results = run(
+        OfflineTask::Rerank,
+        model_id,
+        &dir,
+        "{\"id\": 1, \"query\": \"test\", \"texts\": [\"test\", \"other\"]}\n\
+         {\"id\": 2, \"query\": \"test\", \"texts\": [\"other\", \"test\"]}\n\
+         {\"id\": 3, \"query\": \"test\", \"texts\": [\"test\"]}\n",
+    )
+    .await?;
+    assert_eq!(results.len(), 3);
+    let scores: Vec<f64> = results[0]["scores"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|score| score.as_f64().unwrap())
+        .collect();
+    assert!(scores[0] > scores[1]);
+
+    // The records of an offline run are batches of at most `--max-concurrent-requests` texts
+    let err = run(
+        OfflineTask::Rerank,
+        model_id,
+        &dir,
+        "{\"query\": \"test\", \"texts\": [\"a\", \"b\", \"c\"]}\n",
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("batch size 3 > maximum allowed batch size 2"));
+
+    let err = run(
+        OfflineTask::Rerank,
+        model_id,
+        &dir,
+        "{\"query\": \"test\"}\n",
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("`texts` cannot be empty"));
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}